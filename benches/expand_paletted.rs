@@ -9,7 +9,7 @@
 
 use criterion::{criterion_group, criterion_main, Criterion, Throughput};
 use png::benchable_apis::{create_info_from_plte_trns_bitdepth, create_transform_fn, TransformFn};
-use png::{Info, Transformations};
+use png::{Info, ScaledFloat, Transformations};
 use rand::Rng;
 use std::fmt::{self, Display};
 
@@ -109,7 +109,7 @@ impl Input {
 
 #[inline(always)]
 fn create_expand_palette_fn(info: &Info) -> TransformFn {
-    create_transform_fn(info, Transformations::EXPAND).unwrap()
+    create_transform_fn(info, Transformations::EXPAND, ScaledFloat::new(1.0 / 2.2)).unwrap()
 }
 
 fn bench_create_fn(c: &mut Criterion, plte_size: usize, trns_size: usize) {
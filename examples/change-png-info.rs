@@ -23,7 +23,7 @@ fn main() -> BoxResult<()> {
     // # Encode
     let path_out = Path::new(r"./target/test_modified.png");
     let file = File::create(path_out)?;
-    let ref mut w = BufWriter::new(file);
+    let w = &mut BufWriter::new(file);
 
     // Get defaults for interlaced parameter.
     let mut info_out = png_info.clone();
@@ -45,7 +45,7 @@ fn main() -> BoxResult<()> {
     let mut counter = 0u8;
     while let Ok(info) = reader.next_frame(&mut buf) {
         let bytes = &buf[..info.buffer_size()];
-        writer.write_image_data(&bytes)?;
+        writer.write_image_data(bytes)?;
         counter += 1;
         println!("Written frame: {}", counter);
     }
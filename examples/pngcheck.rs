@@ -284,7 +284,7 @@ fn main() {
                     })
                 })
         } else {
-            check_image(&config, &file)
+            check_image(&config, file)
         };
 
         result.unwrap_or_else(|err| {
@@ -49,7 +49,7 @@ impl Adam7Info {
     /// nevertheless exposed as a public API, because it helps to provide self-contained example
     /// usage of [`expand_interlaced_row`](crate::expand_interlaced_row).
     pub fn new(pass: u8, line: u32, width: u32) -> Self {
-        assert!(1 <= pass && pass <= 7);
+        assert!((1..=7).contains(&pass));
         assert!(width > 0);
 
         let info = PassConstants::PASSES[pass as usize - 1];
@@ -67,6 +67,15 @@ impl Adam7Info {
         PassConstants::PASSES[self.pass as usize - 1]
     }
 
+    /// The column and row in the full image of the pixel at `idx` in this pass line.
+    pub(crate) fn image_position(self, idx: u32) -> (u32, u32) {
+        let pass = self.pass_constants();
+        (
+            idx * u32::from(pass.x_sampling) + u32::from(pass.x_offset),
+            self.line * u32::from(pass.y_sampling) + u32::from(pass.y_offset),
+        )
+    }
+
     /// How often to repeat a pixel.
     fn splat_pixel_repeat(self, idx: usize) -> u8 {
         let pass = self.pass_constants();
@@ -411,6 +420,52 @@ pub fn expand_pass(
     }
 }
 
+/// Copies the pixels of a single Adam7 pass line out of `img` into `interlaced_row`.
+///
+/// This is the inverse of [`expand_pass`] and is used when encoding interlaced images. The
+/// `interlaced_row` must hold at least the packed bytes of `interlace_info.samples` pixels, any
+/// trailing bits of the last byte are set to zero.
+pub(crate) fn extract_pass(
+    img: &[u8],
+    img_row_stride: usize,
+    interlaced_row: &mut [u8],
+    interlace_info: &Adam7Info,
+    bits_per_pixel: u8,
+) {
+    match bits_per_pixel {
+        1 | 2 | 4 => {
+            let mask = (1u8 << bits_per_pixel) - 1;
+            interlaced_row.fill(0);
+
+            let bit_indices = expand_adam7_bits(img_row_stride, interlace_info, bits_per_pixel);
+            for (idx, pos) in bit_indices.enumerate() {
+                let px = (img[pos.byte] >> (8 - bits_per_pixel - pos.bit)) & mask;
+                // Bounded by the row length, which is a `usize` already.
+                let out_bit = idx * usize::from(bits_per_pixel);
+                let shift = 8 - bits_per_pixel - (out_bit % 8) as u8;
+                interlaced_row[out_bit / 8] |= px << shift;
+            }
+        }
+        8 => {
+            let byte_indices = expand_adam7_bytes(img_row_stride, interlace_info, 1);
+
+            for (bytepos, px) in byte_indices.zip(interlaced_row) {
+                *px = img[bytepos];
+            }
+        }
+        _ => {
+            debug_assert!(bits_per_pixel % 8 == 0);
+            let bytes_pp = bits_per_pixel / 8;
+            let byte_indices = expand_adam7_bytes(img_row_stride, interlace_info, bytes_pp);
+
+            for (bytepos, px) in byte_indices.zip(interlaced_row.chunks_exact_mut(bytes_pp.into()))
+            {
+                px.copy_from_slice(&img[bytepos..][..px.len()]);
+            }
+        }
+    }
+}
+
 /// Expand pass, but also ensure that after each pass the whole image has been initialized up to
 /// the data available. In constrast to `expand_pass` there are no holes left in the image.
 ///
@@ -937,6 +992,44 @@ mod tests {
         }
     }
 
+    /// Check that extracting all passes of an image and expanding them again recovers the image.
+    #[test]
+    fn extract_pass_roundtrip() {
+        // Choose ragged sizes so that partial bytes and empty passes are covered.
+        const WIDTH: u32 = 13;
+        const HEIGHT: u32 = 7;
+
+        for &bpp in &[1u8, 2, 4, 8, 16, 24, 32, 48, 64] {
+            let bytes_of = |pix: u32| (u32::from(bpp) * pix).next_multiple_of(8) as usize / 8;
+            let rowbytes = bytes_of(WIDTH);
+
+            // Padding bits at the end of each row are not part of any pixel.
+            let padding_mask = match (u32::from(bpp) * WIDTH) % 8 {
+                0 => 0xff,
+                bits => 0xffu8 << (8 - bits),
+            };
+            let original: Vec<u8> = (0..rowbytes * HEIGHT as usize)
+                .map(|i| {
+                    let byte = (i * 7 + 3) as u8;
+                    if i % rowbytes == rowbytes - 1 {
+                        byte & padding_mask
+                    } else {
+                        byte
+                    }
+                })
+                .collect();
+
+            let mut expanded = vec![0; original.len()];
+            for adam7_info in Adam7Iterator::new(WIDTH, HEIGHT) {
+                let mut interlace_line = vec![0xaa; bytes_of(adam7_info.samples)];
+                extract_pass(&original, rowbytes, &mut interlace_line, &adam7_info, bpp);
+                expand_pass(&mut expanded, rowbytes, &interlace_line, &adam7_info, bpp);
+            }
+
+            assert_eq!(original, expanded, "bpp={bpp}");
+        }
+    }
+
     #[test]
     fn test_expand_pass_splat_1bpp() {
         let width = 8;
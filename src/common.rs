@@ -53,7 +53,7 @@ impl ColorType {
     pub(crate) fn checked_raw_row_length(self, depth: BitDepth, width: u32) -> Option<usize> {
         // No overflow can occur in 64 bits, we multiply 32-bit with 5 more bits.
         let bits = u64::from(width) * u64::from(self.samples_u8()) * u64::from(depth.into_u8());
-        TryFrom::try_from(1 + (bits + 7) / 8).ok()
+        TryFrom::try_from(1 + bits.div_ceil(8)).ok()
     }
 
     pub(crate) fn raw_row_length_from_width(self, depth: BitDepth, width: u32) -> usize {
@@ -398,6 +398,18 @@ pub enum DeflateCompression {
     /// The implementation details and the exact meaning of each level may change in the future,
     /// including in semver-compatible releases.
     Level(u8),
+
+    /// Compression level between 1 and 9 like [`DeflateCompression::Level`], but the image data
+    /// is split into bands of rows that are filtered and compressed on multiple threads.
+    ///
+    /// The compressed bands are joined into a single zlib stream, the same way `pigz` does it.
+    /// Each band is compressed without the history of the previous one, so the output is
+    /// slightly larger than with `Level`. Small images are compressed on a single thread.
+    ///
+    /// Only [`Writer::write_image_data`](crate::Writer::write_image_data) uses multiple threads;
+    /// [`StreamWriter`](crate::StreamWriter) treats this like `Level`.
+    #[cfg(feature = "parallel")]
+    Parallel(u8),
     // Other variants can be added in the future
 }
 
@@ -595,6 +607,18 @@ pub struct CodingIndependentCodePoints {
     pub is_video_full_range_image: bool,
 }
 
+impl CodingIndependentCodePoints {
+    pub(crate) fn encode<W: Write>(self, w: &mut W) -> encoder::Result<()> {
+        let data = [
+            self.color_primaries,
+            self.transfer_function,
+            self.matrix_coefficients,
+            self.is_video_full_range_image as u8,
+        ];
+        encoder::write_chunk(w, chunk::cICP, &data)
+    }
+}
+
 /// Mastering Display Color Volume (mDCV) used at the point of content creation,
 /// as specified in [SMPTE-ST-2086](https://ieeexplore.ieee.org/stamp/stamp.jsp?arnumber=8353899).
 ///
@@ -617,6 +641,33 @@ pub struct MasteringDisplayColorVolume {
     pub min_luminance: u32,
 }
 
+impl MasteringDisplayColorVolume {
+    /// Serializes the chunk, or returns `None` if a chromaticity does not fit into the chunk.
+    ///
+    /// `mDCV` stores chromaticities in units of 0.00002 (rather than the 0.00001 used by
+    /// `ScaledFloat`) in 16 bits, so values above 1.3107 can't be represented.
+    pub(crate) fn to_be_bytes(self) -> Option<[u8; 24]> {
+        let scale = |value: ScaledFloat| u16::try_from(value.into_scaled() / 2).ok();
+        let SourceChromaticities {
+            white,
+            red,
+            green,
+            blue,
+        } = self.chromaticities;
+        let mut data = [0; 24];
+        for (i, value) in [red, green, blue, white]
+            .into_iter()
+            .flat_map(|(x, y)| [x, y])
+            .enumerate()
+        {
+            data[2 * i..][..2].copy_from_slice(&scale(value)?.to_be_bytes());
+        }
+        data[16..20].copy_from_slice(&self.max_luminance.to_be_bytes());
+        data[20..24].copy_from_slice(&self.min_luminance.to_be_bytes());
+        Some(data)
+    }
+}
+
 /// Content light level information of HDR content.
 ///
 /// See https://www.w3.org/TR/png-3/#cLLI-chunk for more details.
@@ -645,6 +696,178 @@ pub struct ContentLightLevelInfo {
     pub max_frame_average_light_level: u32,
 }
 
+impl ContentLightLevelInfo {
+    pub(crate) fn encode<W: Write>(self, w: &mut W) -> encoder::Result<()> {
+        let mut data = [0; 8];
+        data[..4].copy_from_slice(&self.max_content_light_level.to_be_bytes());
+        data[4..].copy_from_slice(&self.max_frame_average_light_level.to_be_bytes());
+        encoder::write_chunk(w, chunk::cLLI, &data)
+    }
+}
+
+/// The time of the last image modification, as stored in the `tIME` chunk.
+///
+/// The time is in Universal Time (UTC). The fields are private so that only valid dates can be
+/// represented, use [`LastModificationTime::new`] or [`LastModificationTime::from_system_time`]
+/// to construct one.
+///
+/// See https://www.w3.org/TR/png-3/#11tIME for more details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LastModificationTime {
+    // Field order matters for the derived `Ord`.
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+impl LastModificationTime {
+    /// Creates a modification time, returning `None` if any of the fields is out of range.
+    ///
+    /// `month` and `day` are 1-based. `second` may be 60 to allow for leap seconds.
+    pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Option<Self> {
+        let valid = (1..=12).contains(&month)
+            && day >= 1
+            && day <= days_in_month(year, month)
+            && hour <= 23
+            && minute <= 59
+            && second <= 60;
+        valid.then_some(LastModificationTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// Converts a `SystemTime` into a modification time.
+    ///
+    /// Returns `None` for times before the Unix epoch or after the year 65535. Sub-second
+    /// precision is truncated.
+    pub fn from_system_time(time: std::time::SystemTime) -> Option<Self> {
+        let secs = time.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs();
+        let days = secs / 86400;
+        let secs_of_day = secs % 86400;
+
+        // Civil-from-days, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        // Shift the epoch to 0000-03-01 so that leap days end up at the end of each era year.
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let doe = z % 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + u64::from(month <= 2);
+
+        Self::new(
+            u16::try_from(year).ok()?,
+            month as u8,
+            day as u8,
+            (secs_of_day / 3600) as u8,
+            (secs_of_day / 60 % 60) as u8,
+            (secs_of_day % 60) as u8,
+        )
+    }
+
+    /// The complete year, e.g. `1995`.
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    /// The month, from 1 to 12.
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    /// The day of the month, from 1 to 31.
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    /// The hour, from 0 to 23.
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    /// The minute, from 0 to 59.
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    /// The second, from 0 to 60 (for leap seconds).
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+
+    pub(crate) fn from_be_bytes(data: [u8; 7]) -> Option<Self> {
+        let year = u16::from_be_bytes([data[0], data[1]]);
+        Self::new(year, data[2], data[3], data[4], data[5], data[6])
+    }
+
+    pub(crate) fn to_be_bytes(self) -> [u8; 7] {
+        let [year_hi, year_lo] = self.year.to_be_bytes();
+        [
+            year_hi,
+            year_lo,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+        ]
+    }
+
+    pub fn encode<W: Write>(self, w: &mut W) -> encoder::Result<()> {
+        encoder::write_chunk(w, chunk::tIME, &self.to_be_bytes())
+    }
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+/// Where an unknown chunk appeared in the chunk sequence.
+///
+/// The PNG specification allows ancillary chunks to be constrained relative to the `PLTE` and
+/// `IDAT` chunks. Remembering the position lets an encoder write the chunk back to an equivalent
+/// place.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChunkPosition {
+    /// The chunk appeared before `PLTE` and the first `IDAT`, or there is no `PLTE` chunk.
+    BeforePlte,
+    /// The chunk appeared after `PLTE` and before the first `IDAT`.
+    BeforeIdat,
+    /// The chunk appeared after the first `IDAT`.
+    AfterIdat,
+}
+
+/// An ancillary chunk that is not interpreted by this crate.
+///
+/// These are only collected when enabled with [`crate::DecodeOptions::set_keep_unknown_chunks`].
+/// Note that the decoder keeps all such chunks, regardless of whether they are safe to copy (see
+/// [`crate::chunk::safe_to_copy`]). Applications that modify the critical chunks of an image
+/// should drop those that are not.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownChunk<'a> {
+    /// The type of the chunk.
+    pub type_: chunk::ChunkType,
+    /// The chunk payload, without the length, type and CRC fields.
+    pub data: Cow<'a, [u8]>,
+    /// Where the chunk appeared relative to `PLTE` and `IDAT`.
+    pub position: ChunkPosition,
+}
+
 /// PNG info struct
 #[derive(Clone, Debug)]
 #[non_exhaustive]
@@ -693,12 +916,19 @@ pub struct Info<'a> {
     pub content_light_level: Option<ContentLightLevelInfo>,
     /// The EXIF metadata for the image.
     pub exif_metadata: Option<Cow<'a, [u8]>>,
+    /// The contents of the image's `tIME` chunk, if present.
+    pub last_modification_time: Option<LastModificationTime>,
     /// tEXt field
     pub uncompressed_latin1_text: Vec<TEXtChunk>,
     /// zTXt field
     pub compressed_latin1_text: Vec<ZTXtChunk>,
     /// iTXt field
     pub utf8_text: Vec<ITXtChunk>,
+    /// Ancillary chunks that were not recognized, in the order they were read.
+    ///
+    /// Only populated when [`crate::DecodeOptions::set_keep_unknown_chunks`] is enabled. The
+    /// encoder writes these chunks back at their recorded position.
+    pub unknown_chunks: Vec<UnknownChunk<'a>>,
 }
 
 impl Default for Info<'_> {
@@ -726,9 +956,11 @@ impl Default for Info<'_> {
             mastering_display_color_volume: None,
             content_light_level: None,
             exif_metadata: None,
+            last_modification_time: None,
             uncompressed_latin1_text: Vec::new(),
             compressed_latin1_text: Vec::new(),
             utf8_text: Vec::new(),
+            unknown_chunks: Vec::new(),
         }
     }
 }
@@ -872,14 +1104,8 @@ bitflags::bitflags! {
     const INVERT_MONO         = 0x0020; // read and write
     /// Normalize pixels to the sBIT depth
     const SHIFT               = 0x0040; // read and write
-    /// Flip RGB to BGR; RGBA to BGRA
-    const BGR                 = 0x0080; // read and write
-    /// Flip RGBA to ARGB or GA to AG
-    const SWAP_ALPHA          = 0x0100; // read and write
     /// Byte-swap 16-bit samples
     const SWAP_ENDIAN         = 0x0200; // read and write
-    /// Change alpha from opacity to transparency
-    const INVERT_ALPHA        = 0x0400; // read and write
     const STRIP_FILLER        = 0x0800; // write only
     const STRIP_FILLER_BEFORE = 0x0800; // write only
     const STRIP_FILLER_AFTER  = 0x1000; // write only
@@ -903,10 +1129,61 @@ bitflags::bitflags! {
         const EXPAND              = 0x00010; // read only */
         /// Expand paletted images to include an alpha channel. Implies `EXPAND`.
         const ALPHA               = 0x10000; // read only */
+        /// Correct color samples from the gamma of the image to the display gamma, see
+        /// [`Decoder::set_display_gamma`](crate::Decoder::set_display_gamma).
+        const GAMMA               = 0x20000; // read only */
+        /// Convert colors to sRGB, from the color space given by the `cICP`, `sRGB`, `cHRM` and
+        /// `gAMA` chunks. Takes precedence over `GAMMA`. Indexed output is not converted, see
+        /// [`Reader::next_frame_linear_srgb`](crate::Reader::next_frame_linear_srgb) for details.
+        const SRGB                = 0x40000; // read only */
+        /// Flip RGB to BGR and RGBA to BGRA.
+        const BGR                 = 0x00080; // read only */
+        /// Move the alpha sample in front of the color samples: RGBA to ARGB and GA to AG.
+        const SWAP_ALPHA          = 0x00100; // read only */
+        /// Change alpha from opacity to transparency.
+        const INVERT_ALPHA        = 0x00400; // read only */
+        /// Add an opaque filler sample in place of alpha to output without alpha: RGB to RGBX and
+        /// gray to GX. The output is reported as `Rgba` or `GrayscaleAlpha`, and `SWAP_ALPHA` and
+        /// `INVERT_ALPHA` apply to the filler.
+        const FILLER              = 0x80000; // read only */
+        /// Multiply the color samples of output with alpha by their alpha, with correct
+        /// rounding. Colors are premultiplied after `GAMMA` and `SRGB` correct them.
+        const PREMULTIPLY         = 0x100000; // read only */
+        /// Output 16 bit samples in the native byte order instead of big endian, so that pairs of
+        /// bytes can be read with `u16::from_ne_bytes`. See also
+        /// [`Reader::next_frame_u16`](crate::Reader::next_frame_u16).
+        const NATIVE_ENDIAN       = 0x200000; // read only */
+        /// Output each sample as a native endian `f32`, normalized to `0.0..=1.0`. Implies
+        /// `EXPAND`. The reported bit depth is the precision of the samples, each of which takes
+        /// four bytes. Alpha is premultiplied in floating point. See also
+        /// [`Reader::next_frame_f32`](crate::Reader::next_frame_f32).
+        const F32                 = 0x400000; // read only */
+        /// Convert colors to linear sRGB like
+        /// [`Reader::next_frame_linear_srgb`](crate::Reader::next_frame_linear_srgb). Implies
+        /// `F32` and takes precedence over `SRGB` and `GAMMA`, alpha is premultiplied in linear
+        /// light.
+        const LINEAR              = 0x800000; // read only */
     }
 }
 
 impl Transformations {
+    /// The transformations that change the order and number of channels.
+    pub(crate) const CHANNEL_LAYOUT: Transformations = Transformations::BGR
+        .union(Transformations::SWAP_ALPHA)
+        .union(Transformations::INVERT_ALPHA)
+        .union(Transformations::FILLER);
+
+    /// The transformations that expand packed samples to 8 bits and palette indices to colors.
+    pub(crate) const EXPANSION: Transformations = Transformations::EXPAND
+        .union(Transformations::ALPHA)
+        .union(Transformations::F32)
+        .union(Transformations::LINEAR);
+
+    /// The transformations that change the representation of samples in memory.
+    pub(crate) const SAMPLE_FORMAT: Transformations = Transformations::NATIVE_ENDIAN
+        .union(Transformations::F32)
+        .union(Transformations::LINEAR);
+
     /// Transform every input to 8bit grayscale or color.
     ///
     /// This sets `EXPAND` and `STRIP_16` which is similar to the default transformation used by
@@ -923,6 +1200,45 @@ impl Default for Transformations {
     }
 }
 
+/// Factor by which frames are scaled down while decoding, see
+/// [`Decoder::set_downscale`](crate::Decoder::set_downscale).
+///
+/// The scaled frame is `ceil(width / factor)` by `ceil(height / factor)` pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Downscale {
+    /// Decode at full resolution.
+    None,
+    /// Decode at half the width and height.
+    Half,
+    /// Decode at a quarter of the width and height.
+    Quarter,
+    /// Decode at an eighth of the width and height.
+    Eighth,
+}
+
+impl Downscale {
+    /// The number of pixels in each direction that are combined into one.
+    pub fn factor(self) -> u32 {
+        match self {
+            Downscale::None => 1,
+            Downscale::Half => 2,
+            Downscale::Quarter => 4,
+            Downscale::Eighth => 8,
+        }
+    }
+
+    /// Returns the scaled down length of `len` pixels.
+    pub fn apply(self, len: u32) -> u32 {
+        len.div_ceil(self.factor())
+    }
+}
+
+impl Default for Downscale {
+    fn default() -> Downscale {
+        Downscale::None
+    }
+}
+
 #[derive(Debug)]
 pub struct ParameterError {
     inner: ParameterErrorKind,
@@ -936,6 +1252,15 @@ pub(crate) enum ParameterErrorKind {
     /// because they must react to a value produced by this library, which can have been subjected
     /// to limits.
     ImageBufferSize { expected: usize, actual: usize },
+    /// A requested region of a frame does not lie within the frame.
+    RegionOutOfBounds {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    /// A requested animation frame is not in the frame index.
+    FrameOutOfBounds { frame: usize, num_frames: usize },
     /// A bit like return `None` from an iterator.
     /// We use it to differentiate between failing to seek to the next image in a sequence and the
     /// absence of a next image. This is an error of the caller because they should have checked
@@ -962,6 +1287,21 @@ impl fmt::Display for ParameterError {
             ImageBufferSize { expected, actual } => {
                 write!(fmt, "wrong data size, expected {} got {}", expected, actual)
             }
+            RegionOutOfBounds {
+                x,
+                y,
+                width,
+                height,
+            } => write!(
+                fmt,
+                "region of {}x{} pixels at ({}, {}) is outside of the frame",
+                width, height, x, y
+            ),
+            FrameOutOfBounds { frame, num_frames } => write!(
+                fmt,
+                "frame {} is out of bounds of an animation with {} frames",
+                frame, num_frames
+            ),
             PolledAfterEndOfImage => write!(fmt, "End of image has been reached"),
             PolledAfterFatalError => {
                 write!(fmt, "A fatal decoding error has been encounted earlier")
@@ -969,3 +1309,49 @@ impl fmt::Display for ParameterError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::LastModificationTime;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn last_modification_time_validation() {
+        assert!(LastModificationTime::new(2000, 1, 1, 12, 34, 56).is_some());
+        assert!(LastModificationTime::new(2016, 12, 31, 23, 59, 60).is_some());
+        assert!(LastModificationTime::new(2000, 2, 29, 0, 0, 0).is_some());
+        assert!(LastModificationTime::new(1900, 2, 29, 0, 0, 0).is_none());
+        assert!(LastModificationTime::new(2001, 2, 29, 0, 0, 0).is_none());
+        assert!(LastModificationTime::new(2000, 4, 31, 0, 0, 0).is_none());
+        assert!(LastModificationTime::new(2000, 0, 1, 0, 0, 0).is_none());
+        assert!(LastModificationTime::new(2000, 13, 1, 0, 0, 0).is_none());
+        assert!(LastModificationTime::new(2000, 1, 0, 0, 0, 0).is_none());
+        assert!(LastModificationTime::new(2000, 1, 1, 24, 0, 0).is_none());
+        assert!(LastModificationTime::new(2000, 1, 1, 0, 60, 0).is_none());
+        assert!(LastModificationTime::new(2000, 1, 1, 0, 0, 61).is_none());
+    }
+
+    #[test]
+    fn last_modification_time_from_system_time() {
+        let from_secs =
+            |secs| LastModificationTime::from_system_time(UNIX_EPOCH + Duration::from_secs(secs));
+
+        assert_eq!(from_secs(0), LastModificationTime::new(1970, 1, 1, 0, 0, 0));
+        assert_eq!(
+            from_secs(946_684_799),
+            LastModificationTime::new(1999, 12, 31, 23, 59, 59)
+        );
+        assert_eq!(
+            from_secs(951_782_400),
+            LastModificationTime::new(2000, 2, 29, 0, 0, 0)
+        );
+        assert_eq!(
+            from_secs(1_700_000_000),
+            LastModificationTime::new(2023, 11, 14, 22, 13, 20)
+        );
+        assert_eq!(
+            LastModificationTime::from_system_time(UNIX_EPOCH - Duration::from_secs(1)),
+            None
+        );
+    }
+}
@@ -0,0 +1,409 @@
+//! Decoding from a [`futures_io::AsyncBufRead`] without blocking on I/O.
+//!
+//! [`AsyncDecoder`] and [`AsyncReader`] wrap a [`Decoder`] and a [`Reader`] whose input is the
+//! `AsyncBufRead`, and drive the same state machine: every operation of the reader is polled
+//! until the input it needs is available.
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::AsyncBufRead;
+
+use super::read_decoder::{Input, ReadDecoder};
+use super::stream::{DecodeOptions, DecodeWarning, DecodingError};
+use super::{frame_count, Decoder, InterlaceInfo, InterlacedRow, Limits, OutputInfo, Reader, Row};
+use crate::common::{BitDepth, ColorType, FrameControl, Info, ScaledFloat, Transformations};
+
+/// The input of the [`Decoder`] and [`Reader`] behind the async front end.
+struct AsyncInput<R>(R);
+
+impl<R: AsyncBufRead + Unpin> Input for AsyncInput<R> {
+    fn poll_fill_buf(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Pin::new(&mut self.0).poll_fill_buf(cx)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        Pin::new(&mut self.0).consume(amt);
+    }
+}
+
+/// PNG decoder reading from an [`AsyncBufRead`].
+///
+/// This is the async counterpart of [`Decoder`](crate::Decoder) and is configured the same way.
+/// Only available with the `async` feature.
+///
+/// ```
+/// # futures_executor::block_on(async {
+/// use png::AsyncDecoder;
+///
+/// let data = std::fs::read("tests/pngsuite/basi0g01.png").unwrap();
+/// let decoder = AsyncDecoder::new(&data[..]);
+/// let mut reader = decoder.read_info().await.unwrap();
+/// let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+/// let info = reader.next_frame(&mut buf).await.unwrap();
+/// assert_eq!((info.width, info.height), (32, 32));
+/// # });
+/// ```
+pub struct AsyncDecoder<R: AsyncBufRead + Unpin> {
+    decoder: Decoder<AsyncInput<R>>,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncDecoder<R> {
+    /// Create a new decoder configuration with default limits.
+    pub fn new(r: R) -> Self {
+        Self::new_with_limits(r, Limits::default())
+    }
+
+    /// Create a new decoder configuration with custom limits.
+    pub fn new_with_limits(r: R, limits: Limits) -> Self {
+        let mut read_decoder = ReadDecoder::new(AsyncInput(r));
+        read_decoder.set_limits(limits);
+        AsyncDecoder {
+            decoder: Decoder::with_read_decoder(read_decoder),
+        }
+    }
+
+    /// Create a new decoder configuration with custom [`DecodeOptions`].
+    pub fn new_with_options(r: R, decode_options: DecodeOptions) -> Self {
+        let read_decoder = ReadDecoder::with_options(AsyncInput(r), decode_options);
+        AsyncDecoder {
+            decoder: Decoder::with_read_decoder(read_decoder),
+        }
+    }
+
+    /// Limit resource usage, see [`Decoder::set_limits`](crate::Decoder::set_limits).
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.decoder.set_limits(limits);
+    }
+
+    /// Read the PNG header and return the information contained within.
+    ///
+    /// Most image metadata will not be read until [`read_info`] is called, so those fields will be
+    /// None or empty.
+    ///
+    /// [`read_info`]: Self::read_info
+    pub async fn read_header_info(&mut self) -> Result<&Info<'static>, DecodingError> {
+        let read_decoder = &mut self.decoder.read_decoder;
+        poll_fn(|cx| read_decoder.poll_read_header_info(cx).map_ok(|_| ())).await?;
+        Ok(read_decoder.info().unwrap())
+    }
+
+    /// Reads all meta data until the first IDAT chunk
+    pub async fn read_info(mut self) -> Result<AsyncReader<R>, DecodingError> {
+        self.read_header_info().await?;
+        let mut reader = self.decoder.into_reader()?;
+        poll_fn(|cx| reader.poll_read_until_image_data(cx)).await?;
+
+        reader.remaining_frames = frame_count(reader.info());
+        Ok(AsyncReader { reader })
+    }
+
+    /// Set the allowed and performed transformations.
+    ///
+    /// See [`Decoder::set_transformations`](crate::Decoder::set_transformations).
+    pub fn set_transformations(&mut self, transform: Transformations) {
+        self.decoder.set_transformations(transform);
+    }
+
+    /// Set the gamma that [`Transformations::GAMMA`] corrects color samples to.
+    ///
+    /// See [`Decoder::set_display_gamma`](crate::Decoder::set_display_gamma).
+    pub fn set_display_gamma(&mut self, display_gamma: ScaledFloat) {
+        self.decoder.set_display_gamma(display_gamma);
+    }
+
+    /// Set the decoder to ignore all text chunks while parsing.
+    pub fn set_ignore_text_chunk(&mut self, ignore_text_chunk: bool) {
+        self.decoder.set_ignore_text_chunk(ignore_text_chunk);
+    }
+
+    /// Set the decoder to ignore iccp chunks while parsing.
+    pub fn set_ignore_iccp_chunk(&mut self, ignore_iccp_chunk: bool) {
+        self.decoder.set_ignore_iccp_chunk(ignore_iccp_chunk);
+    }
+
+    /// Set the decoder to collect unknown ancillary chunks into [`Info::unknown_chunks`].
+    pub fn set_keep_unknown_chunks(&mut self, keep_unknown_chunks: bool) {
+        self.decoder.set_keep_unknown_chunks(keep_unknown_chunks);
+    }
+
+    /// Set the decoder to ignore and not verify the Adler-32 checksum
+    /// and CRC code.
+    pub fn ignore_checksums(&mut self, ignore_checksums: bool) {
+        self.decoder.ignore_checksums(ignore_checksums);
+    }
+}
+
+/// PNG reader over an [`AsyncBufRead`], created by [`AsyncDecoder::read_info`].
+///
+/// This is the async counterpart of [`Reader`](crate::Reader). Only available with the `async`
+/// feature.
+pub struct AsyncReader<R: AsyncBufRead + Unpin> {
+    reader: Reader<AsyncInput<R>>,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncReader<R> {
+    /// Advances to the start of the next animation frame and returns the [`FrameControl`] that
+    /// describes it, see [`Reader::next_frame_info`](crate::Reader::next_frame_info).
+    pub async fn next_frame_info(&mut self) -> Result<&FrameControl, DecodingError> {
+        poll_fn(|cx| self.reader.poll_next_frame_info(cx)).await?;
+        // `StreamingDecoder` guarantees an `fcTL` chunk before a sequence of `fdAT` chunks.
+        Ok(self.info().frame_control.as_ref().unwrap())
+    }
+
+    /// Get information on the image.
+    ///
+    /// The structure will change as new frames of an animated image are decoded.
+    pub fn info(&self) -> &Info<'static> {
+        self.reader.info()
+    }
+
+    /// The deviations from the specification that were tolerated so far, see
+    /// [`Reader::warnings`](crate::Reader::warnings).
+    pub fn warnings(&self) -> &[DecodeWarning] {
+        self.reader.warnings()
+    }
+
+    /// Decodes the next frame into `buf`, see [`Reader::next_frame`](crate::Reader::next_frame).
+    pub async fn next_frame(&mut self, buf: &mut [u8]) -> Result<OutputInfo, DecodingError> {
+        poll_fn(|cx| self.reader.poll_start_frame(cx, buf.len())).await?;
+        poll_fn(|cx| self.reader.poll_next_frame_rows(cx, buf)).await
+    }
+
+    /// Returns the next processed row of the image (discarding [`InterlaceInfo`]).
+    pub async fn next_row(&mut self) -> Result<Option<Row<'_>>, DecodingError> {
+        self.next_interlaced_row()
+            .await
+            .map(|v| v.map(|v| Row { data: v.data }))
+    }
+
+    /// Returns the next processed row of the image.
+    pub async fn next_interlaced_row(
+        &mut self,
+    ) -> Result<Option<InterlacedRow<'_>>, DecodingError> {
+        let interlace = poll_fn(|cx| self.reader.poll_next_interlaced_row(cx)).await?;
+        Ok(interlace.map(|interlace| self.reader.scratch_row(interlace)))
+    }
+
+    /// Reads the next row of the image into the provided `output_buffer`, see
+    /// [`Reader::read_row`](crate::Reader::read_row).
+    pub async fn read_row(
+        &mut self,
+        output_buffer: &mut [u8],
+    ) -> Result<Option<InterlaceInfo>, DecodingError> {
+        poll_fn(|cx| self.reader.poll_read_row(cx, output_buffer)).await
+    }
+
+    /// Read the rest of the image and chunks and finish up, including text chunks or others.
+    pub async fn finish(&mut self) -> Result<(), DecodingError> {
+        poll_fn(|cx| self.reader.poll_finish(cx)).await
+    }
+
+    /// Returns the color type and the number of bits per sample
+    /// of the data returned by [`AsyncReader::next_row`] and [`AsyncReader::next_frame`].
+    pub fn output_color_type(&self) -> (ColorType, BitDepth) {
+        self.reader.output_color_type()
+    }
+
+    /// Return the number of bytes required to hold a deinterlaced image frame that is decoded
+    /// using the given input transformations.
+    pub fn output_buffer_size(&self) -> Option<usize> {
+        self.reader.output_buffer_size()
+    }
+
+    /// Returns the number of bytes required to hold a deinterlaced row.
+    pub fn output_line_size(&self, width: u32) -> Option<usize> {
+        self.reader.output_line_size(width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, ErrorKind};
+    use std::task::Context;
+
+    use futures_executor::block_on;
+
+    use super::*;
+    use crate::test_utils::*;
+    use crate::Decoder;
+
+    /// Hands out the input a few bytes at a time and is only ready on every other poll.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        chunk: usize,
+        ready: bool,
+    }
+
+    impl futures_io::AsyncRead for Trickle<'_> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            _: &mut [u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Poll::Ready(Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "the decoder only reads through `AsyncBufRead`",
+            )))
+        }
+    }
+
+    impl AsyncBufRead for Trickle<'_> {
+        fn poll_fill_buf(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<&[u8]>> {
+            let this = self.get_mut();
+            this.ready = !this.ready;
+            if !this.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let len = this.chunk.min(this.data.len());
+            Poll::Ready(Ok(&this.data[..len]))
+        }
+
+        fn consume(self: Pin<&mut Self>, amt: usize) {
+            let this = self.get_mut();
+            this.data = &this.data[amt..];
+        }
+    }
+
+    fn pngsuite() -> impl Iterator<Item = (String, Vec<u8>)> {
+        glob::glob("tests/pngsuite/*.png")
+            .unwrap()
+            .map(|r| r.unwrap())
+            .filter(|path| !path.file_name().unwrap().to_str().unwrap().starts_with('x'))
+            .map(|path| (path.display().to_string(), std::fs::read(path).unwrap()))
+    }
+
+    #[test]
+    fn next_frame_matches_reader() {
+        for (name, png) in pngsuite() {
+            let mut decoder = Decoder::new(Cursor::new(&png));
+            decoder.set_transformations(Transformations::EXPAND);
+            let mut reader = decoder.read_info().unwrap();
+            let mut expected = vec![0; reader.output_buffer_size().unwrap()];
+            let expected_info = reader.next_frame(&mut expected).unwrap();
+            reader.finish().unwrap();
+
+            let data = Trickle {
+                data: &png,
+                chunk: 7,
+                ready: false,
+            };
+            let mut decoder = AsyncDecoder::new(data);
+            decoder.set_transformations(Transformations::EXPAND);
+            let (info, buf) = block_on(async {
+                let mut reader = decoder.read_info().await.unwrap();
+                let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+                let info = reader.next_frame(&mut buf).await.unwrap();
+                reader.finish().await.unwrap();
+                (info, buf)
+            });
+            assert_eq!(info, expected_info, "{name}");
+            assert_eq!(buf, expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn next_row_matches_reader() {
+        for (name, png) in pngsuite() {
+            let mut reader = Decoder::new(Cursor::new(&png)).read_info().unwrap();
+            let mut expected = Vec::new();
+            while let Some(row) = reader.next_row().unwrap() {
+                expected.extend_from_slice(row.data());
+            }
+
+            let rows = block_on(async {
+                let mut reader = AsyncDecoder::new(&png[..]).read_info().await.unwrap();
+                let mut rows = Vec::new();
+                while let Some(row) = reader.next_row().await.unwrap() {
+                    rows.extend_from_slice(row.data());
+                }
+                rows
+            });
+            assert_eq!(rows, expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn animation_frames() {
+        let png = std::fs::read("tests/animated/basic_f20.png").unwrap();
+        let mut reader = Decoder::new(Cursor::new(&png)).read_info().unwrap();
+        let mut expected = Vec::new();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        for _ in 0..reader.info().animation_control.unwrap().num_frames {
+            let info = reader.next_frame(&mut buf).unwrap();
+            expected.push(buf[..info.buffer_size()].to_vec());
+        }
+
+        let frames = block_on(async {
+            let mut reader = AsyncDecoder::new(&png[..]).read_info().await.unwrap();
+            let mut frames = Vec::new();
+            let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+            for _ in 0..reader.info().animation_control.unwrap().num_frames {
+                let info = reader.next_frame(&mut buf).await.unwrap();
+                frames.push(buf[..info.buffer_size()].to_vec());
+            }
+            assert!(reader.next_frame(&mut buf).await.is_err());
+            frames
+        });
+        assert_eq!(frames, expected);
+    }
+
+    #[test]
+    fn strict_mode() {
+        let decode = |png: &[u8], strict| {
+            let data = Trickle {
+                data: png,
+                chunk: 7,
+                ready: false,
+            };
+            let mut options = DecodeOptions::default();
+            options.set_strict(strict);
+            block_on(async {
+                let mut reader = AsyncDecoder::new_with_options(data, options)
+                    .read_info()
+                    .await?;
+                let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+                reader.next_frame(&mut buf).await?;
+                reader.finish().await?;
+                Ok::<_, DecodingError>(reader.warnings().to_vec())
+            })
+        };
+        for (name, png) in pngsuite() {
+            decode(&png, true).unwrap_or_else(|err| panic!("{name}: {err}"));
+        }
+
+        let mut png = std::fs::read("tests/pngsuite/basi6a08.png").unwrap();
+        png.extend_from_slice(b"junk");
+        let err = decode(&png, true).unwrap_err();
+        assert!(format!("{err}").starts_with("Data after the IEND chunk."));
+
+        let mut png = Vec::new();
+        write_png_sig(&mut png);
+        write_rgba8_ihdr_with_width(&mut png, 4);
+        write_rgba8_idats(&mut png, 4, 0x1000);
+        write_chunk(&mut png, b"IDAT", b"junk");
+        write_iend(&mut png);
+        let err = decode(&png, true).unwrap_err();
+        assert!(matches!(err, DecodingError::Format(_)), "{err:?}");
+        let warnings = decode(&png, false).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, crate::DecodeWarningKind::ExtraImageData);
+    }
+
+    #[test]
+    fn truncated_input() {
+        let png = std::fs::read("tests/pngsuite/basn6a08.png").unwrap();
+        let truncated = &png[..png.len() / 2];
+        let err = block_on(async {
+            let mut reader = AsyncDecoder::new(truncated).read_info().await.unwrap();
+            let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+            reader.next_frame(&mut buf).await.unwrap_err()
+        });
+        assert!(matches!(err, DecodingError::IoError(_)), "{err:?}");
+    }
+}
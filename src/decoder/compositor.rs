@@ -0,0 +1,506 @@
+//! Composing the frames of an APNG onto a full canvas.
+
+use std::io::{BufRead, Seek};
+use std::mem;
+use std::ops::Range;
+
+use super::{Decoder, FrameIndex, Reader};
+use crate::common::{
+    BitDepth, BlendOp, ColorType, DisposeOp, Downscale, FrameControl, Info, Transformations,
+};
+use crate::DecodingError;
+
+/// Composes the frames of an animated PNG into full canvas frames.
+///
+/// [`Reader::next_frame`] returns the raw subframes of an animation. They have to be placed at
+/// their offset, blended with the previous frames and disposed of according to their
+/// [`FrameControl`]. The compositor does all of this and returns each frame as it should be
+/// displayed, as a RGBA image of the full image size.
+///
+/// The samples are 8 bits wide, or 16 bits in big endian byte order for images with a bit depth
+/// of 16. Set [`Transformations::STRIP_16`] on the decoder to always get 8-bit samples.
+///
+/// Images that are not animated are returned as a single frame, the default image of an
+/// animation that is not part of the animation itself is skipped.
+///
+/// ```
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use png::{AnimationCompositor, Decoder};
+///
+/// let file = BufReader::new(File::open("tests/animated/basic_f20.png").unwrap());
+/// let mut compositor = AnimationCompositor::new(Decoder::new(file)).unwrap();
+/// while let Some(frame) = compositor.next_frame().unwrap() {
+///     // Display `frame.buffer` for `frame.delay_num / frame.delay_den` seconds.
+///     assert_eq!(frame.buffer.len(), 4 * 100 * 100);
+/// }
+/// ```
+pub struct AnimationCompositor<R: BufRead> {
+    reader: Reader<R>,
+    /// The composed frame, as RGBA samples.
+    canvas: Vec<u8>,
+    /// The region of the canvas that was covered by the last frame, saved before drawing it.
+    previous: Vec<u8>,
+    /// The raw subframe as returned by the reader.
+    frame: Vec<u8>,
+    /// The frame control of the last frame, to dispose of it before drawing the next one.
+    last_frame: Option<FrameControl>,
+    /// Whether the default image has to be skipped as it is not part of the animation.
+    skip_default_image: bool,
+    remaining_frames: u32,
+    wide: bool,
+}
+
+/// A fully composed frame, see [`AnimationCompositor::next_frame`].
+#[derive(Clone, Copy, Debug)]
+pub struct CompositedFrame<'a> {
+    /// The RGBA samples of the whole image, in row-major order.
+    pub buffer: &'a [u8],
+    /// Frame delay fraction numerator.
+    ///
+    /// Frames of images that are not animated have no delay.
+    pub delay_num: u16,
+    /// Frame delay fraction denominator.
+    ///
+    /// If the denominator is 0, it is to be treated as if it were 100.
+    pub delay_den: u16,
+}
+
+impl<R: BufRead> AnimationCompositor<R> {
+    /// Reads the image header and prepares composing the frames.
+    ///
+    /// The transformations of the decoder are kept, [`Transformations::ALPHA`] is added so that
+    /// frames can be blended. Transformations of the channel order, such as
+    /// [`Transformations::BGR`], premultiplied alpha, sample formats such as
+    /// [`Transformations::F32`] and downscaling are not supported and are reset.
+    ///
+    /// The canvas and frame buffers are charged to the [`Limits`](crate::Limits) of the decoder,
+    /// returning [`DecodingError::LimitsExceeded`] if they do not fit.
+    pub fn new(mut decoder: Decoder<R>) -> Result<Self, DecodingError> {
+        decoder.set_transformations(
+            (decoder.transform | Transformations::ALPHA)
+                - Transformations::CHANNEL_LAYOUT
+                - Transformations::PREMULTIPLY
+                - Transformations::SAMPLE_FORMAT,
+        );
+        decoder.set_downscale(Downscale::None);
+        let mut reader = decoder.read_info()?;
+
+        let info = reader.info();
+        let (_, bit_depth) = reader.output_color_type();
+        let wide = bit_depth == BitDepth::Sixteen;
+        let canvas_len = canvas_len(info, wide).ok_or(DecodingError::LimitsExceeded)?;
+        let frame_len = reader
+            .output_buffer_size()
+            .ok_or(DecodingError::LimitsExceeded)?;
+
+        let (skip_default_image, remaining_frames) = match info.animation_control {
+            Some(animation) => (info.frame_control.is_none(), animation.num_frames),
+            None => (false, 1),
+        };
+
+        // The saved region of the previous frame can cover the whole canvas.
+        reader.decoder.reserve_bytes(canvas_len)?;
+        reader.decoder.reserve_bytes(canvas_len)?;
+        reader.decoder.reserve_bytes(frame_len)?;
+
+        Ok(AnimationCompositor {
+            reader,
+            canvas: vec![0; canvas_len],
+            previous: Vec::new(),
+            frame: vec![0; frame_len],
+            last_frame: None,
+            skip_default_image,
+            remaining_frames,
+            wide,
+        })
+    }
+
+    /// Get information on the image.
+    pub fn info(&self) -> &Info<'static> {
+        self.reader.info()
+    }
+
+    /// Returns the bit depth of the samples of the composed frames, either 8 or 16.
+    pub fn output_bit_depth(&self) -> BitDepth {
+        if self.wide {
+            BitDepth::Sixteen
+        } else {
+            BitDepth::Eight
+        }
+    }
+
+    /// Decodes the next frame and composes it onto the canvas.
+    ///
+    /// Returns `None` after the last frame of the animation.
+    pub fn next_frame(&mut self) -> Result<Option<CompositedFrame<'_>>, DecodingError> {
+        if self.remaining_frames == 0 {
+            return Ok(None);
+        }
+        if mem::take(&mut self.skip_default_image) {
+            self.reader.next_frame_info()?;
+        }
+        if let Some(last_frame) = self.last_frame.take() {
+            self.dispose(&last_frame);
+        }
+
+        let output = self.reader.next_frame(&mut self.frame)?;
+        let control = match self.reader.info().frame_control {
+            Some(control) => control,
+            None => FrameControl {
+                width: output.width,
+                height: output.height,
+                delay_num: 0,
+                delay_den: 0,
+                ..FrameControl::default()
+            },
+        };
+
+        if control.dispose_op == DisposeOp::Previous {
+            self.save_region(&control);
+        }
+        self.draw(&control, output.color_type, output.line_size);
+        self.last_frame = Some(control);
+        self.remaining_frames -= 1;
+
+        Ok(Some(CompositedFrame {
+            buffer: &self.canvas,
+            delay_num: control.delay_num,
+            delay_den: control.delay_den,
+        }))
+    }
+
+    /// Reads the rest of the image, see [`Reader::finish`].
+    pub fn finish(&mut self) -> Result<(), DecodingError> {
+        self.reader.finish()
+    }
+
+    fn pixel_bytes(&self) -> usize {
+        4 << usize::from(self.wide)
+    }
+
+    /// Returns the byte ranges of the rows of the canvas covered by a frame.
+    fn region_rows(&self, control: &FrameControl) -> impl Iterator<Item = Range<usize>> {
+        let pixel_bytes = self.pixel_bytes();
+        let stride = self.reader.info().width as usize * pixel_bytes;
+        let start = control.x_offset as usize * pixel_bytes;
+        let len = control.width as usize * pixel_bytes;
+        (control.y_offset as usize..)
+            .take(control.height as usize)
+            .map(move |y| y * stride + start..y * stride + start + len)
+    }
+
+    fn save_region(&mut self, control: &FrameControl) {
+        self.previous.clear();
+        for range in self.region_rows(control) {
+            self.previous.extend_from_slice(&self.canvas[range]);
+        }
+    }
+
+    fn dispose(&mut self, control: &FrameControl) {
+        match control.dispose_op {
+            DisposeOp::None => {}
+            DisposeOp::Background => {
+                for range in self.region_rows(control) {
+                    self.canvas[range].fill(0);
+                }
+            }
+            DisposeOp::Previous => {
+                // The region was saved before the frame was drawn. For the first frame that is
+                // the cleared canvas, as if it were disposed of with `DisposeOp::Background`.
+                let mut saved = self
+                    .previous
+                    .chunks_exact(control.width as usize * self.pixel_bytes());
+                for range in self.region_rows(control) {
+                    self.canvas[range].copy_from_slice(saved.next().unwrap());
+                }
+            }
+        }
+    }
+
+    /// Draws the decoded subframe onto the canvas.
+    fn draw(&mut self, control: &FrameControl, color_type: ColorType, line_size: usize) {
+        let samples = color_type.samples();
+        let sample_bytes = 1 << usize::from(self.wide);
+        let max = if self.wide { 0xffff } else { 0xff };
+        for (range, row) in self.region_rows(control).zip(self.frame.chunks(line_size)) {
+            let canvas = &mut self.canvas[range];
+            let pixels = row.chunks_exact(samples * sample_bytes);
+            for (dst, src) in canvas.chunks_exact_mut(4 * sample_bytes).zip(pixels) {
+                let src = read_pixel(src, samples, self.wide);
+                let pixel = match control.blend_op {
+                    BlendOp::Source => src,
+                    BlendOp::Over => blend_over(src, read_pixel(dst, 4, self.wide), max),
+                };
+                write_pixel(pixel, dst, self.wide);
+            }
+        }
+    }
+}
+
+impl<R: BufRead + Seek> AnimationCompositor<R> {
+    /// Moves to a frame of the animation, so that the next call to
+    /// [`AnimationCompositor::next_frame`] returns it.
+    ///
+    /// Only the frames from the keyframe of the frame on are decoded, see [`FrameIndex`]. The
+    /// index must have been built from the same input.
+    pub fn seek_to_frame(&mut self, index: &FrameIndex, frame: usize) -> Result<(), DecodingError> {
+        let keyframe = index.get(frame).map_or(frame, |indexed| indexed.keyframe);
+        self.reader.seek_to_frame(index, keyframe)?;
+        self.canvas.fill(0);
+        self.last_frame = None;
+        self.skip_default_image = false;
+        self.remaining_frames = (index.len() - keyframe) as u32;
+        for _ in keyframe..frame {
+            self.next_frame()?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the byte length of a RGBA canvas of the image size.
+fn canvas_len(info: &Info, wide: bool) -> Option<usize> {
+    let pixels = (info.width as usize).checked_mul(info.height as usize)?;
+    let len = pixels.checked_mul(4 << usize::from(wide))?;
+    (len <= isize::MAX as usize).then_some(len)
+}
+
+/// Reads a gray-alpha or RGBA pixel as RGBA samples.
+fn read_pixel(pixel: &[u8], samples: usize, wide: bool) -> [u32; 4] {
+    let sample = |idx: usize| {
+        if wide {
+            u32::from(u16::from_be_bytes([pixel[2 * idx], pixel[2 * idx + 1]]))
+        } else {
+            u32::from(pixel[idx])
+        }
+    };
+    match samples {
+        2 => {
+            let gray = sample(0);
+            [gray, gray, gray, sample(1)]
+        }
+        _ => [sample(0), sample(1), sample(2), sample(3)],
+    }
+}
+
+fn write_pixel(pixel: [u32; 4], output: &mut [u8], wide: bool) {
+    for (idx, sample) in pixel.into_iter().enumerate() {
+        if wide {
+            output[2 * idx..][..2].copy_from_slice(&(sample as u16).to_be_bytes());
+        } else {
+            output[idx] = sample as u8;
+        }
+    }
+}
+
+/// Blends `src` over `dst`, where both are not premultiplied and `max` is the opaque alpha value.
+fn blend_over(src: [u32; 4], dst: [u32; 4], max: u32) -> [u32; 4] {
+    match src[3] {
+        0 => return dst,
+        alpha if alpha == max => return src,
+        _ => {}
+    }
+
+    // With alpha values scaled to 0..=1 the result is `a = sa + da * (1 - sa)` and
+    // `c = (sc * sa + dc * da * (1 - sa)) / a`, both scaled by `max * max` here.
+    let (max, src_alpha) = (u64::from(max), u64::from(src[3]));
+    let dst_weight = u64::from(dst[3]) * (max - src_alpha);
+    let src_weight = src_alpha * max;
+    let total = src_weight + dst_weight;
+
+    let mut output = [0; 4];
+    for ((out, &s), &d) in output.iter_mut().zip(&src).zip(&dst).take(3) {
+        *out = ((u64::from(s) * src_weight + u64::from(d) * dst_weight + total / 2) / total) as u32;
+    }
+    output[3] = ((total + max / 2) / max) as u32;
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{blend_over, AnimationCompositor};
+    use crate::{
+        BitDepth, BlendOp, ColorType, Decoder, DecodingError, DisposeOp, Encoder, Limits,
+        Transformations,
+    };
+
+    #[test]
+    fn blend_over_is_exact_at_the_extremes() {
+        let dst = [10, 20, 30, 255];
+        assert_eq!(blend_over([1, 2, 3, 0], dst, 255), dst);
+        assert_eq!(blend_over([1, 2, 3, 255], dst, 255), [1, 2, 3, 255]);
+        assert_eq!(
+            blend_over([200, 0, 0, 128], [0, 0, 200, 255], 255),
+            [100, 0, 100, 255]
+        );
+        assert_eq!(
+            blend_over([255, 0, 0, 128], [0, 0, 0, 0], 255),
+            [255, 0, 0, 128]
+        );
+        assert_eq!(
+            blend_over([0xffff, 0, 0, 0x8000], [0, 0, 0, 0xffff], 0xffff),
+            [0x8000, 0, 0, 0xffff]
+        );
+    }
+
+    fn composited_frames(png: &[u8], transform: Transformations) -> Vec<(Vec<u8>, u16, u16)> {
+        let mut decoder = Decoder::new(Cursor::new(png));
+        decoder.set_transformations(transform);
+        let mut compositor = AnimationCompositor::new(decoder).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = compositor.next_frame().unwrap() {
+            frames.push((frame.buffer.to_vec(), frame.delay_num, frame.delay_den));
+        }
+        compositor.finish().unwrap();
+        frames
+    }
+
+    #[test]
+    fn offsets_blending_and_disposal() {
+        const RED: [u8; 4] = [255, 0, 0, 255];
+        let mut png = Vec::new();
+        let mut encoder = Encoder::new(&mut png, 4, 4);
+        encoder.set_color(ColorType::Rgba);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.set_animated(4, 0).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+
+        writer.set_frame_delay(1, 10).unwrap();
+        writer.write_image_data(&RED.repeat(16)).unwrap();
+
+        writer.set_frame_dimension(2, 2).unwrap();
+        writer.set_frame_position(1, 1).unwrap();
+        writer.set_blend_op(BlendOp::Over).unwrap();
+        writer.set_dispose_op(DisposeOp::Previous).unwrap();
+        writer
+            .write_image_data(&[0, 0, 255, 128].repeat(4))
+            .unwrap();
+
+        writer.set_frame_dimension(1, 1).unwrap();
+        writer.set_frame_position(0, 0).unwrap();
+        writer.set_blend_op(BlendOp::Source).unwrap();
+        writer.set_dispose_op(DisposeOp::Background).unwrap();
+        writer.write_image_data(&[0, 255, 0, 255]).unwrap();
+
+        writer.set_frame_position(3, 3).unwrap();
+        writer.set_blend_op(BlendOp::Over).unwrap();
+        writer.set_frame_delay(0, 0).unwrap();
+        writer.write_image_data(&[0, 0, 0, 0]).unwrap();
+        writer.finish().unwrap();
+
+        let canvas = |pixels: &[((usize, usize), [u8; 4])]| {
+            let mut canvas = RED.repeat(16);
+            for &((x, y), pixel) in pixels {
+                canvas[(y * 4 + x) * 4..][..4].copy_from_slice(&pixel);
+            }
+            canvas
+        };
+        let blended = [127, 0, 128, 255];
+        let frames = composited_frames(&png, Transformations::IDENTITY);
+        assert_eq!(
+            frames,
+            [
+                (canvas(&[]), 1, 10),
+                (
+                    canvas(&[
+                        ((1, 1), blended),
+                        ((2, 1), blended),
+                        ((1, 2), blended),
+                        ((2, 2), blended),
+                    ]),
+                    1,
+                    10
+                ),
+                (canvas(&[((0, 0), [0, 255, 0, 255])]), 1, 10),
+                (canvas(&[((0, 0), [0; 4])]), 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn previous_on_first_frame_clears() {
+        let mut png = Vec::new();
+        let mut encoder = Encoder::new(&mut png, 2, 1);
+        encoder.set_color(ColorType::Grayscale);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.set_animated(2, 0).unwrap();
+        encoder.set_dispose_op(DisposeOp::Previous).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[10, 20]).unwrap();
+        writer.set_frame_dimension(1, 1).unwrap();
+        writer.set_blend_op(BlendOp::Over).unwrap();
+        writer.write_image_data(&[30]).unwrap();
+        writer.finish().unwrap();
+
+        let frames = composited_frames(&png, Transformations::IDENTITY);
+        assert_eq!(frames[0].0, [10, 10, 10, 255, 20, 20, 20, 255]);
+        assert_eq!(frames[1].0, [30, 30, 30, 255, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn separate_default_image_is_skipped() {
+        let mut png = Vec::new();
+        let mut encoder = Encoder::new(&mut png, 1, 1);
+        encoder.set_color(ColorType::Rgb);
+        encoder.set_depth(BitDepth::Sixteen);
+        encoder.set_animated(1, 0).unwrap();
+        encoder.set_sep_def_img(true).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[0xff; 6]).unwrap();
+        writer.write_image_data(&[1, 2, 3, 4, 5, 6]).unwrap();
+        writer.finish().unwrap();
+
+        let frames = composited_frames(&png, Transformations::IDENTITY);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].0, [1, 2, 3, 4, 5, 6, 0xff, 0xff]);
+
+        let frames = composited_frames(&png, Transformations::STRIP_16);
+        assert_eq!(frames[0].0, [1, 3, 5, 0xff]);
+    }
+
+    #[test]
+    fn still_image_is_a_single_frame() {
+        let png = std::fs::read("tests/pngsuite/basn3p08.png").unwrap();
+        let mut decoder = Decoder::new(Cursor::new(&png));
+        decoder.set_transformations(Transformations::ALPHA);
+        let mut expected = vec![0; 32 * 32 * 4];
+        decoder
+            .read_info()
+            .unwrap()
+            .next_frame(&mut expected)
+            .unwrap();
+
+        let frames = composited_frames(&png, Transformations::IDENTITY);
+        assert_eq!(frames, [(expected, 0, 0)]);
+    }
+
+    #[test]
+    fn buffers_are_charged_to_the_limits() {
+        let png = std::fs::read("tests/animated/basic_f20.png").unwrap();
+        let limits = Limits { bytes: 64 * 1024 };
+        let decoder = Decoder::new_with_limits(Cursor::new(&png), limits);
+        assert!(matches!(
+            AnimationCompositor::new(decoder),
+            Err(DecodingError::LimitsExceeded)
+        ));
+    }
+
+    #[test]
+    fn animated_file() {
+        let png = std::fs::read("tests/animated/basic_f20.png").unwrap();
+        let num_frames = Decoder::new(Cursor::new(&png))
+            .read_info()
+            .unwrap()
+            .info()
+            .animation_control
+            .unwrap()
+            .num_frames;
+        let frames = composited_frames(&png, Transformations::IDENTITY);
+        assert_eq!(frames.len(), num_frames as usize);
+        assert!(frames
+            .iter()
+            .all(|(buffer, _, _)| buffer.len() == 100 * 100 * 4));
+    }
+}
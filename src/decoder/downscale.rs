@@ -0,0 +1,275 @@
+//! Box filtering rows of a frame while it is decoded, see [`crate::Downscale`].
+
+use crate::common::{BitDepth, Transformations};
+
+/// The representation of the samples of decoded rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum SampleFormat {
+    U8,
+    /// 16 bit samples in big endian byte order.
+    U16,
+    /// 16 bit samples in native byte order, see [`Transformations::NATIVE_ENDIAN`].
+    U16Native,
+    /// Native endian `f32` samples, see [`Transformations::F32`].
+    F32,
+}
+
+impl SampleFormat {
+    /// Returns the format of samples of at least 8 bits that `transform` outputs.
+    pub fn of_output(transform: Transformations, bit_depth: BitDepth) -> Self {
+        if transform.intersects(Transformations::F32 | Transformations::LINEAR) {
+            SampleFormat::F32
+        } else if bit_depth != BitDepth::Sixteen {
+            SampleFormat::U8
+        } else if transform.contains(Transformations::NATIVE_ENDIAN) {
+            SampleFormat::U16Native
+        } else {
+            SampleFormat::U16
+        }
+    }
+
+    fn bytes(self) -> usize {
+        match self {
+            SampleFormat::U8 => 1,
+            SampleFormat::U16 | SampleFormat::U16Native => 2,
+            SampleFormat::F32 => 4,
+        }
+    }
+
+    fn read(self, bytes: &[u8]) -> f64 {
+        match self {
+            SampleFormat::U8 => f64::from(bytes[0]),
+            SampleFormat::U16 => f64::from(u16::from_be_bytes([bytes[0], bytes[1]])),
+            SampleFormat::U16Native => f64::from(u16::from_ne_bytes([bytes[0], bytes[1]])),
+            SampleFormat::F32 => {
+                f64::from(f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            }
+        }
+    }
+
+    /// Writes an average, rounding integers half up.
+    fn write(self, average: f64, bytes: &mut [u8]) {
+        match self {
+            SampleFormat::U8 => bytes[0] = average.round() as u8,
+            SampleFormat::U16 => bytes.copy_from_slice(&(average.round() as u16).to_be_bytes()),
+            SampleFormat::U16Native => {
+                bytes.copy_from_slice(&(average.round() as u16).to_ne_bytes())
+            }
+            SampleFormat::F32 => bytes.copy_from_slice(&(average as f32).to_ne_bytes()),
+        }
+    }
+}
+
+/// Averages blocks of `factor` by `factor` pixels, fed one full resolution row at a time.
+///
+/// Blocks at the right and bottom edge of the frame may have fewer pixels, they are averaged over
+/// the pixels that are present.
+pub(super) struct BoxFilter {
+    factor: usize,
+    samples: usize,
+    format: SampleFormat,
+    /// Full resolution width of the rows.
+    width: usize,
+    /// Sums of the samples of the current row of blocks, exact for integer samples.
+    sums: Vec<f64>,
+    /// The number of rows added to `sums`.
+    rows: u32,
+}
+
+impl BoxFilter {
+    pub fn new(width: u32, factor: u32, samples: usize, format: SampleFormat) -> Self {
+        let scaled_width = width.div_ceil(factor) as usize;
+        BoxFilter {
+            factor: factor as usize,
+            samples,
+            format,
+            width: width as usize,
+            sums: vec![0.0; scaled_width * samples],
+            rows: 0,
+        }
+    }
+
+    /// Adds the samples of a full resolution row to the current row of blocks.
+    pub fn add_row(&mut self, row: &[u8]) {
+        let sample_bytes = self.format.bytes();
+        let pixel_bytes = self.samples * sample_bytes;
+        let pixels = row[..self.width * pixel_bytes].chunks_exact(pixel_bytes);
+        for (idx, pixel) in pixels.enumerate() {
+            let sums = &mut self.sums[idx / self.factor * self.samples..][..self.samples];
+            for (sum, sample) in sums.iter_mut().zip(pixel.chunks_exact(sample_bytes)) {
+                *sum += self.format.read(sample);
+            }
+        }
+        self.rows += 1;
+    }
+
+    /// Writes the averages of the current row of blocks to `output` and starts the next row.
+    pub fn finish_row(&mut self, output: &mut [u8]) {
+        let sample_bytes = self.format.bytes();
+        let outputs = output.chunks_exact_mut(self.samples * sample_bytes);
+        for (idx, (sums, output)) in self
+            .sums
+            .chunks_exact_mut(self.samples)
+            .zip(outputs)
+            .enumerate()
+        {
+            let columns = self.factor.min(self.width - idx * self.factor) as u32;
+            let count = f64::from(columns * self.rows);
+            for (sum, output) in sums.iter_mut().zip(output.chunks_exact_mut(sample_bytes)) {
+                self.format.write(*sum / count, output);
+                *sum = 0.0;
+            }
+        }
+        self.rows = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{BoxFilter, SampleFormat};
+    use crate::test_codec::{decode_frame, decode_png, encode_png};
+    use crate::{BitDepth, ColorType, Decoder, Downscale, Transformations};
+
+    #[test]
+    fn averages_blocks() {
+        // Three pixels of two samples each, the last block only has a single column.
+        let mut filter = BoxFilter::new(3, 2, 2, SampleFormat::U8);
+        filter.add_row(&[0, 10, 2, 20, 100, 0]);
+        filter.add_row(&[4, 30, 6, 41, 201, 255]);
+        let mut output = [0; 4];
+        filter.finish_row(&mut output);
+        assert_eq!(output, [3, 25, 151, 128]);
+
+        filter.add_row(&[9, 9, 9, 9, 7, 7]);
+        filter.finish_row(&mut output);
+        assert_eq!(output, [9, 9, 7, 7]);
+    }
+
+    #[test]
+    fn averages_wide_samples() {
+        let mut filter = BoxFilter::new(2, 2, 1, SampleFormat::U16);
+        filter.add_row(&[0xff, 0xff, 0x00, 0x01]);
+        let mut output = [0; 2];
+        filter.finish_row(&mut output);
+        assert_eq!(output, 0x8000u16.to_be_bytes());
+    }
+
+    #[test]
+    fn averages_float_samples() {
+        let mut filter = BoxFilter::new(2, 2, 1, SampleFormat::F32);
+        let row: Vec<u8> = [0.25f32, 0.5]
+            .iter()
+            .flat_map(|f| f.to_ne_bytes())
+            .collect();
+        filter.add_row(&row);
+        let mut output = [0; 4];
+        filter.finish_row(&mut output);
+        assert_eq!(f32::from_ne_bytes(output), 0.375);
+
+        let mut filter = BoxFilter::new(1, 2, 1, SampleFormat::U16Native);
+        filter.add_row(&1000u16.to_ne_bytes());
+        filter.add_row(&1001u16.to_ne_bytes());
+        let mut output = [0; 2];
+        filter.finish_row(&mut output);
+        assert_eq!(u16::from_ne_bytes(output), 1001);
+    }
+
+    #[test]
+    fn box_filter_non_interlaced() {
+        let (width, height) = (13, 10);
+        let pixels: Vec<u8> = (0..width * height).map(|i| (i * 7 % 256) as u8).collect();
+        let format = (ColorType::Grayscale, BitDepth::Eight);
+        let png = encode_png((width, height), format, &pixels, |_| {});
+
+        for downscale in [Downscale::Half, Downscale::Quarter, Downscale::Eighth] {
+            let f = downscale.factor();
+            let mut decoder = Decoder::new(Cursor::new(&png));
+            decoder.set_downscale(downscale);
+            let (info, actual) = decode_frame(decoder);
+            let (w, h) = (info.width, info.height);
+            assert_eq!((w, h), (downscale.apply(width), downscale.apply(height)));
+
+            let mut expected = Vec::new();
+            for y in 0..h {
+                for x in 0..w {
+                    let (mut sum, mut count) = (0, 0);
+                    for yy in y * f..((y + 1) * f).min(height) {
+                        for xx in x * f..((x + 1) * f).min(width) {
+                            sum += u32::from(pixels[(yy * width + xx) as usize]);
+                            count += 1;
+                        }
+                    }
+                    expected.push(((sum + count / 2) / count) as u8);
+                }
+            }
+            assert_eq!(expected, actual, "{downscale:?}");
+        }
+    }
+
+    #[test]
+    fn indexed_is_sampled() {
+        let (width, height) = (9, 9);
+        let pixels: Vec<u8> = (0..width * height).map(|i| i as u8).collect();
+        let palette: Vec<u8> = (0..=255).flat_map(|i| [i, 255 - i, i / 2]).collect();
+        for interlaced in [false, true] {
+            let format = (ColorType::Indexed, BitDepth::Eight);
+            let png = encode_png((width, height), format, &pixels, |encoder| {
+                encoder.set_interlaced(interlaced);
+                encoder.set_palette(palette.clone());
+            });
+            for transform in [Transformations::IDENTITY, Transformations::EXPAND] {
+                let mut decoder = Decoder::new(Cursor::new(&png));
+                decoder.set_downscale(Downscale::Quarter);
+                decoder.set_transformations(transform);
+                let (info, actual) = decode_frame(decoder);
+                assert_eq!((info.width, info.height), (3, 3));
+                if transform == Transformations::IDENTITY {
+                    assert_eq!(actual, [0, 4, 8, 36, 40, 44, 72, 76, 80]);
+                } else {
+                    // Expanded to RGB, non-interlaced images are averaged instead.
+                    assert_eq!(actual.len(), 3 * 3 * 3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn interlaced_stops_after_early_pass() {
+        let (width, height) = (17, 11);
+        let pixels: Vec<u8> = (0..width * height * 3)
+            .map(|i| (i * 13 % 256) as u8)
+            .collect();
+        let format = (ColorType::Rgb, BitDepth::Eight);
+        let png = encode_png((width, height), format, &pixels, |encoder| {
+            encoder.set_interlaced(true);
+        });
+
+        for downscale in [Downscale::Half, Downscale::Quarter, Downscale::Eighth] {
+            let f = downscale.factor();
+            let mut decoder = Decoder::new(Cursor::new(&png));
+            decoder.set_downscale(downscale);
+            let (info, actual) = decode_frame(decoder);
+            let mut expected = Vec::new();
+            for y in 0..info.height {
+                for x in 0..info.width {
+                    let idx = ((y * f * width + x * f) * 3) as usize;
+                    expected.extend_from_slice(&pixels[idx..idx + 3]);
+                }
+            }
+            assert_eq!(expected, actual, "{downscale:?}");
+        }
+    }
+
+    #[test]
+    fn matches_full_resolution_without_downscale() {
+        let png = std::fs::read("tests/pngsuite/basi2c16.png").unwrap();
+        let (_, expected) = decode_png(&png, Transformations::IDENTITY);
+        let mut decoder = Decoder::new(Cursor::new(&png));
+        decoder.set_downscale(Downscale::None);
+        let (info, actual) = decode_frame(decoder);
+        assert_eq!((info.width, info.height), (32, 32));
+        assert_eq!(expected, actual);
+    }
+}
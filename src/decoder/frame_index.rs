@@ -0,0 +1,306 @@
+//! An index of the frames of an animation, for decoding them in any order.
+
+use std::io::{BufRead, ErrorKind};
+use std::ops::Range;
+
+use super::stream::{Decoded, DecodingError, StreamingDecoder};
+use crate::chunk;
+use crate::common::{BlendOp, DisposeOp, FrameControl};
+
+/// The location of all frames of an animated PNG.
+///
+/// The index is built by a single pass over the chunks of the image, without inflating any image
+/// data. With it [`Reader::seek_to_frame`] and [`AnimationCompositor::seek_to_frame`] jump to a
+/// frame directly.
+///
+/// Composing a frame depends on the frames before it, up to its keyframe: the nearest frame that
+/// is drawn on a known canvas. That is either a cleared canvas, or one that is completely
+/// replaced by the keyframe itself.
+///
+/// Offsets are counted from the start of the input, the PNG signature, and refer to the same
+/// positions in the input that is later seeked.
+///
+/// [`Reader::seek_to_frame`]: crate::Reader::seek_to_frame
+/// [`AnimationCompositor::seek_to_frame`]: crate::AnimationCompositor::seek_to_frame
+#[derive(Clone, Debug, Default)]
+pub struct FrameIndex {
+    frames: Vec<IndexedFrame>,
+}
+
+/// A frame of an animation, see [`FrameIndex`].
+#[derive(Clone, Debug)]
+pub struct IndexedFrame {
+    /// The frame control of this frame.
+    pub control: FrameControl,
+    /// The offset of the `fcTL` chunk of this frame.
+    pub control_offset: u64,
+    /// The `IDAT` or `fdAT` chunks with the image data of this frame.
+    pub data_range: Range<u64>,
+    /// The index of the keyframe that composing this frame starts at.
+    pub keyframe: usize,
+    /// Whether the image data is in `IDAT` chunks.
+    pub(super) in_idat: bool,
+}
+
+impl FrameIndex {
+    /// Reads all chunks of an image and records where its animation frames are.
+    ///
+    /// Images that are not animated have no frames in the index. A default image that is not part
+    /// of the animation is not indexed either.
+    pub fn scan<R: BufRead>(mut r: R) -> Result<Self, DecodingError> {
+        let mut decoder = StreamingDecoder::new();
+        decoder.set_ignore_text_chunk(true);
+        decoder.set_ignore_iccp_chunk(true);
+
+        let mut frames = Vec::new();
+        let mut position = 0;
+        let mut chunk_start = 0;
+        // The frame control and its offset, until the image data of the frame is complete.
+        let mut control = None;
+        // The range of the image data chunks so far, and whether they are `IDAT` chunks.
+        let mut data: Option<(Range<u64>, bool)> = None;
+        // Whether the canvas is cleared before the next frame is drawn.
+        let mut cleared = true;
+
+        loop {
+            let (consumed, decoded) = {
+                let buf = r.fill_buf()?;
+                if buf.is_empty() {
+                    return Err(DecodingError::IoError(ErrorKind::UnexpectedEof.into()));
+                }
+                decoder.update(buf, None)?
+            };
+            r.consume(consumed);
+            position += consumed as u64;
+
+            match decoded {
+                // The length and type of the chunk have just been read.
+                Decoded::ChunkBegin(_, type_) => {
+                    chunk_start = position - 8;
+                    if type_ == chunk::IDAT || type_ == chunk::fdAT {
+                        data.get_or_insert((chunk_start..position, type_ == chunk::IDAT));
+                    }
+                }
+                Decoded::ChunkComplete(chunk::fcTL) => {
+                    let info = decoder.info().unwrap();
+                    control = Some((info.frame_control.unwrap(), chunk_start));
+                }
+                Decoded::ChunkComplete(chunk::IDAT) | Decoded::ChunkComplete(chunk::fdAT) => {
+                    if let Some((range, _)) = &mut data {
+                        range.end = position;
+                    }
+                }
+                Decoded::ImageDataFlushed => {
+                    let (data_range, in_idat) = data.take().unwrap_or((position..position, false));
+                    let Some((control, control_offset)) = control.take() else {
+                        // The default image is not part of the animation.
+                        continue;
+                    };
+                    let idx = frames.len();
+                    let full = control.x_offset == 0
+                        && control.y_offset == 0
+                        && (control.width, control.height) == decoder.info().unwrap().size();
+
+                    // A frame that replaces the whole canvas can start composing, unless it
+                    // restores the unknown canvas from before it afterwards.
+                    let keyframe = if cleared
+                        || (full
+                            && control.blend_op == BlendOp::Source
+                            && control.dispose_op != DisposeOp::Previous)
+                    {
+                        idx
+                    } else {
+                        frames
+                            .last()
+                            .map_or(idx, |frame: &IndexedFrame| frame.keyframe)
+                    };
+                    cleared = match control.dispose_op {
+                        DisposeOp::None => false,
+                        DisposeOp::Background => full || cleared,
+                        DisposeOp::Previous => cleared,
+                    };
+
+                    frames.push(IndexedFrame {
+                        control,
+                        control_offset,
+                        data_range,
+                        keyframe,
+                        in_idat,
+                    });
+                }
+                Decoded::ChunkComplete(chunk::IEND) => break,
+                _ => {}
+            }
+        }
+
+        Ok(FrameIndex { frames })
+    }
+
+    /// The indexed frames, in the order of the animation.
+    pub fn frames(&self) -> &[IndexedFrame] {
+        &self.frames
+    }
+
+    /// Returns the number of frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns `true` if the image has no animation frames.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Returns a frame by its index in the animation.
+    pub fn get(&self, frame: usize) -> Option<&IndexedFrame> {
+        self.frames.get(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::FrameIndex;
+    use crate::{
+        AnimationCompositor, AnimationEncoder, BitDepth, BlendOp, ColorType, Decoder, DisposeOp,
+        Encoder,
+    };
+
+    fn composited(png: &[u8]) -> Vec<Vec<u8>> {
+        let mut compositor = AnimationCompositor::new(Decoder::new(Cursor::new(png))).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = compositor.next_frame().unwrap() {
+            frames.push(frame.buffer.to_vec());
+        }
+        frames
+    }
+
+    /// Checks that seeking to each frame, in reverse, gives the same frames as decoding in order.
+    fn check_seeking(png: &[u8]) {
+        let index = FrameIndex::scan(Cursor::new(png)).unwrap();
+
+        let mut reader = Decoder::new(Cursor::new(png)).read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        let mut raw_frames = Vec::new();
+        if reader.info().frame_control.is_none() {
+            reader.next_frame_info().unwrap();
+        }
+        for indexed in index.frames() {
+            let info = reader.next_frame(&mut buf).unwrap();
+            let control = reader.info().frame_control.unwrap();
+            assert_eq!(control.sequence_number, indexed.control.sequence_number);
+            raw_frames.push(buf[..info.buffer_size()].to_vec());
+        }
+        reader.finish().unwrap();
+
+        for (frame, expected) in raw_frames.iter().enumerate().rev() {
+            reader.seek_to_frame(&index, frame).unwrap();
+            let info = reader.next_frame(&mut buf).unwrap();
+            assert_eq!(
+                &buf[..info.buffer_size()],
+                &expected[..],
+                "raw frame {frame}"
+            );
+        }
+
+        let frames = composited(png);
+        assert_eq!(frames.len(), index.len());
+        let mut compositor = AnimationCompositor::new(Decoder::new(Cursor::new(png))).unwrap();
+        for (frame, expected) in frames.iter().enumerate().rev() {
+            compositor.seek_to_frame(&index, frame).unwrap();
+            let actual = compositor.next_frame().unwrap().unwrap();
+            assert_eq!(actual.buffer, &expected[..], "composed frame {frame}");
+        }
+    }
+
+    #[test]
+    fn index_animated_file() {
+        let png = std::fs::read("tests/animated/basic_f20.png").unwrap();
+        let index = FrameIndex::scan(Cursor::new(&png)).unwrap();
+        assert_eq!(index.len(), 20);
+        for indexed in index.frames() {
+            let offset = indexed.control_offset as usize;
+            assert_eq!(&png[offset + 4..offset + 8], b"fcTL");
+            let data = indexed.data_range.start as usize;
+            assert!([&b"IDAT"[..], b"fdAT"].contains(&&png[data + 4..data + 8]));
+            assert!(indexed.data_range.end > indexed.data_range.start);
+        }
+        check_seeking(&png);
+    }
+
+    #[test]
+    fn keyframes_of_optimized_animation() {
+        let (width, height) = (8, 8);
+        let frames: Vec<Vec<u8>> = (0..8u8)
+            .map(|frame| {
+                (0..width * height)
+                    .flat_map(|i| {
+                        let (x, y) = (i % width, i / width);
+                        let inside = (frame..frame + 2).contains(&(x as u8)) && y < 4;
+                        match frame {
+                            // Every pixel changes, so this frame is a keyframe.
+                            4 => [4; 4],
+                            _ if inside => [255, 0, frame, 255],
+                            0..=3 => [0, 0, 0, 128],
+                            _ => [4; 4],
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut png = Vec::new();
+        let mut encoder = Encoder::new(&mut png, width, height);
+        encoder.set_color(ColorType::Rgba);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.set_animated(frames.len() as u32, 0).unwrap();
+        let mut animation = AnimationEncoder::new(encoder).unwrap();
+        animation.set_transparent_unchanged(true);
+        for frame in &frames {
+            animation.write_frame(frame, 1, 30).unwrap();
+        }
+        animation.finish().unwrap();
+
+        let index = FrameIndex::scan(Cursor::new(&png)).unwrap();
+        let keyframes: Vec<_> = index.frames().iter().map(|f| f.keyframe).collect();
+        assert_eq!(keyframes, [0, 0, 0, 0, 4, 4, 4, 4]);
+        check_seeking(&png);
+        assert_eq!(composited(&png), frames);
+    }
+
+    #[test]
+    fn separate_default_image_is_not_indexed() {
+        let mut png = Vec::new();
+        let mut encoder = Encoder::new(&mut png, 2, 1);
+        encoder.set_color(ColorType::Grayscale);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.set_animated(3, 0).unwrap();
+        encoder.set_sep_def_img(true).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[1, 2]).unwrap();
+        writer.write_image_data(&[3, 4]).unwrap();
+        writer.set_frame_dimension(1, 1).unwrap();
+        writer.set_frame_position(1, 0).unwrap();
+        writer.set_blend_op(BlendOp::Over).unwrap();
+        writer.set_dispose_op(DisposeOp::Previous).unwrap();
+        writer.write_image_data(&[5]).unwrap();
+        writer.write_image_data(&[6]).unwrap();
+        writer.finish().unwrap();
+
+        let index = FrameIndex::scan(Cursor::new(&png)).unwrap();
+        assert_eq!(index.len(), 3);
+        assert!(index.frames().iter().all(|frame| frame.keyframe == 0));
+        check_seeking(&png);
+    }
+
+    #[test]
+    fn still_image_has_no_frames() {
+        let png = std::fs::read("tests/pngsuite/basn0g08.png").unwrap();
+        let index = FrameIndex::scan(Cursor::new(&png)).unwrap();
+        assert!(index.is_empty());
+
+        let mut reader = Decoder::new(Cursor::new(&png)).read_info().unwrap();
+        assert!(reader.seek_to_frame(&index, 0).is_err());
+    }
+}
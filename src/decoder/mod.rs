@@ -1,25 +1,42 @@
+#[cfg(feature = "async")]
+mod async_decoder;
+mod compositor;
+mod downscale;
+mod frame_index;
 mod interlace_info;
+#[cfg(feature = "parallel")]
+mod parallel;
 mod read_decoder;
+pub(crate) mod region;
+mod slice;
 pub(crate) mod stream;
 pub(crate) mod transform;
 mod unfiltering_buffer;
 mod zlib;
 
-use self::read_decoder::{ImageDataCompletionStatus, ReadDecoder};
-use self::stream::{DecodeOptions, DecodingError, FormatErrorInner};
+use self::read_decoder::{block_on, ImageDataCompletionStatus, Input, ReadDecoder};
+use self::stream::{DecodeOptions, DecodeWarning, DecodingError, FormatErrorInner};
 use self::transform::{create_transform_fn, TransformFn};
 use self::unfiltering_buffer::UnfilteringBuffer;
 
 use std::io::{BufRead, Seek};
 use std::mem;
+use std::task::{ready, Context, Poll};
 
 use crate::adam7::Adam7Info;
 use crate::common::{
-    BitDepth, BytesPerPixel, ColorType, Info, ParameterErrorKind, Transformations,
+    BitDepth, BytesPerPixel, ColorType, Downscale, Info, ParameterErrorKind, ScaledFloat,
+    Transformations,
 };
 use crate::FrameControl;
 pub use zlib::{UnfilterBuf, UnfilterRegion};
 
+#[cfg(feature = "async")]
+pub use async_decoder::{AsyncDecoder, AsyncReader};
+pub use compositor::{AnimationCompositor, CompositedFrame};
+pub use frame_index::{FrameIndex, IndexedFrame};
+pub use slice::{SliceDecoder, SliceReader};
+
 pub use interlace_info::InterlaceInfo;
 use interlace_info::InterlaceInfoIter;
 
@@ -76,6 +93,11 @@ impl Limits {
             Err(DecodingError::LimitsExceeded)
         }
     }
+
+    /// Returns bytes reserved with [`Limits::reserve_bytes`] once they are freed again.
+    pub(crate) fn release_bytes(&mut self, bytes: usize) {
+        self.bytes = self.bytes.saturating_add(bytes);
+    }
 }
 
 impl Default for Limits {
@@ -87,10 +109,14 @@ impl Default for Limits {
 }
 
 /// PNG Decoder
-pub struct Decoder<R: BufRead + Seek> {
+pub struct Decoder<R> {
     read_decoder: ReadDecoder<R>,
     /// Output transformations
     transform: Transformations,
+    /// Scaling of decoded frames
+    downscale: Downscale,
+    /// Target of `Transformations::GAMMA`
+    display_gamma: ScaledFloat,
 }
 
 /// A row of data with interlace information attached.
@@ -122,7 +148,7 @@ impl<'data> Row<'data> {
     }
 }
 
-impl<R: BufRead + Seek> Decoder<R> {
+impl<R: BufRead> Decoder<R> {
     /// Create a new decoder configuration with default limits.
     pub fn new(r: R) -> Decoder<R> {
         Decoder::new_with_limits(r, Limits::default())
@@ -132,50 +158,14 @@ impl<R: BufRead + Seek> Decoder<R> {
     pub fn new_with_limits(r: R, limits: Limits) -> Decoder<R> {
         let mut read_decoder = ReadDecoder::new(r);
         read_decoder.set_limits(limits);
-
-        Decoder {
-            read_decoder,
-            transform: Transformations::IDENTITY,
-        }
+        Decoder::with_read_decoder(read_decoder)
     }
 
     /// Create a new decoder configuration with custom [`DecodeOptions`].
     pub fn new_with_options(r: R, decode_options: DecodeOptions) -> Decoder<R> {
         let mut read_decoder = ReadDecoder::with_options(r, decode_options);
         read_decoder.set_limits(Limits::default());
-
-        Decoder {
-            read_decoder,
-            transform: Transformations::IDENTITY,
-        }
-    }
-
-    /// Limit resource usage.
-    ///
-    /// Note that your allocations, e.g. when reading into a pre-allocated buffer, are __NOT__
-    /// considered part of the limits. Nevertheless, required intermediate buffers such as for
-    /// singular lines is checked against the limit.
-    ///
-    /// Note that this is a best-effort basis.
-    ///
-    /// ```
-    /// use std::fs::File;
-    /// use std::io::BufReader;
-    /// use png::{Decoder, Limits};
-    /// // This image is 32×32, 1bit per pixel. The reader buffers one row which requires 4 bytes.
-    /// let mut limits = Limits::default();
-    /// limits.bytes = 3;
-    /// let mut decoder = Decoder::new_with_limits(BufReader::new(File::open("tests/pngsuite/basi0g01.png").unwrap()), limits);
-    /// assert!(decoder.read_info().is_err());
-    ///
-    /// // This image is 32x32 pixels, so the decoder will allocate less than 10Kib
-    /// let mut limits = Limits::default();
-    /// limits.bytes = 10*1024;
-    /// let mut decoder = Decoder::new_with_limits(BufReader::new(File::open("tests/pngsuite/basi0g01.png").unwrap()), limits);
-    /// assert!(decoder.read_info().is_ok());
-    /// ```
-    pub fn set_limits(&mut self, limits: Limits) {
-        self.read_decoder.set_limits(limits);
+        Decoder::with_read_decoder(read_decoder)
     }
 
     /// Read the PNG header and return the information contained within.
@@ -190,16 +180,39 @@ impl<R: BufRead + Seek> Decoder<R> {
 
     /// Reads all meta data until the first IDAT chunk
     pub fn read_info(mut self) -> Result<Reader<R>, DecodingError> {
-        let info = self.read_header_info()?;
+        self.read_header_info()?;
+        let mut reader = self.into_reader()?;
+        reader.read_until_image_data()?;
+
+        reader.remaining_frames = frame_count(reader.info());
+        Ok(reader)
+    }
+}
+
+impl<R> Decoder<R> {
+    fn with_read_decoder(read_decoder: ReadDecoder<R>) -> Decoder<R> {
+        Decoder {
+            read_decoder,
+            transform: Transformations::IDENTITY,
+            downscale: Downscale::None,
+            display_gamma: crate::srgb::substitute_gamma(),
+        }
+    }
+
+    /// Creates the reader once the header has been read, before any other chunk is read.
+    fn into_reader(self) -> Result<Reader<R>, DecodingError> {
+        let info = self.read_decoder.info().unwrap();
         let unfiltering_buffer = UnfilteringBuffer::new(info);
 
-        let mut reader = Reader {
+        let reader = Reader {
             decoder: self.read_decoder,
             bpp: BytesPerPixel::One,
             subframe: SubframeInfo::not_yet_init(),
-            remaining_frames: 0, // Temporary value - fixed below after reading `acTL` and `fcTL`.
+            remaining_frames: 0, // Temporary value - fixed after reading `acTL` and `fcTL`.
             unfiltering_buffer,
             transform: self.transform,
+            downscale: self.downscale,
+            display_gamma: self.display_gamma,
             transform_fn: None,
             scratch_buffer: Vec::new(),
             finished: false,
@@ -224,23 +237,37 @@ impl<R: BufRead + Seek> Decoder<R> {
             return Err(DecodingError::LimitsExceeded);
         }
 
-        reader.read_until_image_data()?;
-
-        reader.remaining_frames = match reader.info().animation_control.as_ref() {
-            None => 1, // No `acTL` => only expecting `IDAT` frame.
-            Some(animation) => {
-                let mut num_frames = animation.num_frames as usize;
-                if reader.info().frame_control.is_none() {
-                    // No `fcTL` before `IDAT` => `IDAT` is not part of the animation, but
-                    // represents an *extra*, default frame for non-APNG-aware decoders.
-                    num_frames += 1;
-                }
-                num_frames
-            }
-        };
         Ok(reader)
     }
 
+    /// Limit resource usage.
+    ///
+    /// Note that your allocations, e.g. when reading into a pre-allocated buffer, are __NOT__
+    /// considered part of the limits. Nevertheless, required intermediate buffers such as for
+    /// singular lines is checked against the limit.
+    ///
+    /// Note that this is a best-effort basis.
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use std::io::BufReader;
+    /// use png::{Decoder, Limits};
+    /// // This image is 32×32, 1bit per pixel. The reader buffers one row which requires 4 bytes.
+    /// let mut limits = Limits::default();
+    /// limits.bytes = 3;
+    /// let mut decoder = Decoder::new_with_limits(BufReader::new(File::open("tests/pngsuite/basi0g01.png").unwrap()), limits);
+    /// assert!(decoder.read_info().is_err());
+    ///
+    /// // This image is 32x32 pixels, so the decoder will allocate less than 10Kib
+    /// let mut limits = Limits::default();
+    /// limits.bytes = 10*1024;
+    /// let mut decoder = Decoder::new_with_limits(BufReader::new(File::open("tests/pngsuite/basi0g01.png").unwrap()), limits);
+    /// assert!(decoder.read_info().is_ok());
+    /// ```
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.read_decoder.set_limits(limits);
+    }
+
     /// Set the allowed and performed transformations.
     ///
     /// A transformation is a pre-processing on the raw image data modifying content or encoding.
@@ -249,6 +276,60 @@ impl<R: BufRead + Seek> Decoder<R> {
         self.transform = transform;
     }
 
+    /// Scale frames down while they are decoded by [`Reader::next_frame`].
+    ///
+    /// Each pixel of the output is the average of a block of pixels of the frame, computed as the
+    /// rows arrive so the frame is never held at full resolution. Interlaced frames are sampled
+    /// instead: decoding stops after the last Adam7 pass that contains the pixels at the top left
+    /// of each block, which is the first pass for [`Downscale::Eighth`]. Indexed and sub-byte
+    /// output can not be averaged and is always sampled.
+    ///
+    /// [`Reader::output_buffer_size`] and the [`OutputInfo`] returned by `next_frame` describe
+    /// the scaled frame. Other ways of reading image data, such as [`Reader::next_row`], are not
+    /// affected.
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use std::io::BufReader;
+    /// use png::{Decoder, Downscale};
+    /// let mut decoder = Decoder::new(BufReader::new(File::open("tests/pngsuite/basn2c08.png").unwrap()));
+    /// decoder.set_downscale(Downscale::Quarter);
+    /// let mut reader = decoder.read_info().unwrap();
+    /// let mut thumbnail = vec![0; reader.output_buffer_size().unwrap()];
+    /// let info = reader.next_frame(&mut thumbnail).unwrap();
+    /// assert_eq!((info.width, info.height), (8, 8));
+    /// ```
+    pub fn set_downscale(&mut self, downscale: Downscale) {
+        self.downscale = downscale;
+    }
+
+    /// Set the gamma that [`Transformations::GAMMA`] corrects color samples to.
+    ///
+    /// The gamma is given like the `gAMA` chunk: the exponent that encodes linear light into
+    /// samples. After the correction the samples are encoded as if the image had a `gAMA` chunk
+    /// with this value, and `1.0` yields linear samples. The default is the gamma of sRGB,
+    /// `0.45455`.
+    ///
+    /// The gamma of the image is [`Info::gamma`], taken from its `gAMA` or `sRGB` chunk.
+    /// Images without either and indexed output are not corrected. Only the significant bits of
+    /// each sample given by the `sBIT` chunk are used.
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use std::io::BufReader;
+    /// use png::{Decoder, ScaledFloat, Transformations};
+    /// let mut decoder = Decoder::new(BufReader::new(File::open("tests/pngsuite/g10n0g16.png").unwrap()));
+    /// decoder.set_transformations(Transformations::GAMMA);
+    /// decoder.set_display_gamma(ScaledFloat::new(1.0 / 2.2));
+    /// let mut reader = decoder.read_info().unwrap();
+    /// assert_eq!(reader.info().gamma(), Some(ScaledFloat::new(1.0)));
+    /// let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+    /// reader.next_frame(&mut buf).unwrap();
+    /// ```
+    pub fn set_display_gamma(&mut self, display_gamma: ScaledFloat) {
+        self.display_gamma = display_gamma;
+    }
+
     /// Set the decoder to ignore all text chunks while parsing.
     ///
     /// eg.
@@ -279,6 +360,23 @@ impl<R: BufRead + Seek> Decoder<R> {
         self.read_decoder.set_ignore_iccp_chunk(ignore_iccp_chunk);
     }
 
+    /// Set the decoder to collect unrecognized ancillary chunks into [`Info::unknown_chunks`].
+    ///
+    /// eg.
+    /// ```
+    /// use std::fs::File;
+    /// use std::io::BufReader;
+    /// use png::Decoder;
+    /// let mut decoder = Decoder::new(BufReader::new(File::open("tests/pngsuite/basi0g01.png").unwrap()));
+    /// decoder.set_keep_unknown_chunks(true);
+    /// let reader = decoder.read_info().unwrap();
+    /// assert!(reader.info().unknown_chunks.is_empty());
+    /// ```
+    pub fn set_keep_unknown_chunks(&mut self, keep_unknown_chunks: bool) {
+        self.read_decoder
+            .set_keep_unknown_chunks(keep_unknown_chunks);
+    }
+
     /// Set the decoder to ignore and not verify the Adler-32 checksum
     /// and CRC code.
     pub fn ignore_checksums(&mut self, ignore_checksums: bool) {
@@ -289,7 +387,7 @@ impl<R: BufRead + Seek> Decoder<R> {
 /// PNG reader (mostly high-level interface)
 ///
 /// Provides a high level that iterates over lines or whole images.
-pub struct Reader<R: BufRead + Seek> {
+pub struct Reader<R> {
     decoder: ReadDecoder<R>,
     bpp: BytesPerPixel,
     subframe: SubframeInfo,
@@ -299,6 +397,10 @@ pub struct Reader<R: BufRead + Seek> {
     unfiltering_buffer: UnfilteringBuffer,
     /// Output transformations
     transform: Transformations,
+    /// Scaling of frames decoded by `next_frame`
+    downscale: Downscale,
+    /// Target of `Transformations::GAMMA`
+    display_gamma: ScaledFloat,
     /// Function that can transform decompressed, unfiltered rows into final output.
     /// See the `transform.rs` module for more details.
     transform_fn: Option<TransformFn>,
@@ -325,7 +427,7 @@ struct SubframeInfo {
     consumed_and_flushed: bool,
 }
 
-impl<R: BufRead + Seek> Reader<R> {
+impl<R: BufRead> Reader<R> {
     /// Advances to the start of the next animation frame and
     /// returns a reference to the [`FrameControl`] info that describes it.
     /// Skips and discards the image data of the previous frame if necessary.
@@ -337,23 +439,7 @@ impl<R: BufRead + Seek> Reader<R> {
     /// [`ParameterError`]: crate::ParameterError
     /// [`AnimationControl::num_frames`]: crate::AnimationControl::num_frames
     pub fn next_frame_info(&mut self) -> Result<&FrameControl, DecodingError> {
-        let remaining_frames = if self.subframe.consumed_and_flushed {
-            self.remaining_frames
-        } else {
-            // One remaining frame will be consumed by the `finish_decoding` call below.
-            self.remaining_frames - 1
-        };
-        if remaining_frames == 0 {
-            return Err(DecodingError::Parameter(
-                ParameterErrorKind::PolledAfterEndOfImage.into(),
-            ));
-        }
-
-        if !self.subframe.consumed_and_flushed {
-            self.subframe.current_interlace_info = None;
-            self.finish_decoding()?;
-        }
-        self.read_until_image_data()?;
+        block_on(|cx| self.poll_next_frame_info(cx))?;
 
         // The PNG standard (and `StreamingDecoder `) guarantes that there is an `fcTL` chunk
         // before the start of image data in a sequence of `fdAT` chunks.  Therefore `unwrap`
@@ -364,44 +450,13 @@ impl<R: BufRead + Seek> Reader<R> {
     /// Reads all meta data until the next frame data starts.
     /// Requires IHDR before the IDAT and fcTL before fdAT.
     fn read_until_image_data(&mut self) -> Result<(), DecodingError> {
-        self.decoder.read_until_image_data()?;
-
-        self.subframe = SubframeInfo::new(self.info());
-        self.bpp = self.info().bpp_in_prediction();
-
-        let frame_bytes = if self.info().interlaced {
-            let mut bytes = 0u64;
-            for pass in crate::adam7::PassConstants::PASSES {
-                bytes += self
-                    .info()
-                    .raw_row_length_from_width(pass.count_samples(self.subframe.width))
-                    as u64
-                    * pass.count_lines(self.subframe.height) as u64;
-            }
-            bytes
-        } else {
-            (self.subframe.rowlen as u64) * self.subframe.height as u64
-        };
-        self.unfiltering_buffer.start_frame(frame_bytes);
-
-        // Allocate output buffer.
-        let buflen = self.unguarded_output_line_size(self.subframe.width);
-        self.decoder.reserve_bytes(buflen)?;
-
-        Ok(())
-    }
-
-    /// Get information on the image.
-    ///
-    /// The structure will change as new frames of an animated image are decoded.
-    pub fn info(&self) -> &Info<'static> {
-        self.decoder.info().unwrap()
+        block_on(|cx| self.poll_read_until_image_data(cx))
     }
 
     /// Decodes the next frame into `buf`.
     ///
     /// Note that this decodes raw subframes that need to be mixed according to blend-op and
-    /// dispose-op by the caller.
+    /// dispose-op by the caller. [`AnimationCompositor`] composes them into full frames.
     ///
     /// The caller must always provide a buffer large enough to hold a complete frame (the APNG
     /// specification restricts subframes to the dimensions given in the image header). The region
@@ -412,34 +467,258 @@ impl<R: BufRead + Seek> Reader<R> {
     /// Output lines will be written in row-major, packed matrix with width and height of the read
     /// frame (or subframe), all samples are in big endian byte order where this matters.
     pub fn next_frame(&mut self, buf: &mut [u8]) -> Result<OutputInfo, DecodingError> {
+        block_on(|cx| self.poll_start_frame(cx, buf.len()))?;
+        if self.downscale != Downscale::None {
+            return self.next_frame_downscaled(buf);
+        }
+        block_on(|cx| self.poll_next_frame_rows(cx, buf))
+    }
+
+    /// Decodes the next frame scaled down by `self.downscale`.
+    fn next_frame_downscaled(&mut self, buf: &mut [u8]) -> Result<OutputInfo, DecodingError> {
+        let factor = self.downscale.factor();
+        let (color_type, bit_depth) = self.output_color_type();
+        let width = self.downscale.apply(self.subframe.width);
+        let output_info = OutputInfo {
+            width,
+            height: self.downscale.apply(self.subframe.height),
+            color_type,
+            bit_depth,
+            line_size: self.unguarded_output_line_size(width),
+        };
+        let line_size = output_info.line_size;
+        let bits_pp = output_bits_per_pixel(self.info(), self.transform);
+
+        let mut scratch = mem::take(&mut self.scratch_buffer);
+        scratch.resize(self.unguarded_output_line_size(self.info().width), 0);
+
+        let result = if self.info().interlaced {
+            // Adam7 passes up to this one contain exactly the pixels at multiples of `factor`.
+            let last_pass = match self.downscale {
+                Downscale::Eighth => 1,
+                Downscale::Quarter => 3,
+                Downscale::Half => 5,
+                Downscale::None => 7,
+            };
+            self.next_passes_sampled(buf, &mut scratch, line_size, last_pass, factor, bits_pp)
+        } else {
+            self.next_rows_downscaled(buf, &mut scratch, &output_info, factor, bits_pp)
+        };
+        self.scratch_buffer = scratch;
+        result?;
+
+        // Skip over the rest of data for this (sub-)frame.
+        self.subframe.current_interlace_info = None;
+        self.finish_decoding()?;
+
+        Ok(output_info)
+    }
+
+    /// Reads the rows of a non-interlaced frame into blocks of `factor` rows.
+    fn next_rows_downscaled(
+        &mut self,
+        buf: &mut [u8],
+        scratch: &mut [u8],
+        output_info: &OutputInfo,
+        factor: u32,
+        bits_pp: usize,
+    ) -> Result<(), DecodingError> {
+        let (color_type, bit_depth) = (output_info.color_type, output_info.bit_depth);
+        let mut filter = (color_type != ColorType::Indexed && bit_depth as u8 >= 8).then(|| {
+            let format = downscale::SampleFormat::of_output(self.transform, bit_depth);
+            downscale::BoxFilter::new(self.subframe.width, factor, color_type.samples(), format)
+        });
+
+        let first_row = self
+            .subframe
+            .current_interlace_info
+            .map_or(self.subframe.height, |info| info.line_number());
+        for row_idx in first_row..self.subframe.height {
+            if self.read_row(scratch)?.is_none() {
+                return Err(DecodingError::Format(
+                    FormatErrorInner::NoMoreImageData.into(),
+                ));
+            }
+            let output = &mut buf[(row_idx / factor) as usize * output_info.line_size..]
+                [..output_info.line_size];
+            match &mut filter {
+                Some(filter) => {
+                    filter.add_row(scratch);
+                    if (row_idx + 1) % factor == 0 || row_idx + 1 == self.subframe.height {
+                        filter.finish_row(output);
+                    }
+                }
+                None if row_idx % factor == 0 => {
+                    for idx in 0..output_info.width as usize {
+                        region::copy_pixel(scratch, idx * factor as usize, output, idx, bits_pp);
+                    }
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the Adam7 passes of an interlaced frame up to `last_pass` and places their pixels.
+    fn next_passes_sampled(
+        &mut self,
+        buf: &mut [u8],
+        scratch: &mut [u8],
+        line_size: usize,
+        last_pass: u8,
+        factor: u32,
+        bits_pp: usize,
+    ) -> Result<(), DecodingError> {
+        while let Some(InterlaceInfo::Adam7(adam7)) = self.subframe.current_interlace_info {
+            if adam7.pass > last_pass {
+                break;
+            }
+            self.read_row(scratch)?;
+            for idx in 0..adam7.samples {
+                let (column, row) = adam7.image_position(idx);
+                debug_assert!(column % factor == 0 && row % factor == 0);
+                let output = &mut buf[(row / factor) as usize * line_size..];
+                region::copy_pixel(
+                    scratch,
+                    idx as usize,
+                    output,
+                    (column / factor) as usize,
+                    bits_pp,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes the next frame into `buf` as native endian 16 bit samples.
+    ///
+    /// The frame is decoded like [`Reader::next_frame`] with [`Transformations::EXPAND`] and
+    /// [`Transformations::NATIVE_ENDIAN`] added, so `buf` needs one element per sample of the
+    /// expanded output. 8 bit samples are scaled to the full range of `u16`, the returned
+    /// [`OutputInfo::bit_depth`] is their precision.
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use std::io::BufReader;
+    /// let file = File::open("tests/pngsuite/basn2c16.png").unwrap();
+    /// let mut reader = png::Decoder::new(BufReader::new(file)).read_info().unwrap();
+    /// let mut buf = vec![0; 32 * 32 * 3];
+    /// let info = reader.next_frame_u16(&mut buf).unwrap();
+    /// assert_eq!(info.bit_depth, png::BitDepth::Sixteen);
+    /// ```
+    pub fn next_frame_u16(&mut self, buf: &mut [u16]) -> Result<OutputInfo, DecodingError> {
+        let transform = (self.transform | Transformations::EXPAND | Transformations::NATIVE_ENDIAN)
+            - Transformations::F32
+            - Transformations::LINEAR;
+        self.next_frame_samples(transform, buf, |data, bit_depth, buf| {
+            if bit_depth == BitDepth::Sixteen {
+                for (sample, bytes) in buf.iter_mut().zip(data.chunks_exact(2)) {
+                    *sample = u16::from_ne_bytes([bytes[0], bytes[1]]);
+                }
+            } else {
+                for (sample, &byte) in buf.iter_mut().zip(data) {
+                    *sample = u16::from(byte) * 257;
+                }
+            }
+        })
+    }
+
+    /// Decodes the next frame into `buf` as samples normalized to `0.0..=1.0`.
+    ///
+    /// The frame is decoded like [`Reader::next_frame`] with [`Transformations::F32`] added, so
+    /// `buf` needs one element per sample of the expanded output.
+    pub fn next_frame_f32(&mut self, buf: &mut [f32]) -> Result<OutputInfo, DecodingError> {
+        let transform = self.transform | Transformations::F32;
+        self.next_frame_samples(transform, buf, f32_samples)
+    }
+
+    /// Decodes the next frame into `buf` as samples of linear sRGB.
+    ///
+    /// The frame is decoded like [`Reader::next_frame`] with [`Transformations::LINEAR`] added,
+    /// so `buf` needs one element per sample of the expanded output. Colors are converted from
+    /// the color space of the image, given by its `cICP`, `sRGB`, `cHRM` and `gAMA` chunks, into
+    /// linear light with the primaries of sRGB. See [`Transformations::SRGB`] for the same
+    /// conversion to sRGB samples.
+    ///
+    /// Alpha samples are normalized to `0.0..=1.0`. Color samples are not clipped: colors outside
+    /// of the sRGB gamut have negative components and the highlights of HDR images exceed `1.0`,
+    /// which is the reference white of 203 cd/m² for PQ and HLG images.
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use std::io::BufReader;
+    /// let file = File::open("tests/pngsuite/g10n2c08.png").unwrap();
+    /// let mut reader = png::Decoder::new(BufReader::new(file)).read_info().unwrap();
+    /// let mut buf = vec![0.0; 32 * 32 * 3];
+    /// let info = reader.next_frame_linear_srgb(&mut buf).unwrap();
+    /// assert_eq!((info.width, info.height), (32, 32));
+    /// ```
+    pub fn next_frame_linear_srgb(&mut self, buf: &mut [f32]) -> Result<OutputInfo, DecodingError> {
+        let transform = self.transform | Transformations::LINEAR;
+        self.next_frame_samples(transform, buf, f32_samples)
+    }
+
+    /// Decodes the next frame with `transform` instead of the configured transformations, and
+    /// converts the decoded bytes into one element of `buf` per sample.
+    fn next_frame_samples<T>(
+        &mut self,
+        transform: Transformations,
+        buf: &mut [T],
+        convert: fn(&[u8], BitDepth, &mut [T]),
+    ) -> Result<OutputInfo, DecodingError> {
+        let previous = mem::replace(&mut self.transform, transform);
+        if previous != transform {
+            self.transform_fn = None;
+        }
+        let result = self.next_frame_converted(buf, convert);
+        if self.transform != previous {
+            self.transform = previous;
+            self.transform_fn = None;
+        }
+        result
+    }
+
+    fn next_frame_converted<T>(
+        &mut self,
+        buf: &mut [T],
+        convert: fn(&[u8], BitDepth, &mut [T]),
+    ) -> Result<OutputInfo, DecodingError> {
         if self.remaining_frames == 0 {
             return Err(DecodingError::Parameter(
                 ParameterErrorKind::PolledAfterEndOfImage.into(),
             ));
         } else if self.subframe.consumed_and_flushed {
-            // Advance until the next `fdAT`
-            // (along the way we should encounter the fcTL for this frame).
             self.read_until_image_data()?;
         }
 
-        // Note that we only check if the buffer size calculation holds in a call to decoding the
-        // frame. Consequently, we can represent the `Info` and frameless decoding even when the
-        // target architecture's address space is too small for a frame. However reading the actual
-        let required_len = self
+        let (color_type, bit_depth) = self.output_color_type();
+        let pixel_bytes = output_bits_per_pixel(self.info(), self.transform) / 8;
+        let sample_bytes = pixel_bytes / color_type.samples();
+        let size = self
             .output_buffer_size()
             .ok_or(DecodingError::LimitsExceeded)?;
-
-        if buf.len() < required_len {
+        if buf.len() < size / sample_bytes {
             return Err(DecodingError::Parameter(
                 ParameterErrorKind::ImageBufferSize {
-                    expected: required_len,
+                    expected: size / sample_bytes,
                     actual: buf.len(),
                 }
                 .into(),
             ));
         }
 
-        let (color_type, bit_depth) = self.output_color_type();
+        if self.downscale != Downscale::None {
+            // Downscaled rows are not complete until later rows or passes are read, so the frame
+            // is decoded into a temporary buffer that is charged to the limits.
+            self.decoder.reserve_bytes(size)?;
+            let mut data = vec![0; size];
+            let result = self.next_frame(&mut data);
+            self.decoder.release_bytes(size);
+            let output_info = result?;
+            convert(&data[..output_info.buffer_size()], bit_depth, buf);
+            return Ok(output_info);
+        }
+
         let output_info = OutputInfo {
             width: self.subframe.width,
             height: self.subframe.height,
@@ -447,65 +726,394 @@ impl<R: BufRead + Seek> Reader<R> {
             bit_depth,
             line_size: self.unguarded_output_line_size(self.subframe.width),
         };
+        let mut scratch = mem::take(&mut self.scratch_buffer);
+        scratch.resize(self.unguarded_output_line_size(self.info().width), 0);
+        let line_samples = output_info.line_size / sample_bytes;
+        let result = self.next_rows_converted(
+            buf,
+            &mut scratch,
+            line_samples,
+            (color_type.samples(), pixel_bytes),
+            bit_depth,
+            convert,
+        );
+        self.scratch_buffer = scratch;
+        result?;
 
-        if self.info().interlaced {
-            let stride = self.unguarded_output_line_size(self.info().width);
-            let samples = color_type.samples() as u8;
-            let bits_pp = samples * (bit_depth as u8);
-            let expand = crate::adam7::expand_pass;
+        Ok(output_info)
+    }
 
-            while let Some(InterlacedRow {
-                data: row,
-                interlace,
-                ..
-            }) = self.next_interlaced_row()?
-            {
-                // `unwrap` won't panic, because we checked `self.info().interlaced` above.
-                let adam7info = interlace.get_adam7_info().unwrap();
-                expand(buf, stride, row, adam7info, bits_pp);
+    /// Reads the remaining rows of the frame and converts each of them straight into `buf`.
+    fn next_rows_converted<T>(
+        &mut self,
+        buf: &mut [T],
+        scratch: &mut [u8],
+        line_samples: usize,
+        (pixel_samples, pixel_bytes): (usize, usize),
+        bit_depth: BitDepth,
+        convert: fn(&[u8], BitDepth, &mut [T]),
+    ) -> Result<(), DecodingError> {
+        while let Some(interlace) = self.read_row(scratch)? {
+            match interlace {
+                InterlaceInfo::Null(_) => {
+                    let line = interlace.line_number() as usize;
+                    let output = &mut buf[line * line_samples..][..line_samples];
+                    convert(
+                        &scratch[..line_samples * pixel_bytes / pixel_samples],
+                        bit_depth,
+                        output,
+                    );
+                }
+                InterlaceInfo::Adam7(adam7) => {
+                    for idx in 0..adam7.samples {
+                        let (column, row) = adam7.image_position(idx);
+                        let pixel = &scratch[idx as usize * pixel_bytes..][..pixel_bytes];
+                        let position =
+                            row as usize * line_samples + column as usize * pixel_samples;
+                        convert(pixel, bit_depth, &mut buf[position..][..pixel_samples]);
+                    }
+                }
             }
-        } else {
-            let current_interlace_info = self.subframe.current_interlace_info.as_ref();
-            let already_done_rows = current_interlace_info
-                .map(|info| info.line_number())
-                .unwrap_or(self.subframe.height);
+        }
+        Ok(())
+    }
 
-            for row in buf
-                .chunks_exact_mut(output_info.line_size)
-                .take(self.subframe.height as usize)
-                .skip(already_done_rows as usize)
+    /// Decodes a rectangle of the next frame into `buf`.
+    ///
+    /// The rectangle is `width` by `height` pixels with its top left corner at column `x` and row
+    /// `y` of the frame (or subframe, see [`Reader::next_frame`]). It is written tightly packed, with
+    /// lines of [`Reader::output_line_size`] for `width` bytes, and the returned [`OutputInfo`]
+    /// describes the rectangle.
+    ///
+    /// Rows above the rectangle still have to be decompressed and unfiltered, but only the columns
+    /// of the rectangle are transformed and decoding stops after its last row. The remaining image
+    /// data of the frame is skipped without decompressing it. Interlaced frames are decoded in
+    /// full because every Adam7 pass contributes to the rectangle, but only its pixels are copied
+    /// into `buf`.
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use std::io::BufReader;
+    /// let file = File::open("tests/pngsuite/basn2c08.png").unwrap();
+    /// let mut reader = png::Decoder::new(BufReader::new(file)).read_info().unwrap();
+    /// // A 4x2 pixel RGB tile at column 8 and row 16 of the 32x32 image.
+    /// let mut tile = vec![0; reader.output_line_size(4).unwrap() * 2];
+    /// let info = reader.next_frame_region(&mut tile, 8, 16, 4, 2).unwrap();
+    /// assert_eq!((info.width, info.height, info.line_size), (4, 2, 12));
+    /// ```
+    pub fn next_frame_region(
+        &mut self,
+        buf: &mut [u8],
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<OutputInfo, DecodingError> {
+        if self.remaining_frames == 0 {
+            return Err(DecodingError::Parameter(
+                ParameterErrorKind::PolledAfterEndOfImage.into(),
+            ));
+        } else if self.subframe.consumed_and_flushed {
+            self.read_until_image_data()?;
+        }
+
+        let fits =
+            |start: u32, len: u32, end: u32| start.checked_add(len).is_some_and(|e| e <= end);
+        if !fits(x, width, self.subframe.width) || !fits(y, height, self.subframe.height) {
+            return Err(DecodingError::Parameter(
+                ParameterErrorKind::RegionOutOfBounds {
+                    x,
+                    y,
+                    width,
+                    height,
+                }
+                .into(),
+            ));
+        }
+
+        let line_size = self
+            .output_line_size(width)
+            .ok_or(DecodingError::LimitsExceeded)?;
+        let required_len = line_size
+            .checked_mul(height as usize)
+            .ok_or(DecodingError::LimitsExceeded)?;
+        if buf.len() < required_len {
+            return Err(DecodingError::Parameter(
+                ParameterErrorKind::ImageBufferSize {
+                    expected: required_len,
+                    actual: buf.len(),
+                }
+                .into(),
+            ));
+        }
+
+        let (color_type, bit_depth) = self.output_color_type();
+        let output_info = OutputInfo {
+            width,
+            height,
+            color_type,
+            bit_depth,
+            line_size,
+        };
+        let bits_pp = output_bits_per_pixel(self.info(), self.transform);
+        let mut scratch = mem::take(&mut self.scratch_buffer);
+        scratch.resize(self.unguarded_output_line_size(self.subframe.width), 0);
+
+        let result = if self.info().interlaced {
+            self.next_interlaced_region(buf, &mut scratch, &output_info, x, y, bits_pp)
+        } else {
+            self.next_region(buf, &mut scratch, &output_info, x, y, bits_pp)
+        };
+        self.scratch_buffer = scratch;
+        result?;
+
+        // Skip over the rest of data for this (sub-)frame.
+        self.subframe.current_interlace_info = None;
+        self.finish_decoding()?;
+
+        Ok(output_info)
+    }
+
+    /// Decodes the rows of a non-interlaced frame up to the last row of a region.
+    fn next_region(
+        &mut self,
+        buf: &mut [u8],
+        scratch: &mut [u8],
+        region: &OutputInfo,
+        x: u32,
+        y: u32,
+        bits_pp: usize,
+    ) -> Result<(), DecodingError> {
+        if self.transform_fn.is_none() {
+            self.transform_fn = Some(create_transform_fn(
+                self.info(),
+                self.transform,
+                self.display_gamma,
+            )?);
+        }
+        // Sub-byte input pixels can not be sliced out of the raw row.
+        let in_bits_pp = self.info().color_type.samples() * self.info().bit_depth as usize;
+        let rowlen = self.subframe.rowlen;
+
+        let first_row = self
+            .subframe
+            .current_interlace_info
+            .map_or(self.subframe.height, |info| info.line_number());
+        for row_idx in first_row..y + region.height {
+            self.next_raw_interlaced_row(rowlen)?;
+            self.subframe.current_interlace_info = self.subframe.interlace_info_iter.next();
+            let Some(line) = row_idx.checked_sub(y) else {
+                continue;
+            };
+
+            let row = self.unfiltering_buffer.prev_row();
+            let output = &mut buf[line as usize * region.line_size..][..region.line_size];
+            let transform_fn = self.transform_fn.as_deref().unwrap();
+            if in_bits_pp % 8 == 0 {
+                let in_bytes_pp = in_bits_pp / 8;
+                let row = &row[x as usize * in_bytes_pp..][..region.width as usize * in_bytes_pp];
+                transform_fn(row, output, self.info());
+            } else {
+                transform_fn(row, scratch, self.info());
+                region::copy_pixels(scratch, x, region.width, bits_pp, output);
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes all passes of an interlaced frame and copies the pixels within a region.
+    fn next_interlaced_region(
+        &mut self,
+        buf: &mut [u8],
+        scratch: &mut [u8],
+        region: &OutputInfo,
+        x: u32,
+        y: u32,
+        bits_pp: usize,
+    ) -> Result<(), DecodingError> {
+        let columns = x..x + region.width;
+        let rows = y..y + region.height;
+        while let Some(interlace) = self.read_row(scratch)? {
+            let InterlaceInfo::Adam7(adam7) = interlace else {
+                unreachable!("interlaced frames have Adam7 rows");
+            };
+            if !rows.contains(&adam7.image_position(0).1) {
+                continue;
+            }
+            for idx in 0..adam7.samples {
+                let (column, row) = adam7.image_position(idx);
+                if column >= columns.end {
+                    break;
+                } else if column >= x {
+                    let output = &mut buf[(row - y) as usize * region.line_size..];
+                    region::copy_pixel(
+                        scratch,
+                        idx as usize,
+                        output,
+                        (column - x) as usize,
+                        bits_pp,
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes the next frame into `buf` like [`Reader::next_frame`], using multiple threads.
+    ///
+    /// The image data of the frame is inflated in a single pass first. The rows are then
+    /// unfiltered in bands on separate threads, where a band can start at any row using the `None`
+    /// or `Sub` filter because those do not depend on the row above. How much of the unfiltering
+    /// runs in parallel thus depends on the filters chosen by the encoder, while the output
+    /// transformations are always spread over all threads.
+    ///
+    /// The whole inflated frame is held in memory in addition to `buf` and counts against the
+    /// [`Limits`], so this is meant for large images that are already in memory, for example
+    /// read through a `Cursor<&[u8]>`. Interlaced and small frames, frames that have been
+    /// partially read with [`Reader::next_row`], and machines with a single core fall back to
+    /// [`Reader::next_frame`].
+    ///
+    /// Only available with the `parallel` feature.
+    #[cfg(feature = "parallel")]
+    pub fn next_frame_parallel(&mut self, buf: &mut [u8]) -> Result<OutputInfo, DecodingError> {
+        let threads = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+        if self.subframe.consumed_and_flushed && self.remaining_frames > 0 {
+            self.read_until_image_data()?;
+        }
+        let frame_bytes = self.subframe.rowlen as u64 * u64::from(self.subframe.height);
+        let bands = frame_bytes / parallel::MIN_BAND_SIZE as u64;
+        self.next_frame_parallel_impl(buf, threads.min(bands as usize))
+    }
+
+    #[cfg(feature = "parallel")]
+    fn next_frame_parallel_impl(
+        &mut self,
+        buf: &mut [u8],
+        threads: usize,
+    ) -> Result<OutputInfo, DecodingError> {
+        if self.remaining_frames == 0 {
+            return Err(DecodingError::Parameter(
+                ParameterErrorKind::PolledAfterEndOfImage.into(),
+            ));
+        } else if self.subframe.consumed_and_flushed {
+            self.read_until_image_data()?;
+        }
+
+        let rows_read = self
+            .subframe
+            .current_interlace_info
+            .map_or(true, |info| info.line_number() != 0);
+        let frame_len =
+            usize::try_from(self.subframe.rowlen as u64 * u64::from(self.subframe.height));
+        let frame_len = match frame_len {
+            Ok(frame_len)
+                if !self.info().interlaced
+                    && !rows_read
+                    && threads > 1
+                    && self.downscale == Downscale::None =>
             {
-                self.next_interlaced_row_impl(self.subframe.rowlen, row)?;
+                frame_len
             }
+            _ => return self.next_frame(buf),
+        };
+
+        let required_len = self
+            .output_buffer_size()
+            .ok_or(DecodingError::LimitsExceeded)?;
+        if buf.len() < required_len {
+            return Err(DecodingError::Parameter(
+                ParameterErrorKind::ImageBufferSize {
+                    expected: required_len,
+                    actual: buf.len(),
+                }
+                .into(),
+            ));
         }
 
-        // Advance over the rest of data for this (sub-)frame.
-        self.finish_decoding()?;
+        let (color_type, bit_depth) = self.output_color_type();
+        let output_info = OutputInfo {
+            width: self.subframe.width,
+            height: self.subframe.height,
+            color_type,
+            bit_depth,
+            line_size: self.unguarded_output_line_size(self.subframe.width),
+        };
+
+        // The inflated frame is only charged to the limits while it is alive. Frames that don't
+        // fit into them are decoded row by row instead.
+        if self.decoder.reserve_bytes(frame_len).is_err() {
+            return self.next_frame(buf);
+        }
+        let result =
+            self.inflate_and_unfilter_parallel(buf, frame_len, output_info.line_size, threads);
+        self.decoder.release_bytes(frame_len);
+        result?;
+
+        self.subframe.current_interlace_info = None;
+        self.finish_decoding_inflated(true)?;
 
         Ok(output_info)
     }
 
-    fn mark_subframe_as_consumed_and_flushed(&mut self) {
-        assert!(self.remaining_frames > 0);
-        self.remaining_frames -= 1;
+    /// Inflates the whole frame before any of it is unfiltered, then unfilters and transforms
+    /// it into `buf` on `threads` threads.
+    #[cfg(feature = "parallel")]
+    fn inflate_and_unfilter_parallel(
+        &mut self,
+        buf: &mut [u8],
+        frame_len: usize,
+        line_size: usize,
+        threads: usize,
+    ) -> Result<(), DecodingError> {
+        let mut data = vec![0; frame_len];
+        let mut region = UnfilterRegion::default();
+        while region.filled < frame_len {
+            if self.subframe.consumed_and_flushed {
+                return Err(DecodingError::Format(
+                    FormatErrorInner::NoMoreImageData.into(),
+                ));
+            }
+            match self
+                .decoder
+                .decode_image_data(Some(&mut region.as_buf(&mut data)))?
+            {
+                ImageDataCompletionStatus::ExpectingMoreData => (),
+                ImageDataCompletionStatus::Done => self.mark_subframe_as_consumed_and_flushed(),
+            }
+        }
 
-        self.subframe.consumed_and_flushed = true;
+        if self.transform_fn.is_none() {
+            self.transform_fn = Some(create_transform_fn(
+                self.info(),
+                self.transform,
+                self.display_gamma,
+            )?);
+        }
+        let transform_fn = self.transform_fn.as_deref().unwrap();
+        let info = self.decoder.info().unwrap();
+        let output_len = line_size * self.subframe.height as usize;
+        parallel::unfilter_and_transform(
+            &mut data,
+            self.subframe.rowlen,
+            self.bpp,
+            &mut buf[..output_len],
+            line_size,
+            &|row, output| transform_fn(row, output, info),
+            threads,
+        )
     }
 
     /// Advance over the rest of data for this (sub-)frame.
     /// Called after decoding the last row of a frame.
     fn finish_decoding(&mut self) -> Result<(), DecodingError> {
-        // Double-check that all rows of this frame have been decoded (i.e. that the potential
-        // `finish_decoding` call below won't be discarding any data).
-        assert!(self.subframe.current_interlace_info.is_none());
-
-        // Discard the remaining data in the current sequence of `IDAT` or `fdAT` chunks.
-        if !self.subframe.consumed_and_flushed {
-            self.decoder.finish_decoding_image_data()?;
-            self.mark_subframe_as_consumed_and_flushed();
-        }
+        block_on(|cx| self.poll_finish_decoding(cx))
+    }
 
-        Ok(())
+    /// Like [`Reader::finish_decoding`], where `inflated` tells whether all image data of the
+    /// frame was decompressed, so that data past its end can be detected.
+    #[cfg(feature = "parallel")]
+    fn finish_decoding_inflated(&mut self, inflated: bool) -> Result<(), DecodingError> {
+        block_on(|cx| self.poll_finish_decoding_inflated(cx, inflated))
     }
 
     /// Returns the next processed row of the image (discarding [`InterlaceInfo`]).
@@ -520,22 +1128,8 @@ impl<R: BufRead + Seek> Reader<R> {
     ///
     /// See also [`Reader::read_row`], which reads into a caller-provided buffer.
     pub fn next_interlaced_row(&mut self) -> Result<Option<InterlacedRow<'_>>, DecodingError> {
-        let mut output_buffer = mem::take(&mut self.scratch_buffer);
-        let max_line_size = self
-            .output_line_size(self.info().width)
-            .ok_or(DecodingError::LimitsExceeded)?;
-        output_buffer.resize(max_line_size, 0u8);
-        let result = self.read_row(&mut output_buffer);
-        self.scratch_buffer = output_buffer;
-        result.map(move |option| {
-            option.map(move |interlace| {
-                let output_line_size = self.output_line_size_for_interlace_info(&interlace);
-                InterlacedRow {
-                    data: &self.scratch_buffer[..output_line_size],
-                    interlace,
-                }
-            })
-        })
+        let interlace = block_on(|cx| self.poll_next_interlaced_row(cx))?;
+        Ok(interlace.map(|interlace| self.scratch_row(interlace)))
     }
 
     /// Reads the next row of the image into the provided `output_buffer`.
@@ -550,10 +1144,281 @@ impl<R: BufRead + Seek> Reader<R> {
         &mut self,
         output_buffer: &mut [u8],
     ) -> Result<Option<InterlaceInfo>, DecodingError> {
+        block_on(|cx| self.poll_read_row(cx, output_buffer))
+    }
+
+    /// Read the rest of the image and chunks and finish up, including text chunks or others
+    /// This will discard the rest of the image if the image is not read already with [`Reader::next_frame`], [`Reader::next_row`] or [`Reader::next_interlaced_row`]
+    pub fn finish(&mut self) -> Result<(), DecodingError> {
+        block_on(|cx| self.poll_finish(cx))
+    }
+
+    /// Unfilter the next raw interlaced row into `self.unfiltering_buffer`.
+    fn next_raw_interlaced_row(&mut self, rowlen: usize) -> Result<(), DecodingError> {
+        block_on(|cx| self.poll_next_raw_interlaced_row(cx, rowlen))
+    }
+}
+
+impl<R> Reader<R> {
+    /// Get information on the image.
+    ///
+    /// The structure will change as new frames of an animated image are decoded.
+    pub fn info(&self) -> &Info<'static> {
+        self.decoder.info().unwrap()
+    }
+
+    /// The deviations from the specification that were tolerated so far, in input order.
+    ///
+    /// The decoder skips or ignores some invalid data instead of failing, like libpng does. Each
+    /// such case is recorded here with the chunk and the reason, so it can be logged or counted.
+    /// In strict mode, see [`DecodeOptions::set_strict`], they are errors instead.
+    ///
+    /// Only the first 256 warnings are kept. Checksums that are not verified, see
+    /// [`DecodeOptions::set_ignore_crc`] and [`DecodeOptions::set_ignore_adler32`], can't cause
+    /// warnings.
+    pub fn warnings(&self) -> &[DecodeWarning] {
+        self.decoder.warnings()
+    }
+
+    /// Returns the color type and the number of bits per sample
+    /// of the data returned by [`Reader::next_row`] and [`Reader::next_frame`].
+    pub fn output_color_type(&self) -> (ColorType, BitDepth) {
+        output_color_type(self.info(), self.transform)
+    }
+
+    /// Return the number of bytes required to hold a deinterlaced image frame that is decoded
+    /// using the given input transformations.
+    ///
+    /// Returns `None` if the output buffer does not fit into the memory space of the machine,
+    /// otherwise returns the byte length in `Some`. The length is smaller than [`isize::MAX`].
+    ///
+    /// With [`Decoder::set_downscale`] this is the size of a scaled frame.
+    pub fn output_buffer_size(&self) -> Option<usize> {
+        output_buffer_size(self.info(), self.transform, self.downscale)
+    }
+
+    /// Returns the number of bytes required to hold a deinterlaced row.
+    pub(crate) fn unguarded_output_line_size(&self, width: u32) -> usize {
+        unguarded_output_line_size(self.info(), self.transform, width)
+    }
+
+    /// Returns the number of bytes required to hold a deinterlaced row.
+    ///
+    /// Returns `None` if the output buffer does not fit into the memory space of the machine,
+    /// otherwise returns the byte length in `Some`. The length is smaller than [`isize::MAX`].
+    pub fn output_line_size(&self, width: u32) -> Option<usize> {
+        output_line_size(self.info(), self.transform, width)
+    }
+
+    fn output_line_size_for_interlace_info(&self, interlace: &InterlaceInfo) -> usize {
+        let width = match interlace {
+            InterlaceInfo::Adam7(Adam7Info { samples: width, .. }) => *width,
+            InterlaceInfo::Null(_) => self.subframe.width,
+        };
+        self.unguarded_output_line_size(width)
+    }
+
+    /// Returns the row that `poll_next_interlaced_row` left in the scratch buffer.
+    fn scratch_row(&self, interlace: InterlaceInfo) -> InterlacedRow<'_> {
+        let output_line_size = self.output_line_size_for_interlace_info(&interlace);
+        InterlacedRow {
+            data: &self.scratch_buffer[..output_line_size],
+            interlace,
+        }
+    }
+
+    fn mark_subframe_as_consumed_and_flushed(&mut self) {
+        assert!(self.remaining_frames > 0);
+        self.remaining_frames -= 1;
+
+        self.subframe.consumed_and_flushed = true;
+    }
+}
+
+/// The state machine shared by [`Reader`] and the async reader. Each operation continues where it
+/// stopped when it is polled again after `Poll::Pending`.
+impl<R: Input> Reader<R> {
+    /// Polls [`Reader::next_frame_info`].
+    fn poll_next_frame_info(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), DecodingError>> {
+        let remaining_frames = if self.subframe.consumed_and_flushed {
+            self.remaining_frames
+        } else {
+            // One remaining frame will be consumed by the `finish_decoding` call below.
+            self.remaining_frames - 1
+        };
+        if remaining_frames == 0 {
+            return Poll::Ready(Err(DecodingError::Parameter(
+                ParameterErrorKind::PolledAfterEndOfImage.into(),
+            )));
+        }
+
+        if !self.subframe.consumed_and_flushed {
+            self.subframe.current_interlace_info = None;
+            ready!(self.poll_finish_decoding(cx))?;
+        }
+        self.poll_read_until_image_data(cx)
+    }
+
+    /// Polls [`Reader::read_until_image_data`].
+    fn poll_read_until_image_data(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), DecodingError>> {
+        ready!(self.decoder.poll_read_until_image_data(cx))?;
+
+        self.subframe = SubframeInfo::new(self.info());
+        self.bpp = self.info().bpp_in_prediction();
+
+        let frame_bytes = self.subframe.frame_bytes(self.info());
+        self.unfiltering_buffer.start_frame(frame_bytes);
+
+        // Allocate output buffer.
+        let buflen = self.unguarded_output_line_size(self.subframe.width);
+        self.decoder.reserve_bytes(buflen)?;
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Advances to the image data of the next frame, unless its rows are being read already, and
+    /// checks that `buf_len` bytes can hold the output of [`Reader::next_frame`].
+    fn poll_start_frame(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf_len: usize,
+    ) -> Poll<Result<(), DecodingError>> {
+        if self.remaining_frames == 0 {
+            return Poll::Ready(Err(DecodingError::Parameter(
+                ParameterErrorKind::PolledAfterEndOfImage.into(),
+            )));
+        } else if self.subframe.consumed_and_flushed {
+            // Advance until the next `fdAT`
+            // (along the way we should encounter the fcTL for this frame).
+            ready!(self.poll_read_until_image_data(cx))?;
+        }
+
+        // Note that we only check if the buffer size calculation holds in a call to decoding the
+        // frame. Consequently, we can represent the `Info` and frameless decoding even when the
+        // target architecture's address space is too small for a frame. However reading the actual
+        let required_len = self
+            .output_buffer_size()
+            .ok_or(DecodingError::LimitsExceeded)?;
+
+        if buf_len < required_len {
+            return Poll::Ready(Err(DecodingError::Parameter(
+                ParameterErrorKind::ImageBufferSize {
+                    expected: required_len,
+                    actual: buf_len,
+                }
+                .into(),
+            )));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Decodes the remaining rows of the frame started by `poll_start_frame` into `buf`, without
+    /// scaling it down.
+    fn poll_next_frame_rows(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<OutputInfo, DecodingError>> {
+        let (color_type, bit_depth) = self.output_color_type();
+        let output_info = OutputInfo {
+            width: self.subframe.width,
+            height: self.subframe.height,
+            color_type,
+            bit_depth,
+            line_size: self.unguarded_output_line_size(self.subframe.width),
+        };
+
+        if self.info().interlaced {
+            let stride = self.unguarded_output_line_size(self.info().width);
+            let bits_pp = output_bits_per_pixel(self.info(), self.transform) as u8;
+            let expand = crate::adam7::expand_pass;
+
+            while let Some(interlace) = ready!(self.poll_next_interlaced_row(cx))? {
+                let row = self.scratch_row(interlace).data;
+                // `unwrap` won't panic, because we checked `self.info().interlaced` above.
+                let adam7info = interlace.get_adam7_info().unwrap();
+                expand(buf, stride, row, adam7info, bits_pp);
+            }
+        } else {
+            let current_interlace_info = self.subframe.current_interlace_info.as_ref();
+            let already_done_rows = current_interlace_info
+                .map(|info| info.line_number())
+                .unwrap_or(self.subframe.height);
+
+            for row in buf
+                .chunks_exact_mut(output_info.line_size)
+                .take(self.subframe.height as usize)
+                .skip(already_done_rows as usize)
+            {
+                ready!(self.poll_next_interlaced_row_impl(cx, self.subframe.rowlen, row))?;
+            }
+        }
+
+        // Advance over the rest of data for this (sub-)frame.
+        ready!(self.poll_finish_decoding(cx))?;
+
+        Poll::Ready(Ok(output_info))
+    }
+
+    /// Polls [`Reader::finish_decoding`].
+    fn poll_finish_decoding(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), DecodingError>> {
+        let inflated = self.unfiltering_buffer.remaining_bytes() == 0;
+        self.poll_finish_decoding_inflated(cx, inflated)
+    }
+
+    /// Like `poll_finish_decoding`, where `inflated` tells whether all image data of the frame
+    /// was decompressed, so that data past its end can be detected.
+    fn poll_finish_decoding_inflated(
+        &mut self,
+        cx: &mut Context<'_>,
+        inflated: bool,
+    ) -> Poll<Result<(), DecodingError>> {
+        // Double-check that all rows of this frame have been decoded (i.e. that the potential
+        // `finish_decoding` call below won't be discarding any data).
+        assert!(self.subframe.current_interlace_info.is_none());
+
+        // Discard the remaining data in the current sequence of `IDAT` or `fdAT` chunks.
+        if !self.subframe.consumed_and_flushed {
+            if inflated {
+                ready!(self.decoder.poll_finish_decoding_complete_image_data(cx))?;
+            } else {
+                ready!(self.decoder.poll_finish_decoding_image_data(cx))?;
+            }
+            self.mark_subframe_as_consumed_and_flushed();
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Polls [`Reader::next_interlaced_row`], which reads the row into the scratch buffer.
+    fn poll_next_interlaced_row(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<InterlaceInfo>, DecodingError>> {
+        let mut output_buffer = mem::take(&mut self.scratch_buffer);
+        let max_line_size = self
+            .output_line_size(self.info().width)
+            .ok_or(DecodingError::LimitsExceeded)?;
+        output_buffer.resize(max_line_size, 0u8);
+        let result = self.poll_read_row(cx, &mut output_buffer);
+        self.scratch_buffer = output_buffer;
+        result
+    }
+
+    /// Polls [`Reader::read_row`].
+    fn poll_read_row(
+        &mut self,
+        cx: &mut Context<'_>,
+        output_buffer: &mut [u8],
+    ) -> Poll<Result<Option<InterlaceInfo>, DecodingError>> {
         let interlace = match self.subframe.current_interlace_info.as_ref() {
             None => {
-                self.finish_decoding()?;
-                return Ok(None);
+                ready!(self.poll_finish_decoding(cx))?;
+                return Poll::Ready(Ok(None));
             }
             Some(interlace) => *interlace,
         };
@@ -570,144 +1435,73 @@ impl<R: BufRead + Seek> Reader<R> {
         let output_line_size = self.output_line_size_for_interlace_info(&interlace);
         let output_buffer = &mut output_buffer[..output_line_size];
 
-        self.next_interlaced_row_impl(rowlen, output_buffer)?;
-
-        Ok(Some(interlace))
-    }
+        ready!(self.poll_next_interlaced_row_impl(cx, rowlen, output_buffer))?;
 
-    fn output_line_size_for_interlace_info(&self, interlace: &InterlaceInfo) -> usize {
-        let width = match interlace {
-            InterlaceInfo::Adam7(Adam7Info { samples: width, .. }) => *width,
-            InterlaceInfo::Null(_) => self.subframe.width,
-        };
-        self.unguarded_output_line_size(width)
+        Poll::Ready(Ok(Some(interlace)))
     }
 
-    /// Read the rest of the image and chunks and finish up, including text chunks or others
-    /// This will discard the rest of the image if the image is not read already with [`Reader::next_frame`], [`Reader::next_row`] or [`Reader::next_interlaced_row`]
-    pub fn finish(&mut self) -> Result<(), DecodingError> {
+    /// Polls [`Reader::finish`].
+    fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), DecodingError>> {
         if self.finished {
-            return Err(DecodingError::Parameter(
+            return Poll::Ready(Err(DecodingError::Parameter(
                 ParameterErrorKind::PolledAfterEndOfImage.into(),
-            ));
+            )));
         }
 
         self.remaining_frames = 0;
-        self.decoder.read_until_end_of_input()?;
+        ready!(self.decoder.poll_read_until_end_of_input(cx))?;
 
         self.finished = true;
-        Ok(())
+        Poll::Ready(Ok(()))
     }
 
     /// Fetch the next interlaced row and filter it according to our own transformations.
-    fn next_interlaced_row_impl(
+    fn poll_next_interlaced_row_impl(
         &mut self,
+        cx: &mut Context<'_>,
         rowlen: usize,
         output_buffer: &mut [u8],
-    ) -> Result<(), DecodingError> {
-        self.next_raw_interlaced_row(rowlen)?;
+    ) -> Poll<Result<(), DecodingError>> {
+        ready!(self.poll_next_raw_interlaced_row(cx, rowlen))?;
         let row = self.unfiltering_buffer.prev_row();
         assert_eq!(row.len(), rowlen - 1);
 
         // Apply transformations and write resulting data to buffer.
         let transform_fn = {
             if self.transform_fn.is_none() {
-                self.transform_fn = Some(create_transform_fn(self.info(), self.transform)?);
+                self.transform_fn = Some(create_transform_fn(
+                    self.info(),
+                    self.transform,
+                    self.display_gamma,
+                )?);
             }
             self.transform_fn.as_deref().unwrap()
         };
         transform_fn(row, output_buffer, self.info());
 
         self.subframe.current_interlace_info = self.subframe.interlace_info_iter.next();
-        Ok(())
-    }
-
-    /// Returns the color type and the number of bits per sample
-    /// of the data returned by [`Reader::next_row`] and [`Reader::next_frame`].
-    pub fn output_color_type(&self) -> (ColorType, BitDepth) {
-        use crate::common::ColorType::*;
-        let t = self.transform;
-        let info = self.info();
-        if t == Transformations::IDENTITY {
-            (info.color_type, info.bit_depth)
-        } else {
-            let bits = match info.bit_depth as u8 {
-                16 if t.intersects(Transformations::STRIP_16) => 8,
-                n if n < 8
-                    && (t.contains(Transformations::EXPAND)
-                        || t.contains(Transformations::ALPHA)) =>
-                {
-                    8
-                }
-                n => n,
-            };
-            let color_type =
-                if t.contains(Transformations::EXPAND) || t.contains(Transformations::ALPHA) {
-                    let has_trns = info.trns.is_some() || t.contains(Transformations::ALPHA);
-                    match info.color_type {
-                        Grayscale if has_trns => GrayscaleAlpha,
-                        Rgb if has_trns => Rgba,
-                        Indexed if has_trns => Rgba,
-                        Indexed => Rgb,
-                        ct => ct,
-                    }
-                } else {
-                    info.color_type
-                };
-            (color_type, BitDepth::from_u8(bits).unwrap())
-        }
-    }
-
-    /// Return the number of bytes required to hold a deinterlaced image frame that is decoded
-    /// using the given input transformations.
-    ///
-    /// Returns `None` if the output buffer does not fit into the memory space of the machine,
-    /// otherwise returns the byte length in `Some`. The length is smaller than [`isize::MAX`].
-    pub fn output_buffer_size(&self) -> Option<usize> {
-        let (width, height) = self.info().size();
-        let (color, depth) = self.output_color_type();
-        // The subtraction should always work, but we do this for consistency. Also note that by
-        // calling `checked_raw_row_length` the row buffer is guaranteed to work whereas if we
-        // ran other function that didn't include the filter byte that could later fail on an image
-        // that is `1xN`...
-        let linelen = color.checked_raw_row_length(depth, width)?.checked_sub(1)?;
-        let height = usize::try_from(height).ok()?;
-        let imglen = linelen.checked_mul(height)?;
-        // Ensure that it fits into address space not only `usize` to allocate.
-        (imglen <= isize::MAX as usize).then_some(imglen)
-    }
-
-    /// Returns the number of bytes required to hold a deinterlaced row.
-    pub(crate) fn unguarded_output_line_size(&self, width: u32) -> usize {
-        let (color, depth) = self.output_color_type();
-        color.raw_row_length_from_width(depth, width) - 1
-    }
-
-    /// Returns the number of bytes required to hold a deinterlaced row.
-    ///
-    /// Returns `None` if the output buffer does not fit into the memory space of the machine,
-    /// otherwise returns the byte length in `Some`. The length is smaller than [`isize::MAX`].
-    pub fn output_line_size(&self, width: u32) -> Option<usize> {
-        let (color, depth) = self.output_color_type();
-        let length = color.checked_raw_row_length(depth, width)?.checked_sub(1)?;
-        // Ensure that it fits into address space not only `usize` to allocate.
-        (length <= isize::MAX as usize).then_some(length)
+        Poll::Ready(Ok(()))
     }
 
     /// Unfilter the next raw interlaced row into `self.unfiltering_buffer`.
-    fn next_raw_interlaced_row(&mut self, rowlen: usize) -> Result<(), DecodingError> {
+    fn poll_next_raw_interlaced_row(
+        &mut self,
+        cx: &mut Context<'_>,
+        rowlen: usize,
+    ) -> Poll<Result<(), DecodingError>> {
         // Read image data until we have at least one full row (but possibly more than one).
         while self.unfiltering_buffer.curr_row_len() < rowlen {
             if self.subframe.consumed_and_flushed {
-                return Err(DecodingError::Format(
+                return Poll::Ready(Err(DecodingError::Format(
                     FormatErrorInner::NoMoreImageData.into(),
-                ));
+                )));
             }
 
             assert!(self.unfiltering_buffer.remaining_bytes() > 0);
-            let completion_status = self
-                .unfiltering_buffer
-                .with_unfilled_buffer(|buffer| self.decoder.decode_image_data(Some(buffer)))?;
+            let completion_status =
+                ready!(self.unfiltering_buffer.with_unfilled_buffer(|buffer| self
+                    .decoder
+                    .poll_decode_image_data(cx, Some(buffer))))?;
 
             match completion_status {
                 ImageDataCompletionStatus::ExpectingMoreData => (),
@@ -715,7 +1509,39 @@ impl<R: BufRead + Seek> Reader<R> {
             }
         }
 
-        self.unfiltering_buffer.unfilter_curr_row(rowlen, self.bpp)
+        Poll::Ready(self.unfiltering_buffer.unfilter_curr_row(rowlen, self.bpp))
+    }
+}
+
+impl<R: BufRead + Seek> Reader<R> {
+    /// Moves the input to a frame of the animation, so that the next call to
+    /// [`Reader::next_frame`] decodes it.
+    ///
+    /// The index must have been built from the same input with [`FrameIndex::scan`]. Like
+    /// [`Reader::next_frame`] this returns the raw subframe, use
+    /// [`AnimationCompositor::seek_to_frame`] for composed frames. The frames after it can be
+    /// decoded as usual.
+    pub fn seek_to_frame(&mut self, index: &FrameIndex, frame: usize) -> Result<(), DecodingError> {
+        let Some(indexed) = index.get(frame) else {
+            return Err(DecodingError::Parameter(
+                ParameterErrorKind::FrameOutOfBounds {
+                    frame,
+                    num_frames: index.len(),
+                }
+                .into(),
+            ));
+        };
+
+        self.decoder.seek_to_frame(
+            indexed.control_offset,
+            indexed.control.sequence_number,
+            indexed.in_idat,
+        )?;
+        self.subframe = SubframeInfo::not_yet_init();
+        self.subframe.consumed_and_flushed = true;
+        self.remaining_frames = index.len() - frame;
+        self.finished = false;
+        Ok(())
     }
 }
 
@@ -751,4 +1577,131 @@ impl SubframeInfo {
             consumed_and_flushed: false,
         }
     }
+
+    /// Returns the number of bytes of filtered image data in the subframe.
+    fn frame_bytes(&self, info: &Info) -> u64 {
+        if info.interlaced {
+            let mut bytes = 0u64;
+            for pass in crate::adam7::PassConstants::PASSES {
+                bytes += info.raw_row_length_from_width(pass.count_samples(self.width)) as u64
+                    * pass.count_lines(self.height) as u64;
+            }
+            bytes
+        } else {
+            (self.rowlen as u64) * self.height as u64
+        }
+    }
+}
+
+/// Returns the number of frames in the image, including a default image that is not part of
+/// the animation.
+fn frame_count(info: &Info) -> usize {
+    match info.animation_control.as_ref() {
+        None => 1, // No `acTL` => only expecting `IDAT` frame.
+        Some(animation) => {
+            let mut num_frames = animation.num_frames as usize;
+            if info.frame_control.is_none() {
+                // No `fcTL` before `IDAT` => `IDAT` is not part of the animation, but
+                // represents an *extra*, default frame for non-APNG-aware decoders.
+                num_frames += 1;
+            }
+            num_frames
+        }
+    }
+}
+
+/// Reads the native endian `f32` samples of [`Transformations::F32`].
+fn f32_samples(data: &[u8], _: BitDepth, buf: &mut [f32]) {
+    for (sample, bytes) in buf.iter_mut().zip(data.chunks_exact(4)) {
+        *sample = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+}
+
+/// Returns the color type and the number of bits per sample of decoded rows.
+fn output_color_type(info: &Info, transform: Transformations) -> (ColorType, BitDepth) {
+    let (color_type, bit_depth) = expanded_color_type(info, transform);
+    if !transform.contains(Transformations::FILLER) || (bit_depth as u8) < 8 {
+        return (color_type, bit_depth);
+    }
+    // The filler takes the place of an alpha sample.
+    let color_type = match color_type {
+        ColorType::Grayscale => ColorType::GrayscaleAlpha,
+        ColorType::Rgb => ColorType::Rgba,
+        color_type => color_type,
+    };
+    (color_type, bit_depth)
+}
+
+/// Returns the color type and bit depth of the samples before the channel layout is changed by
+/// `BGR`, `SWAP_ALPHA`, `INVERT_ALPHA` and `FILLER`.
+fn expanded_color_type(info: &Info, transform: Transformations) -> (ColorType, BitDepth) {
+    use crate::common::ColorType::*;
+    let t = transform;
+    if t == Transformations::IDENTITY {
+        (info.color_type, info.bit_depth)
+    } else {
+        let bits = match info.bit_depth as u8 {
+            16 if t.intersects(Transformations::STRIP_16) => 8,
+            n if n < 8 && t.intersects(Transformations::EXPANSION) => 8,
+            n => n,
+        };
+        let color_type = if t.intersects(Transformations::EXPANSION) {
+            let has_trns = info.trns.is_some() || t.contains(Transformations::ALPHA);
+            match info.color_type {
+                Grayscale if has_trns => GrayscaleAlpha,
+                Rgb if has_trns => Rgba,
+                Indexed if has_trns => Rgba,
+                Indexed => Rgb,
+                ct => ct,
+            }
+        } else {
+            info.color_type
+        };
+        (color_type, BitDepth::from_u8(bits).unwrap())
+    }
+}
+
+/// Returns the number of bits per pixel of decoded rows.
+///
+/// Samples take 32 bits with [`Transformations::F32`], whatever their precision.
+fn output_bits_per_pixel(info: &Info, transform: Transformations) -> usize {
+    let (color_type, bit_depth) = output_color_type(info, transform);
+    let sample_bits = if transform.intersects(Transformations::F32 | Transformations::LINEAR) {
+        32
+    } else {
+        bit_depth as usize
+    };
+    color_type.samples() * sample_bits
+}
+
+/// Returns the number of bytes of a decoded row of `width` pixels, if it fits into a `usize`.
+fn checked_output_line_size(info: &Info, transform: Transformations, width: u32) -> Option<usize> {
+    // No overflow can occur in 64 bits, we multiply 32-bit with at most 128 bits per pixel.
+    let bits = u64::from(width) * output_bits_per_pixel(info, transform) as u64;
+    usize::try_from(bits.div_ceil(8)).ok()
+}
+
+/// Returns the number of bytes required to hold a deinterlaced image frame.
+fn output_buffer_size(
+    info: &Info,
+    transform: Transformations,
+    downscale: Downscale,
+) -> Option<usize> {
+    let (width, height) = info.size();
+    let (width, height) = (downscale.apply(width), downscale.apply(height));
+    let linelen = checked_output_line_size(info, transform, width)?;
+    let height = usize::try_from(height).ok()?;
+    let imglen = linelen.checked_mul(height)?;
+    // Ensure that it fits into address space not only `usize` to allocate.
+    (imglen <= isize::MAX as usize).then_some(imglen)
+}
+
+fn unguarded_output_line_size(info: &Info, transform: Transformations, width: u32) -> usize {
+    (width as usize * output_bits_per_pixel(info, transform)).div_ceil(8)
+}
+
+fn output_line_size(info: &Info, transform: Transformations, width: u32) -> Option<usize> {
+    let length = checked_output_line_size(info, transform, width)?;
+    // Ensure that it fits into address space not only `usize` to allocate.
+    (length <= isize::MAX as usize).then_some(length)
 }
@@ -0,0 +1,215 @@
+//! Unfiltering and transforming a whole inflated frame on multiple threads.
+//!
+//! Unfiltering is sequential in general, because the `Up`, `Avg` and `Paeth` filters refer to the
+//! previous row. Rows using `NoFilter` or `Sub` do not, so the frame is split into bands that
+//! start at such rows and are unfiltered independently. Transforming rows into the output has no
+//! dependencies at all and is always split evenly.
+use std::thread;
+
+use super::stream::{DecodingError, FormatErrorInner};
+use crate::common::BytesPerPixel;
+use crate::filter::{unfilter, RowFilter};
+
+/// Frames smaller than this per thread are not worth the overhead of spawning threads.
+pub(super) const MIN_BAND_SIZE: usize = 256 * 1024;
+
+/// Unfilters the rows in `data`, each `rowlen` bytes long including the filter type byte, and
+/// transforms them into lines of `line_size` bytes in `output`, using up to `threads` threads.
+pub(super) fn unfilter_and_transform(
+    data: &mut [u8],
+    rowlen: usize,
+    bpp: BytesPerPixel,
+    output: &mut [u8],
+    line_size: usize,
+    transform: &(dyn Fn(&[u8], &mut [u8]) + Sync),
+    threads: usize,
+) -> Result<(), DecodingError> {
+    let rows = data.len() / rowlen;
+    let mut independent = Vec::new();
+    for (idx, row) in data.chunks_exact(rowlen).enumerate() {
+        let filter = RowFilter::from_u8(row[0]).ok_or(DecodingError::Format(
+            FormatErrorInner::UnknownFilterMethod(row[0]).into(),
+        ))?;
+        if idx == 0 || matches!(filter, RowFilter::NoFilter | RowFilter::Sub) {
+            independent.push(idx);
+        }
+    }
+
+    // Greedily cut at the first independent row after each even share of the frame.
+    let target = rows.div_ceil(threads);
+    let mut cuts = vec![0];
+    for &idx in &independent[1..] {
+        if idx - cuts.last().unwrap() >= target {
+            cuts.push(idx);
+        }
+    }
+
+    thread::scope(|scope| {
+        let mut rest = &mut *data;
+        let mut handles = Vec::new();
+        for (band, &start) in cuts.iter().enumerate() {
+            let end = cuts.get(band + 1).copied().unwrap_or(rows);
+            let (band, tail) = rest.split_at_mut((end - start) * rowlen);
+            rest = tail;
+            handles.push(scope.spawn(move || unfilter_band(band, rowlen, bpp)));
+        }
+        for handle in handles {
+            handle
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        }
+    });
+
+    let rows_per_thread = rows.div_ceil(threads).max(1);
+    let data = &*data;
+    thread::scope(|scope| {
+        let bands = data
+            .chunks(rows_per_thread * rowlen)
+            .zip(output.chunks_mut(rows_per_thread * line_size));
+        let handles: Vec<_> = bands
+            .map(|(band, output)| {
+                scope.spawn(move || {
+                    for (row, line) in band
+                        .chunks_exact(rowlen)
+                        .zip(output.chunks_exact_mut(line_size))
+                    {
+                        transform(&row[1..], line);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        }
+    });
+
+    Ok(())
+}
+
+/// Unfilters a band whose first row does not depend on the row before it.
+fn unfilter_band(band: &mut [u8], rowlen: usize, bpp: BytesPerPixel) {
+    let mut prev_start = None;
+    for start in (0..band.len()).step_by(rowlen) {
+        let (prev, row) = band.split_at_mut(start);
+        let prev: &[u8] = match prev_start {
+            Some(prev_start) => &prev[prev_start..],
+            None => &[],
+        };
+        // Filter types were validated before splitting the frame into bands.
+        let filter = RowFilter::from_u8(row[0]).unwrap();
+        unfilter(filter, bpp, prev, &mut row[1..rowlen]);
+        prev_start = Some(start + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{ColorType, Decoder, DeflateCompression, Encoder, Filter, Limits, Transformations};
+
+    fn decode_both(png: &[u8], transform: Transformations) -> (Vec<u8>, Vec<u8>) {
+        let mut decoder = Decoder::new(Cursor::new(png));
+        decoder.set_transformations(transform);
+        let mut reader = decoder.read_info().unwrap();
+        let mut expected = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut expected).unwrap();
+
+        let mut decoder = Decoder::new(Cursor::new(png));
+        decoder.set_transformations(transform);
+        let mut reader = decoder.read_info().unwrap();
+        let mut actual = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame_parallel_impl(&mut actual, 4).unwrap();
+        reader.finish().unwrap();
+        (expected, actual)
+    }
+
+    #[test]
+    fn matches_sequential_decoding_pngsuite() {
+        for path in glob::glob("tests/pngsuite/*.png")
+            .unwrap()
+            .map(|r| r.unwrap())
+        {
+            if path.file_name().unwrap().to_str().unwrap().starts_with('x') {
+                // x* files are expected to fail to decode
+                continue;
+            }
+            let png = std::fs::read(&path).unwrap();
+            for transform in [
+                Transformations::IDENTITY,
+                Transformations::normalize_to_color8(),
+            ] {
+                let (expected, actual) = decode_both(&png, transform);
+                assert_eq!(expected, actual, "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn matches_sequential_decoding_filters() {
+        let (width, height) = (97, 301);
+        let pixels: Vec<u8> = (0..width * height * 3)
+            .map(|i| ((i * 13) ^ (i / 211)) as u8)
+            .collect();
+        for filter in [
+            Filter::NoFilter,
+            Filter::Sub,
+            Filter::Paeth,
+            Filter::Adaptive,
+        ] {
+            let mut png = Vec::new();
+            let mut encoder = Encoder::new(&mut png, width as u32, height as u32);
+            encoder.set_color(ColorType::Rgb);
+            encoder.set_filter(filter);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&pixels).unwrap();
+            writer.finish().unwrap();
+
+            let (expected, actual) = decode_both(&png, Transformations::IDENTITY);
+            assert_eq!(expected, pixels);
+            assert_eq!(actual, pixels, "{filter:?}");
+        }
+    }
+
+    #[test]
+    fn truncated_image_data_is_an_error() {
+        let (width, height) = (64, 64);
+        let pixels: Vec<u8> = (0..width * height).map(|i| (i * 7) as u8).collect();
+        let mut png = Vec::new();
+        let mut encoder = Encoder::new(&mut png, width as u32, height as u32);
+        encoder.set_color(ColorType::Grayscale);
+        encoder.set_deflate_compression(DeflateCompression::NoCompression);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+        png.truncate(png.len() / 2);
+
+        let mut reader = Decoder::new(Cursor::new(&*png)).read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        assert!(reader.next_frame_parallel_impl(&mut buf, 4).is_err());
+    }
+
+    #[test]
+    fn frames_over_the_limits_are_decoded_sequentially() {
+        let (width, height) = (97, 301);
+        let pixels: Vec<u8> = (0..width * height * 3).map(|i| (i * 11) as u8).collect();
+        let mut png = Vec::new();
+        let mut encoder = Encoder::new(&mut png, width as u32, height as u32);
+        encoder.set_color(ColorType::Rgb);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+
+        // The inflated frame alone is larger than the limits.
+        let limits = Limits { bytes: 32 * 1024 };
+        let mut reader = Decoder::new_with_limits(Cursor::new(&*png), limits)
+            .read_info()
+            .unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame_parallel_impl(&mut buf, 4).unwrap();
+        reader.finish().unwrap();
+        assert_eq!(buf, pixels);
+    }
+}
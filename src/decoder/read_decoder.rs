@@ -1,31 +1,82 @@
-use super::stream::{DecodeOptions, Decoded, DecodingError, FormatErrorInner, StreamingDecoder};
-use super::zlib::UnfilterBuf;
+use super::stream::{
+    BorrowedChunks, DecodeOptions, DecodeWarning, Decoded, DecodingError, FormatErrorInner,
+    StreamingDecoder,
+};
+use super::zlib::{UnfilterBuf, UnfilterRegion};
 use super::Limits;
 
-use std::io::{BufRead, ErrorKind, Read, Seek};
+use std::io::{self, BufRead, ErrorKind, Seek, SeekFrom};
+use std::sync::{Arc, OnceLock};
+use std::task::{ready, Context, Poll, Wake, Waker};
 
 use crate::chunk;
 use crate::common::Info;
 
-/// Helper for encapsulating reading input from `Read` and feeding it into a `StreamingDecoder`
-/// while hiding low-level `Decoded` events and only exposing a few high-level reading operations
-/// like:
+/// The input of a [`ReadDecoder`], which may or may not block on I/O.
 ///
-/// * `read_header_info` - reading until `IHDR` chunk
-/// * `read_until_image_data` - reading until `IDAT` / `fdAT` sequence
-/// * `decode_image_data` - reading from `IDAT` / `fdAT` sequence into `Vec<u8>`
-/// * `finish_decoding_image_data()` - discarding remaining data from `IDAT` / `fdAT` sequence
-/// * `read_until_end_of_input()` - reading until `IEND` chunk
-pub(crate) struct ReadDecoder<R: Read> {
+/// Every `BufRead` is an `Input` that is always ready. The `async` feature adds one for
+/// `AsyncBufRead`, so that the same decoding state machine drives both `Reader` and
+/// `AsyncReader`.
+pub trait Input {
+    /// Returns the buffered input, like [`BufRead::fill_buf`].
+    fn poll_fill_buf(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>>;
+
+    /// Marks `amt` bytes of the buffered input as read, like [`BufRead::consume`].
+    fn consume(&mut self, amt: usize);
+}
+
+impl<R: BufRead> Input for R {
+    fn poll_fill_buf(&mut self, _: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Poll::Ready(self.fill_buf())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        BufRead::consume(self, amt);
+    }
+}
+
+/// Runs one of the `poll_*` operations on a `BufRead` input, which never returns
+/// `Poll::Pending`, to completion.
+pub(crate) fn block_on<T>(poll: impl FnOnce(&mut Context<'_>) -> Poll<T>) -> T {
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    static WAKER: OnceLock<Waker> = OnceLock::new();
+    let waker = WAKER.get_or_init(|| Arc::new(NoopWaker).into());
+    match poll(&mut Context::from_waker(waker)) {
+        Poll::Ready(value) => value,
+        Poll::Pending => unreachable!("`BufRead` input is always ready"),
+    }
+}
+
+/// Helper for encapsulating reading input from an [`Input`] and feeding it into a
+/// `StreamingDecoder` while hiding low-level `Decoded` events and only exposing a few high-level
+/// reading operations like:
+///
+/// * `poll_read_header_info` - reading until `IHDR` chunk
+/// * `poll_read_until_image_data` - reading until `IDAT` / `fdAT` sequence
+/// * `poll_decode_image_data` - reading from `IDAT` / `fdAT` sequence into `Vec<u8>`
+/// * `poll_finish_decoding_image_data()` - discarding remaining data from `IDAT` / `fdAT` sequence
+/// * `poll_read_until_end_of_input()` - reading until `IEND` chunk
+///
+/// Each operation can be polled again after it returned `Poll::Pending` and then continues where
+/// it stopped, because the input is only consumed once the `StreamingDecoder` has processed it.
+pub(crate) struct ReadDecoder<R> {
     reader: R,
     decoder: StreamingDecoder,
+    /// Whether `poll_read_until_end_of_input` has seen the `IEND` chunk.
+    reached_end: bool,
 }
 
-impl<R: BufRead + Seek> ReadDecoder<R> {
+impl<R> ReadDecoder<R> {
     pub fn new(r: R) -> Self {
         Self {
             reader: r,
             decoder: StreamingDecoder::new(),
+            reached_end: false,
         }
     }
 
@@ -33,7 +84,11 @@ impl<R: BufRead + Seek> ReadDecoder<R> {
         let mut decoder = StreamingDecoder::new_with_options(options);
         decoder.limits = Limits::default();
 
-        Self { reader: r, decoder }
+        Self {
+            reader: r,
+            decoder,
+            reached_end: false,
+        }
     }
 
     pub fn set_limits(&mut self, limits: Limits) {
@@ -44,6 +99,10 @@ impl<R: BufRead + Seek> ReadDecoder<R> {
         self.decoder.limits.reserve_bytes(bytes)
     }
 
+    pub fn release_bytes(&mut self, bytes: usize) {
+        self.decoder.limits.release_bytes(bytes);
+    }
+
     pub fn set_ignore_text_chunk(&mut self, ignore_text_chunk: bool) {
         self.decoder.set_ignore_text_chunk(ignore_text_chunk);
     }
@@ -52,97 +111,192 @@ impl<R: BufRead + Seek> ReadDecoder<R> {
         self.decoder.set_ignore_iccp_chunk(ignore_iccp_chunk);
     }
 
+    pub fn set_keep_unknown_chunks(&mut self, keep_unknown_chunks: bool) {
+        self.decoder.set_keep_unknown_chunks(keep_unknown_chunks);
+    }
+
     pub fn ignore_checksums(&mut self, ignore_checksums: bool) {
         self.decoder.set_ignore_adler32(ignore_checksums);
         self.decoder.set_ignore_crc(ignore_checksums);
     }
 
+    pub fn warnings(&self) -> &[DecodeWarning] {
+        self.decoder.warnings()
+    }
+
+    pub fn info(&self) -> Option<&Info<'static>> {
+        self.decoder.info.as_ref()
+    }
+
+    /// Returns where the chunk payloads that were left in the input are.
+    pub fn borrowed_chunks(&self) -> &BorrowedChunks {
+        &self.decoder.borrowed
+    }
+}
+
+impl<R: Input> ReadDecoder<R> {
     /// Returns the next decoded chunk. If the chunk is an ImageData chunk, its contents are written
     /// into image_data.
-    fn decode_next(
+    fn poll_decode_next(
         &mut self,
+        cx: &mut Context<'_>,
         image_data: Option<&mut UnfilterBuf<'_>>,
-    ) -> Result<Decoded, DecodingError> {
-        let (consumed, result) = {
-            let buf = self.reader.fill_buf()?;
-            if buf.is_empty() {
-                return Err(DecodingError::IoError(ErrorKind::UnexpectedEof.into()));
-            }
-            self.decoder.update(buf, image_data)?
-        };
+    ) -> Poll<Result<Decoded, DecodingError>> {
+        let buf = ready!(self.reader.poll_fill_buf(cx))?;
+        if buf.is_empty() {
+            return Poll::Ready(Err(DecodingError::IoError(ErrorKind::UnexpectedEof.into())));
+        }
+        let (consumed, result) = self.decoder.update(buf, image_data)?;
         self.reader.consume(consumed);
-        Ok(result)
+        Poll::Ready(Ok(result))
     }
 
     /// Reads until the end of `IHDR` chunk.
     ///
     /// Prerequisite: None (idempotent).
-    pub fn read_header_info(&mut self) -> Result<&Info<'static>, DecodingError> {
+    pub fn poll_read_header_info(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<&Info<'static>, DecodingError>> {
         while self.info().is_none() {
-            if let Decoded::ChunkComplete(chunk::IEND) = self.decode_next(None)? {
+            if let Decoded::ChunkComplete(chunk::IEND) = ready!(self.poll_decode_next(cx, None))? {
                 unreachable!()
             }
         }
-        Ok(self.info().unwrap())
+        Poll::Ready(Ok(self.info().unwrap()))
     }
 
     /// Reads until the start of the next `IDAT` or `fdAT` chunk.
     ///
     /// Prerequisite: **Not** within `IDAT` / `fdAT` chunk sequence.
-    pub fn read_until_image_data(&mut self) -> Result<(), DecodingError> {
+    pub fn poll_read_until_image_data(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), DecodingError>> {
         loop {
-            match self.decode_next(None)? {
+            match ready!(self.poll_decode_next(cx, None))? {
                 Decoded::ChunkBegin(_, chunk::IDAT) | Decoded::ChunkBegin(_, chunk::fdAT) => break,
                 Decoded::ChunkComplete(chunk::IEND) => {
-                    return Err(DecodingError::Format(
+                    return Poll::Ready(Err(DecodingError::Format(
                         FormatErrorInner::MissingImageData.into(),
-                    ))
+                    )))
                 }
                 // Ignore all other chunk events. Any other chunk may be between IDAT chunks, fdAT
                 // chunks and their control chunks.
                 _ => {}
             }
         }
-        Ok(())
+        Poll::Ready(Ok(()))
     }
 
     /// Reads `image_data` and reports whether there may be additional data afterwards (i.e. if it
     /// is okay to call `decode_image_data` and/or `finish_decoding_image_data` again)..
     ///
     /// Prerequisite: Input is currently positioned within `IDAT` / `fdAT` chunk sequence.
-    pub fn decode_image_data(
+    pub fn poll_decode_image_data(
         &mut self,
+        cx: &mut Context<'_>,
         image_data: Option<&mut UnfilterBuf<'_>>,
-    ) -> Result<ImageDataCompletionStatus, DecodingError> {
-        match self.decode_next(image_data)? {
-            Decoded::ImageData => Ok(ImageDataCompletionStatus::ExpectingMoreData),
-            Decoded::ImageDataFlushed => Ok(ImageDataCompletionStatus::Done),
+    ) -> Poll<Result<ImageDataCompletionStatus, DecodingError>> {
+        match ready!(self.poll_decode_next(cx, image_data))? {
+            Decoded::ImageData => Poll::Ready(Ok(ImageDataCompletionStatus::ExpectingMoreData)),
+            Decoded::ImageDataFlushed => Poll::Ready(Ok(ImageDataCompletionStatus::Done)),
             // Ignore other events that may happen within an `IDAT` / `fdAT` chunks sequence.
-            _ => Ok(ImageDataCompletionStatus::ExpectingMoreData),
+            _ => Poll::Ready(Ok(ImageDataCompletionStatus::ExpectingMoreData)),
         }
     }
 
     /// Consumes and discards the rest of an `IDAT` / `fdAT` chunk sequence.
     ///
     /// Prerequisite: Input is currently positioned within `IDAT` / `fdAT` chunk sequence.
-    pub fn finish_decoding_image_data(&mut self) -> Result<(), DecodingError> {
+    pub fn poll_finish_decoding_image_data(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), DecodingError>> {
+        loop {
+            if let ImageDataCompletionStatus::Done = ready!(self.poll_decode_image_data(cx, None))?
+            {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+
+    /// Consumes the rest of an `IDAT` / `fdAT` chunk sequence after a frame was decompressed
+    /// completely, and checks that it only holds the end of the zlib stream.
+    ///
+    /// The data is decompressed without room for output, so any image data past the end of the
+    /// frame stops the decompressor. That is an error in strict mode and a warning otherwise.
+    ///
+    /// Prerequisite: Input is currently positioned within `IDAT` / `fdAT` chunk sequence.
+    pub fn poll_finish_decoding_complete_image_data(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), DecodingError>> {
+        self.decoder.set_image_data_complete();
+        let mut buffer = Vec::new();
+        let mut region = UnfilterRegion::default();
         loop {
-            if let ImageDataCompletionStatus::Done = self.decode_image_data(None)? {
-                return Ok(());
+            let image_data = &mut region.as_buf(&mut buffer);
+            if let ImageDataCompletionStatus::Done =
+                ready!(self.poll_decode_image_data(cx, Some(image_data)))?
+            {
+                return Poll::Ready(Ok(()));
             }
         }
     }
 
     /// Reads until the `IEND` chunk.
     ///
-    /// Prerequisite: `IEND` chunk hasn't been reached yet.
-    pub fn read_until_end_of_input(&mut self) -> Result<(), DecodingError> {
-        while !matches!(self.decode_next(None)?, Decoded::ChunkComplete(chunk::IEND)) {}
-        Ok(())
+    /// In strict mode, the input has to end with it.
+    ///
+    /// Prerequisite: `IEND` chunk hasn't been reached yet, or only by a previous poll of this
+    /// operation.
+    pub fn poll_read_until_end_of_input(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), DecodingError>> {
+        while !self.reached_end {
+            let decoded = ready!(self.poll_decode_next(cx, None))?;
+            self.reached_end = matches!(decoded, Decoded::ChunkComplete(chunk::IEND));
+        }
+        if self.decoder.strict() && !ready!(self.reader.poll_fill_buf(cx))?.is_empty() {
+            return Poll::Ready(Err(self
+                .decoder
+                .error_at_position(FormatErrorInner::TrailingData)));
+        }
+        Poll::Ready(Ok(()))
     }
+}
 
-    pub fn info(&self) -> Option<&Info<'static>> {
-        self.decoder.info.as_ref()
+impl<R: BufRead> ReadDecoder<R> {
+    /// Reads until the end of `IHDR` chunk, see `poll_read_header_info`.
+    pub fn read_header_info(&mut self) -> Result<&Info<'static>, DecodingError> {
+        block_on(|cx| self.poll_read_header_info(cx))
+    }
+
+    /// Reads `image_data`, see `poll_decode_image_data`.
+    #[cfg(feature = "parallel")]
+    pub fn decode_image_data(
+        &mut self,
+        image_data: Option<&mut UnfilterBuf<'_>>,
+    ) -> Result<ImageDataCompletionStatus, DecodingError> {
+        block_on(|cx| self.poll_decode_image_data(cx, image_data))
+    }
+}
+
+impl<R: BufRead + Seek> ReadDecoder<R> {
+    /// Moves the input to the `fcTL` chunk of an animation frame, see [`crate::FrameIndex`].
+    pub fn seek_to_frame(
+        &mut self,
+        position: u64,
+        sequence_number: u32,
+        in_idat: bool,
+    ) -> Result<(), DecodingError> {
+        self.reader.seek(SeekFrom::Start(position))?;
+        self.decoder
+            .seek_to_frame(position, sequence_number, in_idat);
+        self.reached_end = false;
+        Ok(())
     }
 }
 
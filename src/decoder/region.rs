@@ -0,0 +1,164 @@
+//! Copying the pixels of a region of interest out of decoded rows.
+
+/// Copies `width` pixels of `bits_pp` bits each, starting at pixel `x` of `row`, to the start of
+/// `output`. Bits of `output` past the copied pixels are left as they are.
+pub(crate) fn copy_pixels(row: &[u8], x: u32, width: u32, bits_pp: usize, output: &mut [u8]) {
+    if bits_pp % 8 == 0 {
+        let bytes_pp = bits_pp / 8;
+        let start = x as usize * bytes_pp;
+        let len = width as usize * bytes_pp;
+        output[..len].copy_from_slice(&row[start..start + len]);
+    } else {
+        for idx in 0..width as usize {
+            copy_pixel(row, x as usize + idx, output, idx, bits_pp);
+        }
+    }
+}
+
+/// Copies the pixel at `src_idx` in `src` to the pixel at `dst_idx` in `dst`.
+pub(super) fn copy_pixel(
+    src: &[u8],
+    src_idx: usize,
+    dst: &mut [u8],
+    dst_idx: usize,
+    bits_pp: usize,
+) {
+    if bits_pp % 8 == 0 {
+        let bytes_pp = bits_pp / 8;
+        dst[dst_idx * bytes_pp..][..bytes_pp]
+            .copy_from_slice(&src[src_idx * bytes_pp..][..bytes_pp]);
+    } else {
+        // Pixels of 1, 2 and 4 bits never straddle a byte boundary.
+        let mask = (1u8 << bits_pp) - 1;
+        let (src_bit, dst_bit) = (src_idx * bits_pp, dst_idx * bits_pp);
+        let value = (src[src_bit / 8] >> (8 - bits_pp - src_bit % 8)) & mask;
+        let shift = 8 - bits_pp - dst_bit % 8;
+        let byte = &mut dst[dst_bit / 8];
+        *byte = (*byte & !(mask << shift)) | (value << shift);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::copy_pixels;
+    use crate::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+
+    #[test]
+    fn copy_subbyte_pixels() {
+        let row = [0b1011_0010, 0b0111_0000];
+        let mut output = [0xff; 2];
+        copy_pixels(&row, 3, 6, 1, &mut output);
+        assert_eq!(output, [0b1001_0011, 0xff]);
+
+        let mut output = [0; 1];
+        copy_pixels(&row, 1, 3, 2, &mut output);
+        assert_eq!(output, [0b1100_1000]);
+    }
+
+    /// Decodes the whole frame and the region, and compares the region with a crop of the frame.
+    fn check_region(png: &[u8], transform: Transformations, region: (u32, u32, u32, u32)) {
+        let (x, y, width, height) = region;
+
+        let mut decoder = Decoder::new(Cursor::new(png));
+        decoder.set_transformations(transform);
+        let mut reader = decoder.read_info().unwrap();
+        let mut frame = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut frame).unwrap();
+        let bits_pp = info.color_type.samples() * info.bit_depth as usize;
+
+        let mut decoder = Decoder::new(Cursor::new(png));
+        decoder.set_transformations(transform);
+        let mut reader = decoder.read_info().unwrap();
+        let line_size = reader.output_line_size(width).unwrap();
+        let mut actual = vec![0; line_size * height as usize];
+        let region_info = reader
+            .next_frame_region(&mut actual, x, y, width, height)
+            .unwrap();
+        assert_eq!((region_info.width, region_info.height), (width, height));
+        assert_eq!(region_info.line_size, line_size);
+        reader.finish().unwrap();
+
+        let mut expected = vec![0; line_size * height as usize];
+        for (row, output) in frame
+            .chunks_exact(info.line_size)
+            .skip(y as usize)
+            .zip(expected.chunks_exact_mut(line_size))
+        {
+            copy_pixels(row, x, width, bits_pp, output);
+        }
+        assert_eq!(expected, actual, "{region:?} {transform:?}");
+    }
+
+    #[test]
+    fn region_matches_cropped_frame() {
+        for path in [
+            "tests/pngsuite/basn0g01.png",
+            "tests/pngsuite/basn0g04.png",
+            "tests/pngsuite/basn2c16.png",
+            "tests/pngsuite/basn3p02.png",
+            "tests/pngsuite/basi0g02.png",
+            "tests/pngsuite/basi3p04.png",
+            "tests/pngsuite/basi6a08.png",
+            "tests/pngsuite/tbrn2c08.png",
+        ] {
+            let png = std::fs::read(path).unwrap();
+            for transform in [
+                Transformations::IDENTITY,
+                Transformations::EXPAND,
+                Transformations::normalize_to_color8(),
+            ] {
+                for region in [
+                    (0, 0, 32, 32),
+                    (3, 5, 7, 11),
+                    (31, 0, 1, 32),
+                    (10, 31, 20, 1),
+                ] {
+                    check_region(&png, transform, region);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn region_out_of_bounds() {
+        let png = std::fs::read("tests/pngsuite/basn0g08.png").unwrap();
+        let mut reader = Decoder::new(Cursor::new(&png)).read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        assert!(reader.next_frame_region(&mut buf, 30, 0, 3, 1).is_err());
+        assert!(reader.next_frame_region(&mut buf, 0, 0, 1, 33).is_err());
+        assert!(reader
+            .next_frame_region(&mut buf, 0, u32::MAX, 1, 1)
+            .is_err());
+        reader.next_frame_region(&mut buf, 0, 0, 32, 32).unwrap();
+    }
+
+    #[test]
+    fn region_of_every_frame() {
+        let (width, height) = (20, 12);
+        let mut png = Vec::new();
+        let mut encoder = Encoder::new(&mut png, width, height);
+        encoder.set_color(ColorType::Grayscale);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.set_animated(3, 0).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        for frame in 0..3u8 {
+            let pixels: Vec<u8> = (0..width * height).map(|i| i as u8 ^ frame).collect();
+            writer.write_image_data(&pixels).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut reader = Decoder::new(Cursor::new(&png)).read_info().unwrap();
+        let mut buf = vec![0; 4 * 2];
+        for frame in 0..3u8 {
+            reader.next_frame_region(&mut buf, 5, 7, 4, 2).unwrap();
+            let expected: Vec<u8> = [7 * width + 5, 8 * width + 5]
+                .iter()
+                .flat_map(|&start| (start..start + 4).map(|i| i as u8 ^ frame))
+                .collect();
+            assert_eq!(buf, expected);
+        }
+        reader.finish().unwrap();
+    }
+}
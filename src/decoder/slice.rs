@@ -0,0 +1,422 @@
+//! Decoding from a byte slice that is entirely in memory.
+//!
+//! The input is handed to the [`StreamingDecoder`](super::stream::StreamingDecoder) as a whole,
+//! so image data is inflated straight from the slice. Chunk payloads that `Info` exposes as raw
+//! bytes are not copied but referenced in the slice.
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use super::stream::{DecodeOptions, DecodeWarning, DecodingError};
+use super::{Decoder, InterlaceInfo, InterlacedRow, Limits, OutputInfo, Reader, Row};
+use crate::common::{
+    BitDepth, ColorType, Downscale, FrameControl, Info, ScaledFloat, Transformations,
+};
+
+/// PNG decoder for an image that is already in memory, such as a memory-mapped file.
+///
+/// This works like [`Decoder`], but the [`Info`] of the [`SliceReader`] it creates borrows from
+/// the input: [`Info::exif_metadata`] and the `data` of each of [`Info::unknown_chunks`] are
+/// [`Cow::Borrowed`] and the chunks are never copied. Chunks that have to be decompressed or
+/// decoded, such as `iCCP` and the text chunks, are still owned.
+///
+/// ```
+/// let png = std::fs::read("tests/pngsuite/basi0g01.png").unwrap();
+/// let mut decoder = png::SliceDecoder::new(&png);
+/// decoder.set_keep_unknown_chunks(true);
+/// let mut reader = decoder.read_info().unwrap();
+/// let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+/// reader.next_frame(&mut buf).unwrap();
+/// assert!(reader.info().exif_metadata.is_none());
+/// ```
+pub struct SliceDecoder<'a> {
+    data: &'a [u8],
+    decoder: Decoder<&'a [u8]>,
+}
+
+impl<'a> SliceDecoder<'a> {
+    /// Create a new decoder configuration with default limits.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self::new_with_options(data, DecodeOptions::default())
+    }
+
+    /// Create a new decoder configuration with custom limits.
+    pub fn new_with_limits(data: &'a [u8], limits: Limits) -> Self {
+        let mut decoder = Self::new(data);
+        decoder.set_limits(limits);
+        decoder
+    }
+
+    /// Create a new decoder configuration with custom [`DecodeOptions`].
+    pub fn new_with_options(data: &'a [u8], mut decode_options: DecodeOptions) -> Self {
+        decode_options.set_borrow_chunk_data(true);
+        Self {
+            data,
+            decoder: Decoder::new_with_options(data, decode_options),
+        }
+    }
+
+    /// Limit resource usage, see [`Decoder::set_limits`].
+    ///
+    /// Chunks that are borrowed from the input do not count against the limits.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.decoder.set_limits(limits);
+    }
+
+    /// Read the PNG header and return the information contained within, see
+    /// [`Decoder::read_header_info`].
+    pub fn read_header_info(&mut self) -> Result<&Info<'a>, DecodingError> {
+        self.decoder.read_header_info()
+    }
+
+    /// Reads all meta data until the first IDAT chunk.
+    pub fn read_info(self) -> Result<SliceReader<'a>, DecodingError> {
+        Ok(SliceReader {
+            data: self.data,
+            reader: self.decoder.read_info()?,
+            info: OnceLock::new(),
+        })
+    }
+
+    /// Set the allowed and performed transformations, see [`Decoder::set_transformations`].
+    pub fn set_transformations(&mut self, transform: Transformations) {
+        self.decoder.set_transformations(transform);
+    }
+
+    /// Scale frames down while they are decoded, see [`Decoder::set_downscale`].
+    pub fn set_downscale(&mut self, downscale: Downscale) {
+        self.decoder.set_downscale(downscale);
+    }
+
+    /// Set the gamma that [`Transformations::GAMMA`] corrects color samples to, see
+    /// [`Decoder::set_display_gamma`].
+    pub fn set_display_gamma(&mut self, display_gamma: ScaledFloat) {
+        self.decoder.set_display_gamma(display_gamma);
+    }
+
+    /// Set the decoder to ignore all text chunks while parsing.
+    pub fn set_ignore_text_chunk(&mut self, ignore_text_chunk: bool) {
+        self.decoder.set_ignore_text_chunk(ignore_text_chunk);
+    }
+
+    /// Set the decoder to ignore iccp chunks while parsing.
+    pub fn set_ignore_iccp_chunk(&mut self, ignore_iccp_chunk: bool) {
+        self.decoder.set_ignore_iccp_chunk(ignore_iccp_chunk);
+    }
+
+    /// Set the decoder to collect unrecognized ancillary chunks into [`Info::unknown_chunks`].
+    pub fn set_keep_unknown_chunks(&mut self, keep_unknown_chunks: bool) {
+        self.decoder.set_keep_unknown_chunks(keep_unknown_chunks);
+    }
+
+    /// Set the decoder to ignore and not verify the Adler-32 checksum
+    /// and CRC code.
+    pub fn ignore_checksums(&mut self, ignore_checksums: bool) {
+        self.decoder.ignore_checksums(ignore_checksums);
+    }
+}
+
+/// PNG reader created by [`SliceDecoder::read_info`].
+///
+/// Offers the [`Reader`] API, with an [`Info`] that borrows from the input.
+pub struct SliceReader<'a> {
+    data: &'a [u8],
+    reader: Reader<&'a [u8]>,
+    /// The `Info` of `reader` with the borrowed chunks filled in. It is built when it is first
+    /// asked for and cleared whenever the reader advances.
+    info: OnceLock<Info<'a>>,
+}
+
+impl<'a> SliceReader<'a> {
+    /// Get information on the image.
+    ///
+    /// The structure will change as new frames of an animated image are decoded.
+    pub fn info(&self) -> &Info<'a> {
+        self.info.get_or_init(|| {
+            let mut info: Info<'a> = self.reader.info().clone();
+            let borrowed = self.reader.decoder.borrowed_chunks();
+            let resolve = |range: &Range<u64>| -> Cow<'a, [u8]> {
+                Cow::Borrowed(&self.data[range.start as usize..range.end as usize])
+            };
+            if let Some(range) = &borrowed.exif_metadata {
+                info.exif_metadata = Some(resolve(range));
+            }
+            for (chunk, range) in info.unknown_chunks.iter_mut().zip(&borrowed.unknown_chunks) {
+                chunk.data = resolve(range);
+            }
+            info
+        })
+    }
+
+    /// The deviations from the specification that were tolerated so far, see
+    /// [`Reader::warnings`].
+    pub fn warnings(&self) -> &[DecodeWarning] {
+        self.reader.warnings()
+    }
+
+    /// Advances to the start of the next animation frame, see [`Reader::next_frame_info`].
+    pub fn next_frame_info(&mut self) -> Result<&FrameControl, DecodingError> {
+        self.advance().next_frame_info()?;
+        Ok(self.info().frame_control.as_ref().unwrap())
+    }
+
+    /// Decodes the next frame into `buf`, see [`Reader::next_frame`].
+    pub fn next_frame(&mut self, buf: &mut [u8]) -> Result<OutputInfo, DecodingError> {
+        self.advance().next_frame(buf)
+    }
+
+    /// Decodes the next frame into `buf` as native endian 16 bit samples, see
+    /// [`Reader::next_frame_u16`].
+    pub fn next_frame_u16(&mut self, buf: &mut [u16]) -> Result<OutputInfo, DecodingError> {
+        self.advance().next_frame_u16(buf)
+    }
+
+    /// Decodes the next frame into `buf` as samples normalized to `0.0..=1.0`, see
+    /// [`Reader::next_frame_f32`].
+    pub fn next_frame_f32(&mut self, buf: &mut [f32]) -> Result<OutputInfo, DecodingError> {
+        self.advance().next_frame_f32(buf)
+    }
+
+    /// Decodes the next frame into `buf` as samples of linear sRGB, see
+    /// [`Reader::next_frame_linear_srgb`].
+    pub fn next_frame_linear_srgb(&mut self, buf: &mut [f32]) -> Result<OutputInfo, DecodingError> {
+        self.advance().next_frame_linear_srgb(buf)
+    }
+
+    /// Decodes a rectangle of the next frame into `buf`, see [`Reader::next_frame_region`].
+    pub fn next_frame_region(
+        &mut self,
+        buf: &mut [u8],
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<OutputInfo, DecodingError> {
+        self.advance().next_frame_region(buf, x, y, width, height)
+    }
+
+    /// Decodes the next frame into `buf` using multiple threads, see
+    /// [`Reader::next_frame_parallel`].
+    ///
+    /// Only available with the `parallel` feature.
+    #[cfg(feature = "parallel")]
+    pub fn next_frame_parallel(&mut self, buf: &mut [u8]) -> Result<OutputInfo, DecodingError> {
+        self.advance().next_frame_parallel(buf)
+    }
+
+    /// Returns the next processed row of the image, see [`Reader::next_row`].
+    pub fn next_row(&mut self) -> Result<Option<Row<'_>>, DecodingError> {
+        self.advance().next_row()
+    }
+
+    /// Returns the next processed row of the image, see [`Reader::next_interlaced_row`].
+    pub fn next_interlaced_row(&mut self) -> Result<Option<InterlacedRow<'_>>, DecodingError> {
+        self.advance().next_interlaced_row()
+    }
+
+    /// Reads the next row of the image into the provided `output_buffer`, see
+    /// [`Reader::read_row`].
+    pub fn read_row(
+        &mut self,
+        output_buffer: &mut [u8],
+    ) -> Result<Option<InterlaceInfo>, DecodingError> {
+        self.advance().read_row(output_buffer)
+    }
+
+    /// Read the rest of the image and chunks and finish up, see [`Reader::finish`].
+    pub fn finish(&mut self) -> Result<(), DecodingError> {
+        self.advance().finish()
+    }
+
+    /// Returns the color type and the number of bits per sample
+    /// of the data returned by [`SliceReader::next_row`] and [`SliceReader::next_frame`].
+    pub fn output_color_type(&self) -> (ColorType, BitDepth) {
+        self.reader.output_color_type()
+    }
+
+    /// Return the number of bytes required to hold a deinterlaced image frame, see
+    /// [`Reader::output_buffer_size`].
+    pub fn output_buffer_size(&self) -> Option<usize> {
+        self.reader.output_buffer_size()
+    }
+
+    /// Returns the number of bytes required to hold a deinterlaced row, see
+    /// [`Reader::output_line_size`].
+    pub fn output_line_size(&self, width: u32) -> Option<usize> {
+        self.reader.output_line_size(width)
+    }
+
+    /// Returns the reader to decode more of the image with, which may parse more chunks.
+    fn advance(&mut self) -> &mut Reader<&'a [u8]> {
+        self.info.take();
+        &mut self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::io::Cursor;
+    use std::ops::Range;
+
+    use super::SliceDecoder;
+    use crate::chunk::ChunkType;
+    use crate::{ColorType, Decoder, Encoder, Info, Transformations};
+
+    /// Encodes a small image with an `eXIf` chunk and two unknown chunks.
+    fn image_with_chunks() -> Vec<u8> {
+        let mut info = Info::with_size(4, 4);
+        info.color_type = ColorType::Grayscale;
+        info.exif_metadata = Some(Cow::Borrowed(b"MM\0*exif data"));
+
+        let mut png = Vec::new();
+        let mut writer = Encoder::with_info(&mut png, info)
+            .unwrap()
+            .write_header()
+            .unwrap();
+        writer
+            .write_chunk(ChunkType(*b"teSt"), b"before image data")
+            .unwrap();
+        writer.write_image_data(&[0x55; 16]).unwrap();
+        writer
+            .write_chunk(ChunkType(*b"teSt"), b"after image data")
+            .unwrap();
+        writer.finish().unwrap();
+        png
+    }
+
+    fn contains(outer: &[u8], inner: &[u8]) -> bool {
+        let Range { start, end } = outer.as_ptr_range();
+        let inner = inner.as_ptr_range();
+        start <= inner.start && inner.end <= end
+    }
+
+    #[test]
+    fn chunks_are_borrowed() {
+        let png = image_with_chunks();
+        let mut decoder = SliceDecoder::new(&png);
+        decoder.set_keep_unknown_chunks(true);
+        let mut reader = decoder.read_info().unwrap();
+
+        let exif = reader.info().exif_metadata.as_ref().unwrap();
+        assert!(matches!(exif, Cow::Borrowed(_)));
+        assert!(contains(&png, exif));
+        assert_eq!(&**exif, b"MM\0*exif data");
+        assert_eq!(reader.info().unknown_chunks.len(), 1);
+
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut buf).unwrap();
+        assert_eq!(buf, [0x55; 16]);
+        reader.finish().unwrap();
+
+        let chunks = &reader.info().unknown_chunks;
+        assert_eq!(chunks.len(), 2);
+        for (chunk, expected) in chunks
+            .iter()
+            .zip([&b"before image data"[..], b"after image data"])
+        {
+            assert!(matches!(chunk.data, Cow::Borrowed(_)));
+            assert!(contains(&png, &chunk.data));
+            assert_eq!(&*chunk.data, expected);
+        }
+    }
+
+    #[test]
+    fn info_follows_rows() {
+        let mut info = Info::with_size(4, 4);
+        info.color_type = ColorType::Grayscale;
+        info.icc_profile = Some(Cow::Borrowed(b"not really a profile"));
+        let mut png = Vec::new();
+        let mut writer = Encoder::with_info(&mut png, info)
+            .unwrap()
+            .write_header()
+            .unwrap();
+        writer.write_image_data(&[0x55; 16]).unwrap();
+        writer
+            .write_chunk(ChunkType(*b"teSt"), b"after image data")
+            .unwrap();
+        writer.finish().unwrap();
+
+        let mut decoder = SliceDecoder::new(&png);
+        decoder.set_keep_unknown_chunks(true);
+        let mut reader = decoder.read_info().unwrap();
+        assert!(reader.info().unknown_chunks.is_empty());
+        while reader.next_row().unwrap().is_some() {}
+        reader.finish().unwrap();
+
+        let chunks = &reader.info().unknown_chunks;
+        assert_eq!(chunks.len(), 1);
+        assert!(contains(&png, &chunks[0].data));
+        // The `Info` of the underlying reader is left as it is.
+        let icc_profile = reader.info().icc_profile.as_deref();
+        assert_eq!(icc_profile, Some(&b"not really a profile"[..]));
+        assert_eq!(reader.reader.info().icc_profile.as_deref(), icc_profile);
+    }
+
+    #[test]
+    fn matches_decoder() {
+        for path in glob::glob("tests/pngsuite/*.png")
+            .unwrap()
+            .map(|r| r.unwrap())
+        {
+            if path.file_name().unwrap().to_str().unwrap().starts_with('x') {
+                // x* files are expected to fail to decode
+                continue;
+            }
+            let png = std::fs::read(&path).unwrap();
+
+            let mut decoder = Decoder::new(Cursor::new(&png));
+            decoder.set_transformations(Transformations::EXPAND);
+            let mut reader = decoder.read_info().unwrap();
+            let mut expected = vec![0; reader.output_buffer_size().unwrap()];
+            reader.next_frame(&mut expected).unwrap();
+            reader.finish().unwrap();
+
+            let mut decoder = SliceDecoder::new(&png);
+            decoder.set_transformations(Transformations::EXPAND);
+            let mut slice_reader = decoder.read_info().unwrap();
+            let mut actual = vec![0; slice_reader.output_buffer_size().unwrap()];
+            slice_reader.next_frame(&mut actual).unwrap();
+            slice_reader.finish().unwrap();
+
+            assert_eq!(expected, actual, "{}", path.display());
+            let (expected, actual) = (reader.info(), slice_reader.info());
+            assert_eq!(expected.palette, actual.palette, "{}", path.display());
+            assert_eq!(expected.icc_profile, actual.icc_profile);
+            assert_eq!(expected.utf8_text, actual.utf8_text);
+            assert_eq!(
+                expected.uncompressed_latin1_text,
+                actual.uncompressed_latin1_text
+            );
+            assert_eq!(
+                expected.compressed_latin1_text,
+                actual.compressed_latin1_text
+            );
+        }
+    }
+
+    #[test]
+    fn typed_frames_match_decoder() {
+        let png = std::fs::read("tests/pngsuite/basi6a16.png").unwrap();
+        let mut reader = Decoder::new(Cursor::new(&png)).read_info().unwrap();
+        let mut expected = vec![0; 32 * 32 * 4];
+        reader.next_frame_u16(&mut expected).unwrap();
+
+        let mut slice_reader = SliceDecoder::new(&png).read_info().unwrap();
+        let mut actual = vec![0; 32 * 32 * 4];
+        slice_reader.next_frame_u16(&mut actual).unwrap();
+        assert_eq!(expected, actual);
+
+        let mut reader = Decoder::new(Cursor::new(&png)).read_info().unwrap();
+        let mut slice_reader = SliceDecoder::new(&png).read_info().unwrap();
+        let (mut expected, mut actual) = (vec![0.0; 32 * 32 * 4], vec![0.0; 32 * 32 * 4]);
+        reader.next_frame_f32(&mut expected).unwrap();
+        slice_reader.next_frame_f32(&mut actual).unwrap();
+        assert_eq!(expected, actual);
+        reader = Decoder::new(Cursor::new(&png)).read_info().unwrap();
+        slice_reader = SliceDecoder::new(&png).read_info().unwrap();
+        reader.next_frame_linear_srgb(&mut expected).unwrap();
+        slice_reader.next_frame_linear_srgb(&mut actual).unwrap();
+        assert_eq!(expected, actual);
+    }
+}
@@ -2,6 +2,7 @@ use std::convert::TryInto;
 use std::error;
 use std::fmt;
 use std::io;
+use std::ops::Range;
 use std::{borrow::Cow, cmp::min};
 
 use crc32fast::Hasher as Crc32;
@@ -11,9 +12,10 @@ use super::zlib::ZlibStream;
 use crate::chunk::is_critical;
 use crate::chunk::{self, ChunkType, IDAT, IEND, IHDR};
 use crate::common::{
-    AnimationControl, BitDepth, BlendOp, ColorType, ContentLightLevelInfo, DisposeOp, FrameControl,
-    Info, MasteringDisplayColorVolume, ParameterError, ParameterErrorKind, PixelDimensions,
-    ScaledFloat, SourceChromaticities, Unit,
+    AnimationControl, BitDepth, BlendOp, ChunkPosition, ColorType, ContentLightLevelInfo,
+    DisposeOp, FrameControl, Info, LastModificationTime, MasteringDisplayColorVolume,
+    ParameterError, ParameterErrorKind, PixelDimensions, ScaledFloat, SourceChromaticities, Unit,
+    UnknownChunk,
 };
 use crate::text_metadata::{ITXtChunk, TEXtChunk, TextDecodingError, ZTXtChunk};
 use crate::traits::ReadBytesExt;
@@ -28,6 +30,9 @@ pub const CHUNK_BUFFER_SIZE: usize = 128;
 #[allow(unexpected_cfgs)]
 const CHECKSUM_DISABLED: bool = cfg!(fuzzing);
 
+/// How many warnings are kept, so that a file full of broken chunks can't use unbounded memory.
+const MAX_WARNINGS: usize = 256;
+
 /// Kind of `u32` value that is being read via `State::U32`.
 #[derive(Debug)]
 enum U32ValueKind {
@@ -125,9 +130,11 @@ pub enum DecodingError {
     /// The input image was not a valid PNG.
     ///
     /// There isn't a lot that can be done here, except if the program itself was responsible for
-    /// creating this image then investigate the generator. This is internally implemented with a
-    /// large Enum. If You are interested in accessing some of the more exact information on the
-    /// variant then we can discuss in an issue.
+    /// creating this image then investigate the generator. [`FormatError::kind`] tells what was
+    /// wrong, and [`FormatError::offset`] where.
+    ///
+    /// Input that ends too early is an [`DecodingError::IoError`] instead, since more data may
+    /// still arrive.
     Format(FormatError),
     /// An interface was used incorrectly.
     ///
@@ -155,9 +162,108 @@ pub enum DecodingError {
     LimitsExceeded,
 }
 
+/// The input image was not a valid PNG, see [`DecodingError::Format`].
 #[derive(Debug)]
 pub struct FormatError {
     inner: FormatErrorInner,
+    /// Where in the input the error was found, if known.
+    offset: Option<u64>,
+    /// The type of the chunk in which the error was found, if any.
+    chunk_type: Option<ChunkType>,
+    /// The index of that chunk, if known.
+    chunk_index: Option<usize>,
+}
+
+impl FormatError {
+    /// What was wrong with the input.
+    pub fn kind(&self) -> FormatErrorKind {
+        self.inner.kind()
+    }
+
+    /// The offset in the input at which the error was found, if known.
+    ///
+    /// This is the start of the field or data that was being decoded, counted from the first byte
+    /// of the PNG signature. For example, it is the CRC of a chunk whose checksum or contents are
+    /// invalid, and the start of the image data that was being decompressed for a corrupt zlib
+    /// stream. It is `None` for errors that are only found after decompression, such as an
+    /// invalid filter type.
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    /// The type of the chunk in which the error was found, if any.
+    pub fn chunk_type(&self) -> Option<ChunkType> {
+        self.chunk_type
+    }
+
+    /// The index of the chunk in which the error was found, counting from 0 for `IHDR`.
+    ///
+    /// It is `None` if the error is not in a chunk, and after seeking to an animation frame
+    /// where the number of preceding chunks is unknown.
+    pub fn chunk_index(&self) -> Option<usize> {
+        self.chunk_index
+    }
+
+    /// Records where in the input the error was found.
+    pub(crate) fn with_location(
+        mut self,
+        offset: u64,
+        chunk_type: Option<ChunkType>,
+        chunk_index: Option<usize>,
+    ) -> Self {
+        self.offset = Some(offset);
+        self.chunk_type = chunk_type;
+        self.chunk_index = chunk_index;
+        self
+    }
+}
+
+/// The kind of a [`FormatError`].
+///
+/// None of them can be fixed by retrying with more input: a PNG that is cut short results in a
+/// [`DecodingError::IoError`] of kind [`std::io::ErrorKind::UnexpectedEof`] instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FormatErrorKind {
+    /// The input does not start with the PNG signature.
+    InvalidSignature,
+    /// The CRC of a chunk does not match its contents.
+    CrcMismatch,
+    /// A chunk has an invalid length for its type.
+    ChunkLengthWrong,
+    /// A chunk other than `IHDR` is the first chunk.
+    ChunkBeforeIhdr,
+    /// A chunk is in the wrong place relative to the `PLTE` or `IDAT` chunks.
+    MisplacedChunk,
+    /// A chunk that must appear at most once is repeated.
+    DuplicateChunk,
+    /// A critical chunk has an unknown type.
+    UnrecognizedCriticalChunk,
+    /// The `IHDR` chunk has invalid dimensions, bit depth, color type, or compression or
+    /// interlace method.
+    InvalidHeader,
+    /// The filter method of the `IHDR` chunk or the filter type of a row is unknown.
+    UnknownFilterMethod,
+    /// An indexed image has no `PLTE` chunk.
+    PaletteRequired,
+    /// The contents of an ancillary chunk or the `PLTE` chunk are invalid.
+    InvalidChunk,
+    /// The animation chunks are invalid, e.g. out of order, or describe a frame outside of the
+    /// image.
+    InvalidAnimation,
+    /// There are no `IDAT` or `fdAT` chunks for a frame.
+    MissingImageData,
+    /// The `IDAT` or `fdAT` chunks of a frame are interrupted by other chunks.
+    UnexpectedRestartOfDataChunkSequence,
+    /// The zlib stream of the image data is corrupt.
+    CorruptFlateStream,
+    /// The image data ends before the end of the frame.
+    NoMoreImageData,
+    /// The image data continues past the end of the frame or of its zlib stream, see
+    /// [`DecodeOptions::set_strict`].
+    ExtraImageData,
+    /// There is data after the `IEND` chunk, see [`DecodeOptions::set_strict`].
+    TrailingData,
 }
 
 #[derive(Debug)]
@@ -227,6 +333,8 @@ pub(crate) enum FormatErrorInner {
     },
     /// A palletized image did not have a palette.
     PaletteRequired,
+    /// The acTL chunk has a `num_frames` of zero.
+    NoAnimationFrames,
     /// The color-depth combination is not valid according to Table 11.1.
     InvalidColorBitDepth {
         color_type: ColorType,
@@ -242,6 +350,8 @@ pub(crate) enum FormatErrorInner {
     InvalidUnit(u8),
     /// The rendering intent of the sRGB chunk is invalid.
     InvalidSrgbRenderingIntent(u8),
+    /// The date or time of the tIME chunk is out of range.
+    InvalidLastModificationTime,
     UnknownCompressionMethod(u8),
     UnknownFilterMethod(u8),
     UnknownInterlaceMethod(u8),
@@ -255,6 +365,10 @@ pub(crate) enum FormatErrorInner {
     },
     /// The image data chunk was too short for the expected pixel count.
     NoMoreImageData,
+    /// The image data continues past the end of the frame or of its zlib stream.
+    ExtraImageData,
+    /// There is data after the `IEND` chunk.
+    TrailingData,
     /// Bad text encoding
     BadTextEncoding(TextDecodingError),
     /// fdAT shorter than 4 bytes
@@ -303,9 +417,76 @@ impl fmt::Display for DecodingError {
 }
 
 impl fmt::Display for FormatError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.inner)?;
+        let Some(offset) = self.offset else {
+            return Ok(());
+        };
+        write!(fmt, " (at byte {}", offset)?;
+        if let Some(chunk_type) = self.chunk_type {
+            write!(fmt, ", in chunk ")?;
+            if let Some(index) = self.chunk_index {
+                write!(fmt, "#{} ", index)?;
+            }
+            write!(fmt, "{}", String::from_utf8_lossy(&chunk_type.0))?;
+        }
+        write!(fmt, ")")
+    }
+}
+
+impl FormatErrorInner {
+    fn kind(&self) -> FormatErrorKind {
+        use FormatErrorInner::*;
+        match self {
+            InvalidSignature => FormatErrorKind::InvalidSignature,
+            CrcMismatch { .. } => FormatErrorKind::CrcMismatch,
+            ChunkLengthWrong { .. } => FormatErrorKind::ChunkLengthWrong,
+            ChunkBeforeIhdr { .. } => FormatErrorKind::ChunkBeforeIhdr,
+            AfterIdat { .. } | BeforePlte { .. } | AfterPlte { .. } | OutsidePlteIdat { .. } => {
+                FormatErrorKind::MisplacedChunk
+            }
+            DuplicateChunk { .. } => FormatErrorKind::DuplicateChunk,
+            UnrecognizedCriticalChunk { .. } => FormatErrorKind::UnrecognizedCriticalChunk,
+            InvalidColorBitDepth { .. }
+            | InvalidDimensions
+            | InvalidBitDepth(_)
+            | InvalidColorType(_)
+            | UnknownCompressionMethod(_)
+            | UnknownInterlaceMethod(_) => FormatErrorKind::InvalidHeader,
+            UnknownFilterMethod(_) => FormatErrorKind::UnknownFilterMethod,
+            PaletteRequired => FormatErrorKind::PaletteRequired,
+            ShortPalette { .. }
+            | InvalidSbitChunkSize { .. }
+            | InvalidSbit { .. }
+            | ColorWithBadTrns(_)
+            | InvalidUnit(_)
+            | InvalidSrgbRenderingIntent(_)
+            | InvalidLastModificationTime
+            | BadGammaValue
+            | BadTextEncoding(_) => FormatErrorKind::InvalidChunk,
+            MissingFctl
+            | ApngOrder { .. }
+            | FdatShorterThanFourBytes
+            | BadSubFrameBounds {}
+            | NoAnimationFrames
+            | InvalidDisposeOp(_)
+            | InvalidBlendOp(_) => FormatErrorKind::InvalidAnimation,
+            MissingImageData => FormatErrorKind::MissingImageData,
+            UnexpectedRestartOfDataChunkSequence { .. } => {
+                FormatErrorKind::UnexpectedRestartOfDataChunkSequence
+            }
+            CorruptFlateStream { .. } => FormatErrorKind::CorruptFlateStream,
+            NoMoreImageData => FormatErrorKind::NoMoreImageData,
+            ExtraImageData => FormatErrorKind::ExtraImageData,
+            TrailingData => FormatErrorKind::TrailingData,
+        }
+    }
+}
+
+impl fmt::Display for FormatErrorInner {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         use FormatErrorInner::*;
-        match &self.inner {
+        match self {
             CrcMismatch {
                 crc_val,
                 crc_sum,
@@ -369,6 +550,7 @@ impl fmt::Display for FormatError {
             InvalidBlendOp(nr) => write!(fmt, "Invalid blend op {}.", nr),
             InvalidUnit(nr) => write!(fmt, "Invalid physical pixel size unit {}.", nr),
             InvalidSrgbRenderingIntent(nr) => write!(fmt, "Invalid sRGB rendering intent {}.", nr),
+            InvalidLastModificationTime => write!(fmt, "Invalid last modification time."),
             UnknownCompressionMethod(nr) => write!(fmt, "Unknown compression method {}.", nr),
             UnknownFilterMethod(nr) => write!(fmt, "Unknown filter method {}.", nr),
             UnknownInterlaceMethod(nr) => write!(fmt, "Unknown interlace method {}.", nr),
@@ -378,6 +560,12 @@ impl fmt::Display for FormatError {
                 fmt,
                 "IDAT or fDAT chunk does not have enough data for image."
             ),
+            ExtraImageData => write!(
+                fmt,
+                "IDAT or fdAT chunk has data past the end of the image or its zlib stream."
+            ),
+            TrailingData => write!(fmt, "Data after the IEND chunk."),
+            NoAnimationFrames => write!(fmt, "acTL chunk declares zero frames."),
             CorruptFlateStream { err } => {
                 write!(fmt, "Corrupt deflate stream. ")?;
                 write!(fmt, "{:?}", err)
@@ -426,6 +614,78 @@ impl fmt::Display for FormatError {
     }
 }
 
+/// A deviation from the specification that the decoder tolerated instead of failing.
+///
+/// These are collected in [`Reader::warnings`](crate::Reader::warnings). In strict mode, see
+/// [`DecodeOptions::set_strict`], each of them is an error instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct DecodeWarning {
+    /// The type of the chunk with the problem.
+    pub chunk: ChunkType,
+    /// Where the chunk starts in the input, i.e. the position of its length field.
+    pub offset: u64,
+    /// What was wrong with the chunk.
+    pub kind: DecodeWarningKind,
+}
+
+/// The reason for a [`DecodeWarning`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DecodeWarningKind {
+    /// The CRC of an ancillary chunk did not match, so the chunk was skipped.
+    ///
+    /// CRCs are not computed with [`DecodeOptions::set_ignore_crc`], so they can't mismatch.
+    CrcMismatch,
+    /// An ancillary chunk had an invalid length for its type, so it was skipped.
+    ChunkLengthWrong,
+    /// An ancillary chunk that must appear at most once was repeated, so it was ignored.
+    DuplicateChunk,
+    /// An ancillary chunk was in the wrong place, e.g. after `IDAT`, so it was ignored.
+    MisplacedChunk,
+    /// The contents of an ancillary chunk were invalid, so the chunk was ignored.
+    InvalidChunk,
+    /// The `IDAT` or `fdAT` chunks continued past the end of the frame or of its zlib stream, or
+    /// the stream ended without its Adler-32 checksum. The rest of the data was ignored.
+    ///
+    /// A corrupt checksum is only detected when it is verified, which is disabled by default, see
+    /// [`DecodeOptions::set_ignore_adler32`].
+    ExtraImageData,
+}
+
+impl DecodeWarningKind {
+    /// Classifies the error that made an ancillary chunk be ignored.
+    fn of_error(err: &FormatError) -> Self {
+        match err.kind() {
+            FormatErrorKind::DuplicateChunk => DecodeWarningKind::DuplicateChunk,
+            FormatErrorKind::MisplacedChunk => DecodeWarningKind::MisplacedChunk,
+            FormatErrorKind::ChunkLengthWrong => DecodeWarningKind::ChunkLengthWrong,
+            _ => DecodeWarningKind::InvalidChunk,
+        }
+    }
+}
+
+impl fmt::Display for DecodeWarning {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use DecodeWarningKind::*;
+        let reason = match self.kind {
+            CrcMismatch => "CRC mismatch",
+            ChunkLengthWrong => "wrong length",
+            DuplicateChunk => "duplicate chunk",
+            MisplacedChunk => "chunk in the wrong place",
+            InvalidChunk => "invalid contents",
+            ExtraImageData => "extra image data",
+        };
+        write!(
+            fmt,
+            "Ignored chunk {} at offset {}: {}.",
+            String::from_utf8_lossy(&self.chunk.0),
+            self.offset,
+            reason
+        )
+    }
+}
+
 impl From<io::Error> for DecodingError {
     fn from(err: io::Error) -> DecodingError {
         DecodingError::IoError(err)
@@ -440,7 +700,12 @@ impl From<FormatError> for DecodingError {
 
 impl From<FormatErrorInner> for FormatError {
     fn from(inner: FormatErrorInner) -> Self {
-        FormatError { inner }
+        FormatError {
+            inner,
+            offset: None,
+            chunk_type: None,
+            chunk_index: None,
+        }
     }
 }
 
@@ -455,9 +720,7 @@ impl From<DecodingError> for io::Error {
 
 impl From<TextDecodingError> for DecodingError {
     fn from(tbe: TextDecodingError) -> Self {
-        DecodingError::Format(FormatError {
-            inner: FormatErrorInner::BadTextEncoding(tbe),
-        })
+        DecodingError::Format(FormatErrorInner::BadTextEncoding(tbe).into())
     }
 }
 
@@ -469,6 +732,9 @@ pub struct DecodeOptions {
     ignore_text_chunk: bool,
     ignore_iccp_chunk: bool,
     skip_ancillary_crc_failures: bool,
+    keep_unknown_chunks: bool,
+    borrow_chunk_data: bool,
+    strict: bool,
 }
 
 impl Default for DecodeOptions {
@@ -479,6 +745,9 @@ impl Default for DecodeOptions {
             ignore_text_chunk: false,
             ignore_iccp_chunk: false,
             skip_ancillary_crc_failures: true,
+            keep_unknown_chunks: false,
+            borrow_chunk_data: false,
+            strict: false,
         }
     }
 }
@@ -525,6 +794,52 @@ impl DecodeOptions {
     pub fn set_skip_ancillary_crc_failures(&mut self, skip_ancillary_crc_failures: bool) {
         self.skip_ancillary_crc_failures = skip_ancillary_crc_failures;
     }
+
+    /// Collect ancillary chunks that the decoder does not recognize into
+    /// [`Info::unknown_chunks`].
+    ///
+    /// The payload of each collected chunk is counted against [`Limits::bytes`]. Unknown critical
+    /// chunks are still rejected.
+    ///
+    /// Defaults to `false`.
+    pub fn set_keep_unknown_chunks(&mut self, keep_unknown_chunks: bool) {
+        self.keep_unknown_chunks = keep_unknown_chunks;
+    }
+
+    /// Report every recoverable deviation from the specification as an error.
+    ///
+    /// By default the decoder is lenient, like libpng: ancillary chunks with a bad CRC, length or
+    /// content, duplicated ones and ones in the wrong place are skipped, and so is data after the
+    /// zlib stream of a frame and after the `IEND` chunk. Except for the latter, they are listed
+    /// in [`Reader::warnings`](crate::Reader::warnings). In strict mode each of them is a
+    /// [`DecodingError::Format`] instead, and bad CRCs of ancillary chunks are errors regardless
+    /// of [`DecodeOptions::set_skip_ancillary_crc_failures`].
+    ///
+    /// Excess image data is only detected for frames that are decompressed completely, and data
+    /// after `IEND` is detected by [`Reader::finish`](crate::Reader::finish).
+    ///
+    /// Defaults to `false`.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Leave the payload of `eXIf` and unknown chunks in the input instead of copying it into
+    /// `Info`, see [`BorrowedChunks`]. Used by [`crate::SliceDecoder`].
+    pub(crate) fn set_borrow_chunk_data(&mut self, borrow_chunk_data: bool) {
+        self.borrow_chunk_data = borrow_chunk_data;
+    }
+}
+
+/// Where the payloads of chunks are in the input when they were not copied into `Info`.
+///
+/// With [`DecodeOptions::set_borrow_chunk_data`], `Info::exif_metadata` and the `data` of each of
+/// `Info::unknown_chunks` are left empty and the byte ranges of their payloads, counted from the
+/// first byte passed to [`StreamingDecoder::update`], are recorded here instead.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BorrowedChunks {
+    pub exif_metadata: Option<Range<u64>>,
+    /// One range for each of `Info::unknown_chunks`.
+    pub unknown_chunks: Vec<Range<u64>>,
 }
 
 /// PNG StreamingDecoder (low-level interface)
@@ -555,6 +870,14 @@ pub struct StreamingDecoder {
     have_iccp: bool,
     decode_options: DecodeOptions,
     pub(crate) limits: Limits,
+    /// How many bytes of input have been consumed.
+    position: u64,
+    /// How many chunks have been started, or `None` if unknown after seeking to a frame.
+    chunks: Option<usize>,
+    /// Chunk payloads that were left in the input.
+    pub(crate) borrowed: BorrowedChunks,
+    /// The deviations that were tolerated so far.
+    warnings: Vec<DecodeWarning>,
 }
 
 struct ChunkState {
@@ -573,6 +896,12 @@ struct ChunkState {
 
     /// Whether this chunk should be skipped or decoded.
     action: ChunkAction,
+
+    /// Where the payload of the chunk is in the input.
+    input_range: Range<u64>,
+
+    /// Whether the payload is left in the input instead of being collected in `raw_bytes`.
+    borrowed: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -593,6 +922,7 @@ impl StreamingDecoder {
     pub fn new_with_options(decode_options: DecodeOptions) -> StreamingDecoder {
         let mut inflater = ZlibStream::new();
         inflater.set_ignore_adler32(decode_options.ignore_adler32);
+        inflater.set_strict(decode_options.strict);
 
         StreamingDecoder {
             state: Some(State::new_u32(U32ValueKind::Signature1stU32)),
@@ -602,6 +932,8 @@ impl StreamingDecoder {
                 remaining: 0,
                 raw_bytes: Vec::with_capacity(CHUNK_BUFFER_SIZE),
                 action: ChunkAction::Process,
+                input_range: 0..0,
+                borrowed: false,
             },
             inflater,
             info: None,
@@ -612,6 +944,10 @@ impl StreamingDecoder {
             ready_for_fdat_chunks: false,
             decode_options,
             limits: Limits { bytes: usize::MAX },
+            position: 0,
+            chunks: Some(0),
+            borrowed: BorrowedChunks::default(),
+            warnings: Vec::new(),
         }
     }
 
@@ -625,6 +961,28 @@ impl StreamingDecoder {
         self.info = None;
         self.current_seq_no = None;
         self.have_idat = false;
+        self.position = 0;
+        self.chunks = Some(0);
+        self.borrowed = BorrowedChunks::default();
+        self.warnings.clear();
+    }
+
+    /// Continues decoding at the `fcTL` chunk of an animation frame at `position` in the input.
+    ///
+    /// The header must have been decoded already. `in_idat` tells whether the image data of the
+    /// frame is in `IDAT` chunks, which are then expected to follow the `fcTL` chunk.
+    pub(crate) fn seek_to_frame(&mut self, position: u64, sequence_number: u32, in_idat: bool) {
+        self.state = Some(State::new_u32(U32ValueKind::Length));
+        self.current_chunk.type_ = chunk::fcTL;
+        self.current_chunk.remaining = 0;
+        self.current_chunk.raw_bytes.clear();
+        self.inflater.reset();
+        self.current_seq_no = sequence_number.checked_sub(1);
+        self.have_idat = !in_idat;
+        self.ready_for_idat_chunks = in_idat;
+        self.ready_for_fdat_chunks = false;
+        self.position = position;
+        self.chunks = None;
     }
 
     /// Provides access to the inner `info` field
@@ -640,6 +998,11 @@ impl StreamingDecoder {
         self.decode_options.set_ignore_iccp_chunk(ignore_iccp_chunk);
     }
 
+    pub fn set_keep_unknown_chunks(&mut self, keep_unknown_chunks: bool) {
+        self.decode_options
+            .set_keep_unknown_chunks(keep_unknown_chunks);
+    }
+
     /// Return whether the decoder is set to ignore the Adler-32 checksum.
     pub fn ignore_adler32(&self) -> bool {
         self.inflater.ignore_adler32()
@@ -672,6 +1035,58 @@ impl StreamingDecoder {
             .set_skip_ancillary_crc_failures(skip_ancillary_crc_failures)
     }
 
+    /// Whether recoverable deviations are reported as errors, see [`DecodeOptions::set_strict`].
+    pub(crate) fn strict(&self) -> bool {
+        self.decode_options.strict
+    }
+
+    /// The deviations from the specification that were tolerated so far, in input order.
+    ///
+    /// Each of them is also reported as a [`Decoded::BadAncillaryChunk`], except for
+    /// [`DecodeWarningKind::ExtraImageData`]. Only the first 256 are kept.
+    pub fn warnings(&self) -> &[DecodeWarning] {
+        &self.warnings
+    }
+
+    /// Marks the image data of the current frame as completely decompressed, so that the rest of
+    /// its `IDAT` or `fdAT` chunks may only end the zlib stream.
+    pub(crate) fn set_image_data_complete(&mut self) {
+        self.inflater.set_complete();
+    }
+
+    /// Creates an error for input at the current position that is not part of any chunk.
+    pub(crate) fn error_at_position(&self, err: FormatErrorInner) -> DecodingError {
+        DecodingError::Format(FormatError::from(err).with_location(self.position, None, None))
+    }
+
+    /// Makes `type_str` the type of the current chunk, which starts a new chunk.
+    fn start_chunk_type(&mut self, type_str: ChunkType) {
+        self.current_chunk.type_ = type_str;
+        self.chunks = self.chunks.map(|chunks| chunks + 1);
+    }
+
+    /// The type of the current chunk, if a chunk has been started.
+    fn chunk_type(&self) -> Option<ChunkType> {
+        (self.chunks != Some(0)).then_some(self.current_chunk.type_)
+    }
+
+    /// The index of the current chunk, if it is known.
+    fn chunk_index(&self) -> Option<usize> {
+        self.chunks?.checked_sub(1)
+    }
+
+    /// Records a tolerated deviation in the current chunk.
+    fn warn(&mut self, kind: DecodeWarningKind) {
+        if self.warnings.len() == MAX_WARNINGS {
+            return;
+        }
+        self.warnings.push(DecodeWarning {
+            chunk: self.current_chunk.type_,
+            offset: self.current_chunk.input_range.start.saturating_sub(8),
+            kind,
+        });
+    }
+
     /// Low level StreamingDecoder interface.
     ///
     /// Allows to stream partial data to the encoder. Returns a tuple containing the bytes that have
@@ -691,13 +1106,29 @@ impl StreamingDecoder {
         let len = buf.len();
         while !buf.is_empty() {
             let image_data = image_data.as_deref_mut();
+            // A `u32` value may have been started by a previous call.
+            let offset = match &self.state {
+                Some(State::U32 {
+                    accumulated_count, ..
+                }) => self.position - *accumulated_count as u64,
+                _ => self.position,
+            };
 
             match self.next_state(buf, image_data) {
-                Ok((bytes, Decoded::Nothing)) => buf = &buf[bytes..],
+                Ok((bytes, Decoded::Nothing)) => {
+                    self.position += bytes as u64;
+                    buf = &buf[bytes..];
+                }
                 Ok((bytes, result)) => {
+                    self.position += bytes as u64;
                     buf = &buf[bytes..];
                     return Ok((len - buf.len(), result));
                 }
+                Err(DecodingError::Format(err)) => {
+                    debug_assert!(self.state.is_none());
+                    let err = err.with_location(offset, self.chunk_type(), self.chunk_index());
+                    return Err(DecodingError::Format(err));
+                }
                 Err(err) => {
                     debug_assert!(self.state.is_none());
                     return Err(err);
@@ -767,6 +1198,8 @@ impl StreamingDecoder {
                         raw_bytes,
                         type_: _,
                         action,
+                        input_range: _,
+                        borrowed,
                     } = &mut self.current_chunk;
 
                     let buf_avail = raw_bytes.capacity() - raw_bytes.len();
@@ -778,7 +1211,7 @@ impl StreamingDecoder {
                         crc.update(buf);
                     }
 
-                    if *action == ChunkAction::Process {
+                    if *action == ChunkAction::Process && !*borrowed {
                         if raw_bytes.len() == raw_bytes.capacity() {
                             if self.limits.bytes == 0 {
                                 return Err(DecodingError::LimitsExceeded);
@@ -810,7 +1243,12 @@ impl StreamingDecoder {
                 let buf = &buf[..len];
 
                 let consumed = if let Some(image_data) = image_data {
-                    self.inflater.decompress(buf, image_data)?
+                    let extra_data = self.inflater.extra_data();
+                    let consumed = self.inflater.decompress(buf, image_data)?;
+                    if !extra_data && self.inflater.extra_data() {
+                        self.warn(DecodeWarningKind::ExtraImageData);
+                    }
+                    consumed
                 } else {
                     len
                 };
@@ -869,6 +1307,7 @@ impl StreamingDecoder {
             U32ValueKind::Type { length } => {
                 let type_str = ChunkType(bytes);
                 if self.info.is_none() && type_str != IHDR {
+                    self.start_chunk_type(type_str);
                     return Err(DecodingError::Format(
                         FormatErrorInner::ChunkBeforeIhdr { kind: type_str }.into(),
                     ));
@@ -877,7 +1316,14 @@ impl StreamingDecoder {
                     && (self.current_chunk.type_ == IDAT || self.current_chunk.type_ == chunk::fdAT)
                 {
                     let finished = match image_data {
-                        Some(image_data) => self.inflater.finish(image_data)?,
+                        Some(image_data) => {
+                            let extra_data = self.inflater.extra_data();
+                            let finished = self.inflater.finish(image_data)?;
+                            if !extra_data && self.inflater.extra_data() {
+                                self.warn(DecodeWarningKind::ExtraImageData);
+                            }
+                            finished
+                        }
                         None => true,
                     };
 
@@ -906,13 +1352,16 @@ impl StreamingDecoder {
                     }
                 }
 
-                self.current_chunk.type_ = type_str;
+                self.start_chunk_type(type_str);
                 if !self.decode_options.ignore_crc {
                     self.current_chunk.crc.reset();
                     self.current_chunk.crc.update(&type_str.0);
                 }
                 self.current_chunk.remaining = length;
                 self.current_chunk.raw_bytes.clear();
+                let data_start = self.position + consumed_bytes as u64;
+                self.current_chunk.input_range = data_start..data_start + u64::from(length);
+                self.current_chunk.borrowed = false;
 
                 self.state = match type_str {
                     chunk::fdAT => {
@@ -981,14 +1430,17 @@ impl StreamingDecoder {
                         }
                         ChunkAction::Reject => {
                             self.state = Some(State::new_u32(U32ValueKind::Length));
+                            self.warn(DecodeWarningKind::ChunkLengthWrong);
                             Ok((consumed_bytes, Decoded::BadAncillaryChunk(type_str)))
                         }
                     }
                 } else if self.decode_options.skip_ancillary_crc_failures
+                    && !self.decode_options.strict
                     && !chunk::is_critical(type_str)
                 {
                     // Ignore ancillary chunk with invalid CRC
                     self.state = Some(State::new_u32(U32ValueKind::Length));
+                    self.warn(DecodeWarningKind::CrcMismatch);
                     Ok((consumed_bytes, Decoded::BadAncillaryChunk(type_str)))
                 } else {
                     Err(DecodingError::Format(
@@ -1036,6 +1488,8 @@ impl StreamingDecoder {
     }
 
     fn start_chunk(&mut self, type_str: ChunkType, length: u32) -> Result<State, DecodingError> {
+        // Whether the payload can be referenced in the input rather than copied.
+        let mut borrowable = false;
         let target_length = match type_str {
             IHDR => 13..=13,
             chunk::PLTE => 3..=768,
@@ -1052,9 +1506,13 @@ impl StreamingDecoder {
             chunk::mDCV => 24..=24,
             chunk::cLLI => 8..=8,
             chunk::bKGD => 1..=6,
+            chunk::tIME => 7..=7,
 
             // Unbounded size chunks
-            chunk::eXIf => 0..=u32::MAX >> 1, // TODO: allow skipping.
+            chunk::eXIf => {
+                borrowable = true;
+                0..=u32::MAX >> 1 // TODO: allow skipping.
+            }
             chunk::iCCP if !self.decode_options.ignore_iccp_chunk => 0..=u32::MAX >> 1,
             chunk::tEXt if !self.decode_options.ignore_text_chunk => 0..=u32::MAX >> 1,
             chunk::zTXt if !self.decode_options.ignore_text_chunk => 0..=u32::MAX >> 1,
@@ -1067,6 +1525,15 @@ impl StreamingDecoder {
                     FormatErrorInner::UnrecognizedCriticalChunk { type_str }.into(),
                 ));
             }
+            // Known chunks that were ignored through `DecodeOptions`.
+            chunk::iCCP | chunk::tEXt | chunk::zTXt | chunk::iTXt => {
+                self.current_chunk.action = ChunkAction::Skip;
+                return Ok(State::ReadChunkData(type_str));
+            }
+            _ if self.decode_options.keep_unknown_chunks => {
+                borrowable = true;
+                0..=u32::MAX >> 1
+            }
             _ => {
                 self.current_chunk.action = ChunkAction::Skip;
                 return Ok(State::ReadChunkData(type_str));
@@ -1082,12 +1549,18 @@ impl StreamingDecoder {
                         FormatErrorInner::ChunkLengthWrong { kind: type_str }.into(),
                     ));
                 }
+                _ if self.decode_options.strict => {
+                    return Err(DecodingError::Format(
+                        FormatErrorInner::ChunkLengthWrong { kind: type_str }.into(),
+                    ));
+                }
                 _ => {
                     self.current_chunk.action = ChunkAction::Reject;
                 }
             }
         } else {
             self.current_chunk.action = ChunkAction::Process;
+            self.current_chunk.borrowed = borrowable && self.decode_options.borrow_chunk_data;
         }
 
         Ok(State::ReadChunkData(type_str))
@@ -1117,6 +1590,7 @@ impl StreamingDecoder {
             chunk::mDCV => self.parse_mdcv(),
             chunk::cLLI => self.parse_clli(),
             chunk::bKGD => self.parse_bkgd(),
+            chunk::tIME => self.parse_time(),
 
             // Ancillary chunks with unbounded size.
             chunk::eXIf => self.parse_exif(),
@@ -1126,9 +1600,13 @@ impl StreamingDecoder {
             chunk::iTXt => self.parse_itxt(),
 
             // Unrecognized chunks.
-            _ => unreachable!(
-                "Unrecognized chunk {type_str:?} should have been caught in start_chunk"
-            ),
+            _ => {
+                debug_assert!(
+                    self.decode_options.keep_unknown_chunks,
+                    "Unrecognized chunk {type_str:?} should have been caught in start_chunk"
+                );
+                self.parse_unknown(type_str)
+            }
         };
 
         parse_result = parse_result.map_err(|e| {
@@ -1148,16 +1626,17 @@ impl StreamingDecoder {
 
         match parse_result {
             Ok(()) => Ok(Decoded::ChunkComplete(type_str)),
-            Err(DecodingError::Format(_))
-                if type_str != chunk::fcTL && !chunk::is_critical(type_str) =>
+            Err(DecodingError::Format(err))
+                if type_str != chunk::fcTL
+                    && !chunk::is_critical(type_str)
+                    && !self.decode_options.strict =>
             {
                 // Ignore benign errors in most auxiliary chunks. `LimitsExceeded`, `Parameter` and
                 // other error kinds are *not* treated as benign. We don't ignore errors in `fcTL`
                 // chunks because the fallback to the static/non-animated image has to be
-                // implemented *on top* of the `StreamingDecoder` API.
-                //
-                // TODO: Consider supporting a strict mode where even benign errors are reported up.
-                // See https://github.com/image-rs/image-png/pull/569#issuecomment-2642062285
+                // implemented *on top* of the `StreamingDecoder` API. In strict mode even benign
+                // errors are reported up.
+                self.warn(DecodeWarningKind::of_error(&err));
                 Ok(Decoded::BadAncillaryChunk(type_str))
             }
             Err(e) => Err(e),
@@ -1252,6 +1731,12 @@ impl StreamingDecoder {
             // The spec says that "0 is not a valid value" for `num_frames`.
             // So let's ignore such malformed `acTL` chunks.
             if actl.num_frames == 0 {
+                if self.decode_options.strict {
+                    return Err(DecodingError::Format(
+                        FormatErrorInner::NoAnimationFrames.into(),
+                    ));
+                }
+                self.warn(DecodeWarningKind::InvalidChunk);
                 return Ok(());
             }
             info.animation_control = Some(actl);
@@ -1661,6 +2146,24 @@ impl StreamingDecoder {
         Ok(())
     }
 
+    fn parse_time(&mut self) -> Result<(), DecodingError> {
+        let info = self.info.as_mut().unwrap();
+        if info.last_modification_time.is_some() {
+            return Err(DecodingError::Format(
+                FormatErrorInner::DuplicateChunk { kind: chunk::tIME }.into(),
+            ));
+        }
+
+        let data: [u8; 7] = self.current_chunk.raw_bytes[..].try_into().map_err(|_| {
+            DecodingError::Format(FormatErrorInner::ChunkLengthWrong { kind: chunk::tIME }.into())
+        })?;
+        let time = LastModificationTime::from_be_bytes(data).ok_or_else(|| {
+            DecodingError::Format(FormatErrorInner::InvalidLastModificationTime.into())
+        })?;
+        info.last_modification_time = Some(time);
+        Ok(())
+    }
+
     fn parse_exif(&mut self) -> Result<(), DecodingError> {
         let info = self.info.as_mut().unwrap();
         if info.exif_metadata.is_some() {
@@ -1669,7 +2172,12 @@ impl StreamingDecoder {
             ));
         }
 
-        info.exif_metadata = Some(self.current_chunk.raw_bytes.clone().into());
+        if self.current_chunk.borrowed {
+            self.borrowed.exif_metadata = Some(self.current_chunk.input_range.clone());
+            info.exif_metadata = Some(Cow::Borrowed(&[]));
+        } else {
+            info.exif_metadata = Some(self.current_chunk.raw_bytes.clone().into());
+        }
         Ok(())
     }
 
@@ -1684,8 +2192,14 @@ impl StreamingDecoder {
             ))
         } else {
             self.have_iccp = true;
-            let _ = self.parse_iccp_raw();
-            Ok(())
+            match self.parse_iccp_raw() {
+                Err(DecodingError::Format(err)) if !self.decode_options.strict => {
+                    self.warn(DecodeWarningKind::of_error(&err));
+                    Ok(())
+                }
+                Err(_) if !self.decode_options.strict => Ok(()),
+                result => result,
+            }
         }
     }
 
@@ -1912,6 +2426,34 @@ impl StreamingDecoder {
         Ok(())
     }
 
+    fn parse_unknown(&mut self, type_str: ChunkType) -> Result<(), DecodingError> {
+        let data = if self.current_chunk.borrowed {
+            self.borrowed
+                .unknown_chunks
+                .push(self.current_chunk.input_range.clone());
+            Cow::Borrowed(&[][..])
+        } else {
+            let data = self.current_chunk.raw_bytes.clone();
+            self.limits.reserve_bytes(data.len())?;
+            Cow::Owned(data)
+        };
+
+        let info = self.info.as_mut().unwrap();
+        let position = if self.have_idat {
+            ChunkPosition::AfterIdat
+        } else if info.palette.is_some() {
+            ChunkPosition::BeforeIdat
+        } else {
+            ChunkPosition::BeforePlte
+        };
+        info.unknown_chunks.push(UnknownChunk {
+            type_: type_str,
+            data,
+            position,
+        });
+        Ok(())
+    }
+
     fn parse_bkgd(&mut self) -> Result<(), DecodingError> {
         let info = self.info.as_mut().unwrap();
         if info.bkgd.is_some() {
@@ -2004,8 +2546,13 @@ impl Default for StreamingDecoder {
 mod tests {
     use super::ScaledFloat;
     use super::SourceChromaticities;
+    use super::{DecodeWarning, DecodeWarningKind, FormatErrorKind};
+    use crate::chunk::ChunkType;
     use crate::test_utils::*;
-    use crate::{Decoder, DecodingError, Reader, SrgbRenderingIntent, Unit};
+    use crate::{
+        ChunkPosition, Decoder, DecodingError, LastModificationTime, Reader, SrgbRenderingIntent,
+        Unit,
+    };
     use approx::assert_relative_eq;
     use byteorder::WriteBytesExt;
     use std::borrow::Cow;
@@ -2014,7 +2561,6 @@ mod tests {
     use std::fs::File;
     use std::io::BufRead;
     use std::io::Cursor;
-    use std::io::Seek;
     use std::io::{BufReader, ErrorKind, Read, Write};
     use std::rc::Rc;
 
@@ -2527,7 +3073,7 @@ mod tests {
                          private: true, \
                          reserved: false, \
                          safecopy: false \
-             } chunk sequence",
+             } chunk sequence (at byte 133, in chunk #4 fdAT)",
             format!("{err}"),
         );
     }
@@ -2546,7 +3092,10 @@ mod tests {
         // 0-length fdAT should result in an error.
         let err = reader.next_frame(&mut buf).unwrap_err();
         assert!(matches!(&err, DecodingError::Format(_)));
-        assert_eq!("fdAT chunk shorter than 4 bytes", format!("{err}"));
+        assert_eq!(
+            "fdAT chunk shorter than 4 bytes (at byte 420, in chunk #5 fdAT)",
+            format!("{err}")
+        );
 
         // Calling `next_frame` again should return an error.  Same error as above would be nice,
         // but it is probably unnecessary and infeasible (`DecodingError` can't derive `Clone`
@@ -2574,7 +3123,10 @@ mod tests {
         // 3-bytes-long fdAT should result in an error.
         let err = reader.next_frame(&mut buf).unwrap_err();
         assert!(matches!(&err, DecodingError::Format(_)));
-        assert_eq!("fdAT chunk shorter than 4 bytes", format!("{err}"));
+        assert_eq!(
+            "fdAT chunk shorter than 4 bytes (at byte 420, in chunk #5 fdAT)",
+            format!("{err}")
+        );
     }
 
     #[test]
@@ -2696,7 +3248,7 @@ mod tests {
                          private: false, \
                          reserved: false, \
                          safecopy: false \
-             } chunk appeared before IHDR chunk",
+             } chunk appeared before IHDR chunk (at byte 12, in chunk #0 IDAT)",
             format!("{err}"),
         );
     }
@@ -2783,20 +3335,6 @@ mod tests {
             assert!(state.current_pos <= state.available_len);
         }
     }
-    impl Seek for StreamingInput {
-        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
-            let mut state = self.state.borrow_mut();
-            state.current_pos = match pos {
-                std::io::SeekFrom::Start(n) => n as usize,
-                std::io::SeekFrom::End(n) => (self.full_input.len() as i64 + n) as usize,
-                std::io::SeekFrom::Current(n) => (state.current_pos as i64 + n) as usize,
-            } as usize;
-            Ok(state.current_pos as u64)
-        }
-        fn stream_position(&mut self) -> std::io::Result<u64> {
-            Ok(self.state.borrow().current_pos as u64)
-        }
-    }
 
     /// Test resuming/retrying `Reader.next_frame` after `UnexpectedEof`.
     #[test]
@@ -2830,6 +3368,34 @@ mod tests {
         );
     }
 
+    /// Test decoding from a reader that implements `BufRead` but not `Seek`, such as stdin.
+    #[test]
+    fn test_decoding_without_seek() {
+        for path in [
+            "tests/pngsuite/basi0g01.png",
+            "tests/animated/basic_f20.png",
+        ] {
+            let png = std::fs::read(path).unwrap();
+
+            let mut expected = Vec::new();
+            let mut reader = crate::Decoder::new(Cursor::new(&png)).read_info().unwrap();
+            let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+            while reader.next_frame(&mut buf).is_ok() {
+                expected.extend_from_slice(&buf);
+            }
+
+            // `Read::chain` does not implement `Seek`.
+            let input = BufReader::new(Read::chain(&[][..], &png[..]));
+            let mut actual = Vec::new();
+            let mut reader = crate::Decoder::new(input).read_info().unwrap();
+            while reader.next_frame(&mut buf).is_ok() {
+                actual.extend_from_slice(&buf);
+            }
+            reader.finish().unwrap();
+            assert_eq!(expected, actual, "{path}");
+        }
+    }
+
     /// Test resuming/retrying `Reader.next_row` after `UnexpectedEof`.
     #[test]
     fn test_streaming_input_and_decoding_via_next_row() {
@@ -2983,7 +3549,7 @@ mod tests {
         Decoder::new(Cursor::new(png)).read_info().unwrap()
     }
 
-    fn get_fctl_sequence_number(reader: &Reader<impl BufRead + Seek>) -> u32 {
+    fn get_fctl_sequence_number(reader: &Reader<impl BufRead>) -> u32 {
         reader
             .info()
             .frame_control
@@ -3103,7 +3669,7 @@ mod tests {
         let mut buf = vec![0; reader.output_buffer_size().unwrap()];
 
         assert_eq!(get_fctl_sequence_number(&reader), 0);
-        while let Some(_) = reader.next_row().unwrap() {}
+        while reader.next_row().unwrap().is_some() {}
         assert_eq!(get_fctl_sequence_number(&reader), 0);
 
         buf.fill(0x0f);
@@ -3125,7 +3691,7 @@ mod tests {
 
         let mut rows_of_frame1 = 0;
         assert_eq!(get_fctl_sequence_number(&reader), 0);
-        while let Some(_) = reader.next_row().unwrap() {
+        while reader.next_row().unwrap().is_some() {
             rows_of_frame1 += 1;
         }
         assert_eq!(rows_of_frame1, 16);
@@ -3134,7 +3700,7 @@ mod tests {
         let mut rows_of_frame2 = 0;
         assert_eq!(reader.next_frame_info().unwrap().sequence_number, 1);
         assert_eq!(get_fctl_sequence_number(&reader), 1);
-        while let Some(_) = reader.next_row().unwrap() {
+        while reader.next_row().unwrap().is_some() {
             rows_of_frame2 += 1;
         }
         assert_eq!(rows_of_frame2, 16);
@@ -3325,7 +3891,10 @@ mod tests {
         let reader = Decoder::new(Cursor::new(png)).read_info();
         let err = reader.err().unwrap();
         assert!(matches!(&err, DecodingError::Format(_)));
-        assert_eq!("Sub frame is out-of-bounds.", format!("{err}"));
+        assert_eq!(
+            "Sub frame is out-of-bounds. (at byte 87, in chunk #2 fcTL)",
+            format!("{err}")
+        );
     }
 
     #[test]
@@ -3386,4 +3955,384 @@ mod tests {
         assert_eq!(actl.num_frames, 2);
         assert_eq!(actl.num_plays, 123);
     }
+
+    /// Decodes the first frame of `png` and checks that nothing follows it, returning the
+    /// warnings.
+    fn decode_strictly(png: &[u8], strict: bool) -> Result<Vec<DecodeWarning>, DecodingError> {
+        let mut options = crate::DecodeOptions::default();
+        options.set_strict(strict);
+        let mut reader = Decoder::new_with_options(Cursor::new(png), options).read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut buf)?;
+        reader.finish()?;
+        Ok(reader.warnings().to_vec())
+    }
+
+    fn warning(chunk: &[u8; 4], offset: u64, kind: DecodeWarningKind) -> DecodeWarning {
+        DecodeWarning {
+            chunk: ChunkType(*chunk),
+            offset,
+            kind,
+        }
+    }
+
+    /// Chunk types and data to write around the image data.
+    type Chunks<'a> = &'a [(&'a [u8; 4], &'a [u8])];
+
+    fn write_rgba8_png(before_idat: Chunks, after_idat: Chunks) -> Vec<u8> {
+        const SIZE: u32 = 4;
+        let mut png = Vec::new();
+        write_png_sig(&mut png);
+        write_rgba8_ihdr_with_width(&mut png, SIZE);
+        for (chunk_type, data) in before_idat {
+            write_chunk(&mut png, *chunk_type, data);
+        }
+        write_rgba8_idats(&mut png, SIZE, 0x1000);
+        for (chunk_type, data) in after_idat {
+            write_chunk(&mut png, *chunk_type, data);
+        }
+        write_iend(&mut png);
+        png
+    }
+
+    #[test]
+    fn test_strict_pngsuite() {
+        for entry in std::fs::read_dir("tests/pngsuite").unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap();
+            if !name.ends_with(".png") || name.starts_with('x') {
+                continue;
+            }
+            let png = std::fs::read(&path).unwrap();
+            decode_strictly(&png, true).unwrap_or_else(|err| panic!("{name}: {err}"));
+            assert_eq!(decode_strictly(&png, false).unwrap(), [], "{name}");
+        }
+    }
+
+    #[test]
+    fn test_strict_ancillary_chunks() {
+        let gama = 45455u32.to_be_bytes();
+        // The signature and `IHDR` chunk, then the `IDAT` chunk of 79 bytes.
+        let first = 8 + 25;
+        let idat_len = 12 + 79;
+        let cases: [(Chunks, Chunks, DecodeWarning); 5] = [
+            (
+                &[(b"gAMA", &gama), (b"gAMA", &gama)],
+                &[],
+                warning(b"gAMA", first + 16, DecodeWarningKind::DuplicateChunk),
+            ),
+            (
+                &[],
+                &[(b"gAMA", &gama)],
+                warning(b"gAMA", first + idat_len, DecodeWarningKind::MisplacedChunk),
+            ),
+            (
+                &[(b"sRGB", &[0, 0])],
+                &[],
+                warning(b"sRGB", first, DecodeWarningKind::ChunkLengthWrong),
+            ),
+            (
+                &[(b"tEXt", &[b'A'; 100])],
+                &[],
+                warning(b"tEXt", first, DecodeWarningKind::InvalidChunk),
+            ),
+            (
+                &[(b"acTL", &[0; 8])],
+                &[],
+                warning(b"acTL", first, DecodeWarningKind::InvalidChunk),
+            ),
+        ];
+        for (before_idat, after_idat, expected) in cases {
+            let png = write_rgba8_png(before_idat, after_idat);
+            assert_eq!(decode_strictly(&png, false).unwrap(), [expected]);
+            let err = decode_strictly(&png, true).unwrap_err();
+            assert!(matches!(err, DecodingError::Format(_)), "{err:?}");
+        }
+
+        // A bad CRC is an error even when such chunks are skipped otherwise.
+        let mut png = write_rgba8_png(&[(b"gAMA", &gama)], &[]);
+        let crc = 8 + 25 + 8 + 4;
+        png[crc] ^= 1;
+        let warnings = decode_strictly(&png, false).unwrap();
+        assert_eq!(
+            warnings,
+            [warning(b"gAMA", first, DecodeWarningKind::CrcMismatch)]
+        );
+        assert_eq!(
+            warnings[0].to_string(),
+            "Ignored chunk gAMA at offset 33: CRC mismatch."
+        );
+        let err = decode_strictly(&png, true).unwrap_err();
+        assert!(matches!(err, DecodingError::Format(_)), "{err:?}");
+    }
+
+    #[test]
+    fn test_warnings_are_capped() {
+        let gama = 45455u32.to_be_bytes();
+        let chunks = vec![(b"gAMA", &gama[..]); super::MAX_WARNINGS + 10];
+        let png = write_rgba8_png(&chunks, &[]);
+        let warnings = decode_strictly(&png, false).unwrap();
+        assert_eq!(warnings.len(), super::MAX_WARNINGS);
+        assert!(warnings
+            .iter()
+            .all(|warning| warning.kind == DecodeWarningKind::DuplicateChunk));
+    }
+
+    #[test]
+    fn test_strict_trailing_data() {
+        let mut png = write_rgba8_png(&[], &[]);
+        png.extend_from_slice(b"junk");
+        decode_strictly(&png, false).unwrap();
+        let err = decode_strictly(&png, true).unwrap_err();
+        assert_eq!(format!("{err}"), "Data after the IEND chunk. (at byte 136)");
+    }
+
+    #[test]
+    fn test_strict_extra_image_data() {
+        let expected = "IDAT or fdAT chunk has data past the end of the image or its zlib stream.";
+
+        // Data after the end of the zlib stream.
+        let mut png = Vec::new();
+        write_png_sig(&mut png);
+        write_rgba8_ihdr_with_width(&mut png, 4);
+        write_rgba8_idats(&mut png, 4, 0x1000);
+        write_chunk(&mut png, b"IDAT", b"junk");
+        write_iend(&mut png);
+        assert_eq!(
+            decode_strictly(&png, false).unwrap(),
+            [warning(
+                b"IDAT",
+                8 + 25 + 91,
+                DecodeWarningKind::ExtraImageData
+            )]
+        );
+        let err = decode_strictly(&png, true).unwrap_err();
+        assert_eq!(
+            format!("{err}"),
+            format!("{expected} (at byte 132, in chunk #2 IDAT)")
+        );
+
+        // A zlib stream with more rows than the image.
+        let mut png = Vec::new();
+        write_png_sig(&mut png);
+        write_rgba8_ihdr_with_width(&mut png, 4);
+        write_chunk(
+            &mut png,
+            b"IDAT",
+            &generate_rgba8_with_width_and_height(4, 5),
+        );
+        write_iend(&mut png);
+        assert_eq!(
+            decode_strictly(&png, false).unwrap(),
+            [warning(b"IDAT", 8 + 25, DecodeWarningKind::ExtraImageData)]
+        );
+        let err = decode_strictly(&png, true).unwrap_err();
+        assert_eq!(
+            format!("{err}"),
+            format!("{expected} (at byte 116, in chunk #1 IDAT)")
+        );
+    }
+
+    #[test]
+    fn test_format_error_location() {
+        let format_error = |png: &[u8]| match decode_strictly(png, false).unwrap_err() {
+            DecodingError::Format(err) => err,
+            err => panic!("{err:?}"),
+        };
+
+        // The CRC of `IHDR` follows its 13 bytes of data.
+        let mut png = write_rgba8_png(&[], &[]);
+        png[8 + 8 + 13] ^= 1;
+        let err = format_error(&png);
+        assert_eq!(err.offset(), Some(29));
+        assert_eq!(err.chunk_type(), Some(crate::chunk::IHDR));
+        assert_eq!(err.chunk_index(), Some(0));
+        assert!(format!("{err}").ends_with(" (at byte 29, in chunk #0 IHDR)"));
+
+        // An unknown critical chunk after a `gAMA` chunk, found at its type.
+        let png = write_rgba8_png(&[(b"gAMA", &45455u32.to_be_bytes()), (b"CRIT", &[])], &[]);
+        let err = format_error(&png);
+        assert_eq!(err.offset(), Some(8 + 25 + 16 + 4));
+        assert_eq!(err.chunk_type(), Some(ChunkType(*b"CRIT")));
+        assert_eq!(err.chunk_index(), Some(2));
+
+        // Not in a chunk.
+        let mut png = write_rgba8_png(&[], &[]);
+        png[0] = 0;
+        let err = format_error(&png);
+        assert_eq!(err.offset(), Some(0));
+        assert_eq!(err.chunk_type(), None);
+        assert_eq!(format!("{err}"), "Invalid PNG signature. (at byte 0)");
+
+        // Invalid filter types are only found after decompression.
+        let mut png = Vec::new();
+        write_png_sig(&mut png);
+        write_rgba8_ihdr_with_width(&mut png, 1);
+        let mut compressed = Vec::new();
+        let mut compressor =
+            fdeflate::StoredOnlyCompressor::new(Cursor::new(&mut compressed)).unwrap();
+        compressor.write_data(&[9, 0, 0, 0, 0]).unwrap();
+        compressor.finish().unwrap();
+        write_chunk(&mut png, b"IDAT", &compressed);
+        write_iend(&mut png);
+        let err = format_error(&png);
+        assert_eq!(err.offset(), None);
+        assert_eq!(err.chunk_type(), None);
+    }
+
+    #[test]
+    fn test_format_error_kind() {
+        let kind = |png: &[u8], strict| match decode_strictly(png, strict).unwrap_err() {
+            DecodingError::Format(err) => err.kind(),
+            err => panic!("{err:?}"),
+        };
+
+        let mut png = write_rgba8_png(&[], &[]);
+        png[1] = b'p';
+        assert_eq!(kind(&png, false), FormatErrorKind::InvalidSignature);
+
+        let mut png = write_rgba8_png(&[], &[]);
+        png[8 + 8 + 13] ^= 1;
+        assert_eq!(kind(&png, false), FormatErrorKind::CrcMismatch);
+
+        let png = write_rgba8_png(&[(b"CRIT", &[])], &[]);
+        assert_eq!(
+            kind(&png, false),
+            FormatErrorKind::UnrecognizedCriticalChunk
+        );
+
+        let png = write_rgba8_png(&[], &[(b"gAMA", &45455u32.to_be_bytes())]);
+        assert_eq!(kind(&png, true), FormatErrorKind::MisplacedChunk);
+
+        let mut png = write_rgba8_png(&[], &[]);
+        png.push(0);
+        assert_eq!(kind(&png, true), FormatErrorKind::TrailingData);
+
+        // Input that is cut short is not a format error.
+        let png = write_rgba8_png(&[], &[]);
+        let err = decode_strictly(&png[..png.len() - 20], false).unwrap_err();
+        assert!(
+            matches!(&err, DecodingError::IoError(err) if err.kind() == ErrorKind::UnexpectedEof),
+            "{err:?}"
+        );
+    }
+
+    fn write_png_with_unknown_chunks() -> Vec<u8> {
+        const SIZE: u32 = 4;
+        let mut png = Vec::new();
+        write_png_sig(&mut png);
+        write_rgba8_ihdr_with_width(&mut png, SIZE);
+        write_chunk(&mut png, b"prVW", b"before plte");
+        write_chunk(&mut png, b"PLTE", &[0, 0, 0, 255, 255, 255]);
+        write_chunk(&mut png, b"tEXt", b"key\0value");
+        write_chunk(&mut png, b"caNv", b"before idat");
+        write_chunk(
+            &mut png,
+            b"IDAT",
+            &generate_rgba8_with_width_and_height(SIZE, SIZE),
+        );
+        write_chunk(&mut png, b"vpAg", b"after idat");
+        write_iend(&mut png);
+        png
+    }
+
+    #[test]
+    fn test_unknown_chunks_skipped_by_default() {
+        let png = write_png_with_unknown_chunks();
+        let mut reader = Decoder::new(Cursor::new(png)).read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut buf).unwrap();
+        reader.finish().unwrap();
+        assert!(reader.info().unknown_chunks.is_empty());
+    }
+
+    #[test]
+    fn test_keep_unknown_chunks() {
+        let png = write_png_with_unknown_chunks();
+        let mut decoder = Decoder::new(Cursor::new(png));
+        decoder.set_keep_unknown_chunks(true);
+        decoder.set_ignore_text_chunk(true);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut buf).unwrap();
+        reader.finish().unwrap();
+
+        // The ignored `tEXt` chunk is known and must not be reported as unknown.
+        let chunks = &reader.info().unknown_chunks;
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].type_, ChunkType(*b"prVW"));
+        assert_eq!(&*chunks[0].data, b"before plte");
+        assert_eq!(chunks[0].position, ChunkPosition::BeforePlte);
+        assert_eq!(chunks[1].type_, ChunkType(*b"caNv"));
+        assert_eq!(&*chunks[1].data, b"before idat");
+        assert_eq!(chunks[1].position, ChunkPosition::BeforeIdat);
+        assert_eq!(chunks[2].type_, ChunkType(*b"vpAg"));
+        assert_eq!(&*chunks[2].data, b"after idat");
+        assert_eq!(chunks[2].position, ChunkPosition::AfterIdat);
+    }
+
+    #[test]
+    fn test_keep_unknown_chunks_limits() {
+        const SIZE: u32 = 4;
+        let mut png = Vec::new();
+        write_png_sig(&mut png);
+        write_rgba8_ihdr_with_width(&mut png, SIZE);
+        write_chunk(&mut png, b"prVW", &[0; 4096]);
+        write_chunk(
+            &mut png,
+            b"IDAT",
+            &generate_rgba8_with_width_and_height(SIZE, SIZE),
+        );
+        write_iend(&mut png);
+
+        let mut decoder = Decoder::new(Cursor::new(png));
+        decoder.set_keep_unknown_chunks(true);
+        decoder.set_limits(crate::Limits { bytes: 4096 });
+        assert!(matches!(
+            decoder.read_info(),
+            Err(DecodingError::LimitsExceeded)
+        ));
+    }
+
+    #[test]
+    fn test_time_chunk() {
+        let read_time = |path: &str| {
+            let decoder = Decoder::new(BufReader::new(File::open(path).unwrap()));
+            let reader = decoder.read_info().unwrap();
+            reader.info().last_modification_time
+        };
+
+        assert_eq!(
+            read_time("tests/pngsuite/cm0n0g04.png"),
+            LastModificationTime::new(2000, 1, 1, 12, 34, 56)
+        );
+        assert_eq!(
+            read_time("tests/pngsuite/cm7n0g04.png"),
+            LastModificationTime::new(1970, 1, 1, 0, 0, 0)
+        );
+        assert_eq!(
+            read_time("tests/pngsuite/cm9n0g04.png"),
+            LastModificationTime::new(1999, 12, 31, 23, 59, 59)
+        );
+        assert_eq!(read_time("tests/pngsuite/basn0g04.png"), None);
+    }
+
+    #[test]
+    fn test_invalid_time_chunk_is_ignored() {
+        const SIZE: u32 = 4;
+        let mut png = Vec::new();
+        write_png_sig(&mut png);
+        write_rgba8_ihdr_with_width(&mut png, SIZE);
+        // February 30th.
+        write_chunk(&mut png, b"tIME", &[0x07, 0xd0, 2, 30, 0, 0, 0]);
+        write_chunk(
+            &mut png,
+            b"IDAT",
+            &generate_rgba8_with_width_and_height(SIZE, SIZE),
+        );
+        write_iend(&mut png);
+
+        let reader = Decoder::new(Cursor::new(png)).read_info().unwrap();
+        assert_eq!(reader.info().last_modification_time, None);
+    }
 }
@@ -1,9 +1,20 @@
 //! Transforming a decompressed, unfiltered row into the final output.
 
+mod color;
+mod float;
+mod gamma;
+mod layout;
 mod palette;
+mod premultiply;
 
-use crate::{BitDepth, ColorType, DecodingError, Info, Transformations};
+use crate::{BitDepth, ColorType, DecodingError, Info, ScaledFloat, Transformations};
 
+use self::color::ColorCorrection;
+use self::float::FloatConversion;
+use self::gamma::GammaCorrection;
+use self::layout::ChannelLayout;
+
+use super::expanded_color_type;
 use super::stream::FormatErrorInner;
 
 /// Type of a function that can transform a decompressed, unfiltered row (the
@@ -16,16 +27,93 @@ pub type TransformFn = Box<dyn Fn(&[u8], &mut [u8], &Info) + Send + Sync>;
 
 /// Returns a transformation function that should be applied to image rows based
 /// on 1) decoded image metadata (`info`) and 2) the transformations requested
-/// by the crate client (`transform`).
+/// by the crate client (`transform`, and `display_gamma` for [`Transformations::GAMMA`]).
 pub fn create_transform_fn(
     info: &Info,
     transform: Transformations,
+    display_gamma: ScaledFloat,
+) -> Result<TransformFn, DecodingError> {
+    let float = FloatConversion::new(info, transform);
+    let correction = if transform.contains(Transformations::LINEAR) {
+        // The samples are linearized when they are converted to floating point.
+        None
+    } else if transform.contains(Transformations::SRGB) {
+        ColorCorrection::to_srgb(info, transform)
+    } else if transform.contains(Transformations::GAMMA) {
+        GammaCorrection::new(info, transform, display_gamma).map(ColorCorrection::Samples)
+    } else {
+        None
+    };
+    // Floating point samples are premultiplied when they are converted.
+    let mut premultiply =
+        premultiply::create_premultiply_fn(info, transform).filter(|_| float.is_none());
+    // An expanded palette is premultiplied in its lookup table, unless the colors are corrected
+    // after the expansion.
+    let premultiply_palette =
+        premultiply.is_some() && info.color_type == ColorType::Indexed && correction.is_none();
+    if premultiply_palette {
+        premultiply = None;
+    }
+    let transform_fn = create_sample_transform_fn(info, transform, premultiply_palette)?;
+    let layout = ChannelLayout::new(info, transform);
+    let (color_type, bit_depth) = expanded_color_type(info, transform);
+    let swap_bytes = transform.contains(Transformations::NATIVE_ENDIAN)
+        && float.is_none()
+        && bit_depth == BitDepth::Sixteen
+        && cfg!(target_endian = "little");
+    if correction.is_none()
+        && premultiply.is_none()
+        && float.is_none()
+        && layout.is_none()
+        && !swap_bytes
+    {
+        return Ok(transform_fn);
+    }
+
+    let input_bits = info.color_type.samples() * info.bit_depth as usize;
+    let sample_bits = color_type.samples() * bit_depth as usize;
+    let float_bits = float
+        .as_ref()
+        .map_or(sample_bits, FloatConversion::bits_per_pixel);
+    let output_bits = layout
+        .as_ref()
+        .map_or(float_bits, ChannelLayout::bits_per_pixel);
+    Ok(Box::new(move |row, output_buffer, info| {
+        // The samples are transformed into the front of the output, before they are widened and
+        // a filler is added.
+        let pixels = (row.len() * 8 / input_bits).min(output_buffer.len() * 8 / output_bits);
+        let samples = &mut output_buffer[..(pixels * sample_bits).div_ceil(8)];
+        transform_fn(row, samples, info);
+        if let Some(correction) = &correction {
+            correction.apply(samples);
+        }
+        if let Some(premultiply) = premultiply {
+            premultiply(samples);
+        }
+        if let Some(float) = &float {
+            float.apply(pixels, output_buffer);
+        }
+        if let Some(layout) = &layout {
+            layout.apply(pixels, output_buffer);
+        }
+        if swap_bytes {
+            for sample in output_buffer[..pixels * output_bits / 8].chunks_exact_mut(2) {
+                sample.swap(0, 1);
+            }
+        }
+    }))
+}
+
+/// Returns the transformation of the color type and depth of the samples.
+fn create_sample_transform_fn(
+    info: &Info,
+    transform: Transformations,
+    premultiply_palette: bool,
 ) -> Result<TransformFn, DecodingError> {
     let color_type = info.color_type;
     let bit_depth = info.bit_depth as u8;
     let trns = info.trns.is_some() || transform.contains(Transformations::ALPHA);
-    let expand =
-        transform.contains(Transformations::EXPAND) || transform.contains(Transformations::ALPHA);
+    let expand = transform.intersects(Transformations::EXPANSION);
     let strip16 = bit_depth == 16 && transform.contains(Transformations::STRIP_16);
     match color_type {
         ColorType::Indexed if expand => {
@@ -44,7 +132,7 @@ pub fn create_transform_fn(
                 ))
             } else {
                 Ok(if trns {
-                    palette::create_expansion_into_rgba8(info)
+                    palette::create_expansion_into_rgba8(info, premultiply_palette)
                 } else {
                     palette::create_expansion_into_rgb8(info)
                 })
@@ -0,0 +1,670 @@
+//! Conversion of decoded colors to sRGB and linear sRGB, see [`Transformations::SRGB`] and
+//! [`Transformations::LINEAR`].
+
+use super::gamma::GammaCorrection;
+use crate::common::{
+    BitDepth, ColorType, Info, ScaledFloat, SourceChromaticities, Transformations,
+};
+use crate::decoder::expanded_color_type;
+
+/// Matrices are applied to column vectors of linear RGB or XYZ.
+type Matrix = [[f64; 3]; 3];
+
+/// The reference white of SDR content in HDR signals in cd/m², see ITU-R BT.2408.
+const REFERENCE_WHITE: f64 = 203.0;
+
+/// The nominal peak luminance of an HLG display in cd/m².
+const HLG_PEAK: f64 = 1000.0;
+
+/// The transfer function that encodes linear light into samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Transfer {
+    /// The piecewise curve of IEC 61966-2-1.
+    Srgb,
+    /// Samples are linear light raised to `1 / exponent`.
+    Power(f64),
+    Linear,
+    /// The curve of ITU-R BT.709, decoded with its inverse.
+    Bt709,
+    /// The perceptual quantizer of SMPTE ST 2084.
+    Pq,
+    /// Hybrid log-gamma of ITU-R BT.2100, decoded to scene light.
+    Hlg,
+}
+
+impl Transfer {
+    /// The transfer characteristics of ITU-T H.273 that can be decoded.
+    fn from_cicp(code: u8) -> Option<Self> {
+        Some(match code {
+            1 | 6 | 14 | 15 => Transfer::Bt709,
+            4 => Transfer::Power(2.2),
+            5 => Transfer::Power(2.8),
+            8 => Transfer::Linear,
+            13 => Transfer::Srgb,
+            16 => Transfer::Pq,
+            18 => Transfer::Hlg,
+            _ => return None,
+        })
+    }
+
+    /// Decodes a normalized sample into linear light, where `1.0` is the reference white. HDR
+    /// samples may decode to more than that.
+    fn decode(self, sample: f64) -> f64 {
+        match self {
+            Transfer::Srgb => {
+                if sample <= 0.04045 {
+                    sample / 12.92
+                } else {
+                    ((sample + 0.055) / 1.055).powf(2.4)
+                }
+            }
+            Transfer::Power(exponent) => sample.powf(exponent),
+            Transfer::Linear => sample,
+            Transfer::Bt709 => {
+                if sample < 0.081 {
+                    sample / 4.5
+                } else {
+                    ((sample + 0.099) / 1.099).powf(1.0 / 0.45)
+                }
+            }
+            Transfer::Pq => {
+                const M1: f64 = 0.1593017578125;
+                const M2: f64 = 78.84375;
+                const C1: f64 = 0.8359375;
+                const C2: f64 = 18.8515625;
+                const C3: f64 = 18.6875;
+                let p = sample.powf(1.0 / M2);
+                let luminance = ((p - C1).max(0.0) / (C2 - C3 * p)).powf(1.0 / M1) * 10000.0;
+                luminance / REFERENCE_WHITE
+            }
+            Transfer::Hlg => {
+                const A: f64 = 0.17883277;
+                const B: f64 = 0.28466892;
+                const C: f64 = 0.55991073;
+                if sample <= 0.5 {
+                    sample * sample / 3.0
+                } else {
+                    (((sample - C) / A).exp() + B) / 12.0
+                }
+            }
+        }
+    }
+}
+
+/// Encodes linear light with the sRGB transfer function, clipping it to `0.0..=1.0`.
+fn srgb_encode(linear: f64) -> f64 {
+    let linear = linear.clamp(0.0, 1.0);
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The color space that samples are encoded in, relative to sRGB.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct ColorSpace {
+    transfer: Transfer,
+    /// Converts linear RGB of this color space to linear sRGB, `None` for the primaries and
+    /// white point of sRGB.
+    matrix: Option<Matrix>,
+    /// The luminance of each primary, for the system gamma of HLG.
+    luminance: [f64; 3],
+}
+
+impl ColorSpace {
+    const SRGB: ColorSpace = ColorSpace {
+        transfer: Transfer::Srgb,
+        matrix: None,
+        luminance: [0.2126, 0.7152, 0.0722],
+    };
+
+    /// The color space of the samples of an image.
+    ///
+    /// A `cICP` chunk takes precedence over an `sRGB` chunk, which takes precedence over `cHRM`
+    /// and `gAMA`. Missing chromaticities are those of sRGB and a missing gamma is the curve of
+    /// sRGB. ICC profiles are not interpreted.
+    pub fn of_image(info: &Info) -> Self {
+        if let Some(cicp) = info.coding_independent_code_points {
+            // Narrow range and YCbCr images are not valid PNG.
+            if cicp.matrix_coefficients == 0 && cicp.is_video_full_range_image {
+                if let Some(space) = Self::from_cicp(cicp.color_primaries, cicp.transfer_function) {
+                    return space;
+                }
+            }
+        }
+        if info.srgb.is_some() {
+            return ColorSpace::SRGB;
+        }
+
+        let mut space = info
+            .chrm_chunk
+            .as_ref()
+            .and_then(Self::from_chromaticities)
+            .unwrap_or(ColorSpace::SRGB);
+        if let Some(gamma) = info.gama_chunk {
+            space.transfer = Transfer::Power(1.0 / f64::from(gamma.into_value()));
+        }
+        space
+    }
+
+    fn from_cicp(primaries: u8, transfer: u8) -> Option<Self> {
+        let transfer = Transfer::from_cicp(transfer)?;
+        let chromaticities = match primaries {
+            1 => crate::srgb::substitute_chromaticities(),
+            // ITU-R BT.2020
+            9 => SourceChromaticities::new(
+                (0.3127, 0.3290),
+                (0.708, 0.292),
+                (0.170, 0.797),
+                (0.131, 0.046),
+            ),
+            // Display P3
+            12 => SourceChromaticities::new(
+                (0.3127, 0.3290),
+                (0.680, 0.320),
+                (0.265, 0.690),
+                (0.150, 0.060),
+            ),
+            _ => return None,
+        };
+        let mut space = Self::from_chromaticities(&chromaticities)?;
+        space.transfer = transfer;
+        Some(space)
+    }
+
+    /// Returns the color space with the sRGB curve and the given primaries and white point, or
+    /// `None` if they do not span a color space.
+    fn from_chromaticities(chromaticities: &SourceChromaticities) -> Option<Self> {
+        let point = |(x, y): (ScaledFloat, ScaledFloat)| {
+            (f64::from(x.into_value()), f64::from(y.into_value()))
+        };
+        let to_xyz = rgb_to_xyz(
+            [
+                point(chromaticities.red),
+                point(chromaticities.green),
+                point(chromaticities.blue),
+            ],
+            point(chromaticities.white),
+        )?;
+
+        let srgb = crate::srgb::substitute_chromaticities();
+        let srgb_to_xyz = rgb_to_xyz(
+            [point(srgb.red), point(srgb.green), point(srgb.blue)],
+            point(srgb.white),
+        )
+        .unwrap();
+        let adaptation = bradford(
+            xy_to_xyz(point(chromaticities.white))?,
+            xy_to_xyz(point(srgb.white)).unwrap(),
+        )?;
+        let matrix = multiply(
+            &invert(&srgb_to_xyz).unwrap(),
+            &multiply(&adaptation, &to_xyz),
+        );
+
+        let identity = (0..3)
+            .all(|i| (0..3).all(|j| (matrix[i][j] - if i == j { 1.0 } else { 0.0 }).abs() < 1e-4));
+        Some(ColorSpace {
+            transfer: Transfer::Srgb,
+            matrix: (!identity).then_some(matrix),
+            luminance: to_xyz[1],
+        })
+    }
+
+    fn is_srgb(&self) -> bool {
+        self.transfer == Transfer::Srgb && self.matrix.is_none()
+    }
+
+    /// Whether each sample converts on its own, without the other samples of the pixel.
+    fn per_sample(&self, color_type: ColorType) -> bool {
+        match color_type {
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => true,
+            _ => self.matrix.is_none() && self.transfer != Transfer::Hlg,
+        }
+    }
+
+    /// Converts a decoded gray sample to linear sRGB.
+    fn gray_to_linear(&self, gray: f64) -> f64 {
+        if self.transfer == Transfer::Hlg {
+            gray * hlg_gain(gray)
+        } else {
+            gray
+        }
+    }
+
+    /// Converts decoded RGB samples to linear sRGB.
+    fn rgb_to_linear(&self, mut rgb: [f64; 3]) -> [f64; 3] {
+        if self.transfer == Transfer::Hlg {
+            let luminance = (0..3).map(|i| self.luminance[i] * rgb[i]).sum();
+            let gain = hlg_gain(luminance);
+            rgb = rgb.map(|sample| sample * gain);
+        }
+        match &self.matrix {
+            Some(matrix) => apply(matrix, rgb),
+            None => rgb,
+        }
+    }
+}
+
+/// The factor of the HLG system gamma that turns scene light of the given luminance into display
+/// light relative to the reference white.
+fn hlg_gain(luminance: f64) -> f64 {
+    if luminance <= 0.0 {
+        0.0
+    } else {
+        HLG_PEAK / REFERENCE_WHITE * luminance.powf(0.2)
+    }
+}
+
+/// Returns the XYZ of a chromaticity with a luminance of 1.
+fn xy_to_xyz((x, y): (f64, f64)) -> Option<[f64; 3]> {
+    (y > 0.0).then(|| [x / y, 1.0, (1.0 - x - y) / y])
+}
+
+/// Returns the matrix from linear RGB to XYZ, where the white point has a luminance of 1.
+fn rgb_to_xyz(primaries: [(f64, f64); 3], white: (f64, f64)) -> Option<Matrix> {
+    let [r, g, b] = [
+        xy_to_xyz(primaries[0])?,
+        xy_to_xyz(primaries[1])?,
+        xy_to_xyz(primaries[2])?,
+    ];
+    let columns = [[r[0], g[0], b[0]], [r[1], g[1], b[1]], [r[2], g[2], b[2]]];
+    let scale = apply(&invert(&columns)?, xy_to_xyz(white)?);
+    Some(columns.map(|row| [row[0] * scale[0], row[1] * scale[1], row[2] * scale[2]]))
+}
+
+/// Returns the Bradford chromatic adaptation between two white points.
+fn bradford(from: [f64; 3], to: [f64; 3]) -> Option<Matrix> {
+    const CONE: Matrix = [
+        [0.8951, 0.2664, -0.1614],
+        [-0.7502, 1.7135, 0.0367],
+        [0.0389, -0.0685, 1.0296],
+    ];
+    let (from, to) = (apply(&CONE, from), apply(&CONE, to));
+    let mut scale = [[0.0; 3]; 3];
+    for i in 0..3 {
+        if from[i] == 0.0 {
+            return None;
+        }
+        scale[i][i] = to[i] / from[i];
+    }
+    Some(multiply(&invert(&CONE)?, &multiply(&scale, &CONE)))
+}
+
+fn apply(m: &Matrix, v: [f64; 3]) -> [f64; 3] {
+    m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut product = [[0.0; 3]; 3];
+    for (i, row) in product.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    product
+}
+
+fn invert(m: &Matrix) -> Option<Matrix> {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let det: f64 = (0..3).map(|j| m[0][j] * cofactor(0, j)).sum();
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let mut inverse = [[0.0; 3]; 3];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = cofactor(j, i) / det;
+        }
+    }
+    Some(inverse)
+}
+
+/// A correction of the color samples of transformed rows.
+pub(super) enum ColorCorrection {
+    /// Each sample is corrected on its own with a lookup table.
+    Samples(GammaCorrection),
+    /// The samples of a pixel are converted together.
+    Pixels(PixelConversion),
+}
+
+impl ColorCorrection {
+    /// Returns the conversion of the output of `transform` to sRGB, or `None` if the image is
+    /// already in sRGB or the output is indexed.
+    pub fn to_srgb(info: &Info, transform: Transformations) -> Option<Self> {
+        let space = ColorSpace::of_image(info);
+        if space.is_srgb() {
+            return None;
+        }
+        let (color_type, bit_depth) = expanded_color_type(info, transform);
+        if color_type == ColorType::Indexed {
+            return None;
+        }
+        if space.per_sample(color_type) {
+            // Gray has no primaries, only its transfer function is converted.
+            let curve = |sample| srgb_encode(space.gray_to_linear(space.transfer.decode(sample)));
+            GammaCorrection::with_curve(info, transform, curve).map(ColorCorrection::Samples)
+        } else {
+            Some(ColorCorrection::Pixels(PixelConversion::new(
+                space, color_type, bit_depth,
+            )))
+        }
+    }
+
+    /// Corrects the color samples of a transformed row in place.
+    pub fn apply(&self, row: &mut [u8]) {
+        match self {
+            ColorCorrection::Samples(correction) => correction.apply(row),
+            ColorCorrection::Pixels(conversion) => conversion.apply(row),
+        }
+    }
+}
+
+/// Converts RGB pixels of 8 or 16 bit samples to sRGB.
+pub(super) struct PixelConversion {
+    space: ColorSpace,
+    /// Whether the output has 16 bit samples.
+    sixteen: bool,
+    /// The number of samples per pixel of the output.
+    samples: usize,
+    /// Decodes each output sample value to linear light.
+    decode: Vec<f32>,
+    /// Encodes linear light, quantized to 16 bits, to 8 bit sRGB samples.
+    encode: Vec<u8>,
+}
+
+impl PixelConversion {
+    fn new(space: ColorSpace, color_type: ColorType, bit_depth: BitDepth) -> Self {
+        let sixteen = bit_depth == BitDepth::Sixteen;
+        PixelConversion {
+            space,
+            sixteen,
+            samples: color_type.samples(),
+            decode: decode_table(space.transfer, bit_depth),
+            encode: if sixteen {
+                Vec::new()
+            } else {
+                (0..=u16::MAX)
+                    .map(|linear| (srgb_encode(f64::from(linear) / 65535.0) * 255.0).round() as u8)
+                    .collect()
+            },
+        }
+    }
+
+    fn apply(&self, row: &mut [u8]) {
+        if self.sixteen {
+            for pixel in row.chunks_exact_mut(self.samples * 2) {
+                let sample = |i: usize| u16::from_be_bytes([pixel[2 * i], pixel[2 * i + 1]]);
+                let rgb = self.to_linear([sample(0), sample(1), sample(2)]);
+                for (i, linear) in rgb.into_iter().enumerate() {
+                    let encoded = (srgb_encode(linear) * 65535.0).round() as u16;
+                    pixel[2 * i..][..2].copy_from_slice(&encoded.to_be_bytes());
+                }
+            }
+        } else {
+            for pixel in row.chunks_exact_mut(self.samples) {
+                let rgb = self.to_linear([pixel[0], pixel[1], pixel[2]].map(u16::from));
+                for (sample, linear) in pixel.iter_mut().zip(rgb) {
+                    let quantized = (linear.clamp(0.0, 1.0) * 65535.0).round() as usize;
+                    *sample = self.encode[quantized];
+                }
+            }
+        }
+    }
+
+    fn to_linear(&self, rgb: [u16; 3]) -> [f64; 3] {
+        self.space
+            .rgb_to_linear(rgb.map(|sample| f64::from(self.decode[usize::from(sample)])))
+    }
+}
+
+/// Returns the linear light of each sample value of the bit depth.
+fn decode_table(transfer: Transfer, bit_depth: BitDepth) -> Vec<f32> {
+    let max = (1u32 << bit_depth as u8) - 1;
+    (0..=max)
+        .map(|sample| transfer.decode(f64::from(sample) / f64::from(max)) as f32)
+        .collect()
+}
+
+/// Converts pixels of 8 or 16 bit samples to linear sRGB.
+///
+/// Alpha samples are normalized to `0.0..=1.0`. Color samples are not clipped, so colors outside
+/// the gamut of sRGB have negative samples and HDR highlights exceed `1.0`.
+pub(super) struct Linearization {
+    space: ColorSpace,
+    color_type: ColorType,
+    /// Decodes each sample value to linear light.
+    decode: Vec<f32>,
+    /// The largest sample value.
+    max: f32,
+}
+
+impl Linearization {
+    pub fn new(space: ColorSpace, color_type: ColorType, bit_depth: BitDepth) -> Self {
+        Linearization {
+            space,
+            color_type,
+            decode: decode_table(space.transfer, bit_depth),
+            max: ((1u32 << bit_depth as u8) - 1) as f32,
+        }
+    }
+
+    /// Converts the sample values of a pixel into `out`.
+    pub fn apply(&self, pixel: &[u16], out: &mut [f32]) {
+        let decode = |sample: u16| f64::from(self.decode[usize::from(sample)]);
+        match self.color_type {
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => {
+                out[0] = self.space.gray_to_linear(decode(pixel[0])) as f32;
+            }
+            _ => {
+                let rgb = self
+                    .space
+                    .rgb_to_linear([0, 1, 2].map(|i| decode(pixel[i])));
+                for (out, linear) in out.iter_mut().zip(rgb) {
+                    *out = linear as f32;
+                }
+            }
+        }
+        if matches!(self.color_type, ColorType::GrayscaleAlpha | ColorType::Rgba) {
+            let samples = self.color_type.samples();
+            out[samples - 1] = f32::from(pixel[samples - 1]) / self.max;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{ColorCorrection, ColorSpace, Linearization, PixelConversion, Transfer};
+    use crate::test_codec::{decode_png, encode_png};
+    use crate::{
+        BitDepth, CodingIndependentCodePoints, ColorType, Decoder, Info, ScaledFloat,
+        SourceChromaticities, SrgbRenderingIntent, Transformations,
+    };
+
+    fn assert_close(actual: [f64; 3], expected: [f64; 3], tolerance: f64) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() <= tolerance, "{actual:?} != {expected:?}");
+        }
+    }
+
+    fn cicp(color_primaries: u8, transfer_function: u8) -> CodingIndependentCodePoints {
+        CodingIndependentCodePoints {
+            color_primaries,
+            transfer_function,
+            matrix_coefficients: 0,
+            is_video_full_range_image: true,
+        }
+    }
+
+    fn decode_linear(png: &[u8]) -> Vec<f32> {
+        let mut reader = Decoder::new(Cursor::new(png)).read_info().unwrap();
+        let (width, height) = reader.info().size();
+        let samples = reader.output_color_type().0.samples().max(3);
+        let mut buf = vec![0.0; (width * height) as usize * samples];
+        let info = reader.next_frame_linear_srgb(&mut buf).unwrap();
+        buf.truncate((width * height) as usize * info.color_type.samples());
+        buf
+    }
+
+    #[test]
+    fn srgb_chromaticities_have_no_matrix() {
+        let space =
+            ColorSpace::from_chromaticities(&crate::srgb::substitute_chromaticities()).unwrap();
+        assert!(space.is_srgb());
+        assert_close(space.luminance, [0.2126, 0.7152, 0.0722], 1e-3);
+    }
+
+    #[test]
+    fn wide_gamut_matrices() {
+        // The conversions of ITU-R BT.2087 and of Display P3.
+        let bt2020 = ColorSpace::from_cicp(9, 8).unwrap();
+        assert_close(bt2020.matrix.unwrap()[0], [1.6605, -0.5876, -0.0728], 1e-3);
+        assert_close(bt2020.matrix.unwrap()[1], [-0.1246, 1.1329, -0.0083], 1e-3);
+        let p3 = ColorSpace::from_cicp(12, 13).unwrap();
+        assert_close(p3.matrix.unwrap()[0], [1.2249, -0.2247, 0.0], 1e-3);
+        assert_eq!(p3.transfer, Transfer::Srgb);
+
+        assert!(ColorSpace::from_cicp(2, 13).is_none());
+        assert!(ColorSpace::from_cicp(9, 2).is_none());
+    }
+
+    #[test]
+    fn white_point_is_adapted() {
+        let d50 =
+            SourceChromaticities::new((0.3457, 0.3585), (0.64, 0.33), (0.30, 0.60), (0.15, 0.06));
+        let space = ColorSpace::from_chromaticities(&d50).unwrap();
+        assert_close(space.rgb_to_linear([1.0; 3]), [1.0; 3], 1e-3);
+        assert!(space.rgb_to_linear([1.0, 0.0, 0.0])[2] > 0.0);
+    }
+
+    #[test]
+    fn hdr_reference_white() {
+        // 203 cd/m² in PQ, and 75% HLG, are the reference white.
+        assert!((Transfer::Pq.decode(0.58069) - 1.0).abs() < 1e-3);
+        let hlg = ColorSpace::from_cicp(9, 18).unwrap();
+        let white = hlg.gray_to_linear(Transfer::Hlg.decode(0.75));
+        assert!((white - 1.0).abs() < 0.01, "{white}");
+        assert_close(
+            hlg.rgb_to_linear([Transfer::Hlg.decode(0.75); 3]),
+            [white; 3],
+            1e-3,
+        );
+    }
+
+    #[test]
+    fn srgb_needs_no_correction() {
+        let mut info = Info::with_size(1, 1);
+        info.color_type = ColorType::Rgb;
+        assert!(ColorCorrection::to_srgb(&info, Transformations::SRGB).is_none());
+        info.srgb = Some(SrgbRenderingIntent::Perceptual);
+        info.gama_chunk = Some(ScaledFloat::new(1.0));
+        assert!(ColorCorrection::to_srgb(&info, Transformations::SRGB).is_none());
+
+        info.srgb = None;
+        assert!(ColorCorrection::to_srgb(&info, Transformations::SRGB).is_some());
+        info.coding_independent_code_points = Some(cicp(1, 13));
+        assert!(ColorCorrection::to_srgb(&info, Transformations::SRGB).is_none());
+        info.coding_independent_code_points = None;
+        info.color_type = ColorType::Indexed;
+        assert!(ColorCorrection::to_srgb(&info, Transformations::SRGB).is_none());
+    }
+
+    #[test]
+    fn convert_wide_gamut_pixels() {
+        let space = ColorSpace::from_cicp(9, 13).unwrap();
+        // white, gray, red, and a green that is outside of sRGB
+        let data = [
+            [255, 255, 255, 255],
+            [128, 128, 128, 128],
+            [255, 0, 0, 64],
+            [0, 255, 0, 0],
+        ]
+        .concat();
+
+        let mut converted = data.clone();
+        PixelConversion::new(space, ColorType::Rgba, BitDepth::Eight).apply(&mut converted);
+        assert_eq!(&converted[..8], &data[..8]);
+        // Red of BT.2020 is more saturated than red of sRGB, so it clips.
+        assert_eq!(&converted[8..], [255, 0, 0, 64, 0, 255, 0, 0]);
+
+        let data16: Vec<u8> = data.iter().flat_map(|&v| [v, v]).collect();
+        let mut converted = data16.clone();
+        PixelConversion::new(space, ColorType::Rgba, BitDepth::Sixteen).apply(&mut converted);
+        assert_eq!(&converted[..16], &data16[..16]);
+        assert_eq!(&converted[16..24], [255, 255, 0, 0, 0, 0, 64, 64]);
+    }
+
+    #[test]
+    fn linearize_wide_gamut_pixels() {
+        let space = ColorSpace::from_cicp(9, 13).unwrap();
+        let linearization = Linearization::new(space, ColorType::Rgba, BitDepth::Eight);
+        let mut linear = [0.0; 4];
+        linearization.apply(&[255, 0, 0, 64], &mut linear);
+        // Colors outside of sRGB are not clipped.
+        assert_close(
+            [linear[0], linear[1], linear[2]].map(f64::from),
+            [1.6605, -0.1246, -0.0182],
+            1e-3,
+        );
+        assert_eq!(linear[3], 64.0 / 255.0);
+
+        let linearization =
+            Linearization::new(ColorSpace::SRGB, ColorType::Grayscale, BitDepth::Sixteen);
+        for sample in [0, 1000, 40000, 65535] {
+            linearization.apply(&[sample], &mut linear);
+            let expected = Transfer::Srgb.decode(f64::from(sample) / 65535.0);
+            assert!((f64::from(linear[0]) - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn gamma_only_converts_the_curve() {
+        let data: Vec<u8> = (0..=255).collect();
+        let format = (ColorType::Grayscale, BitDepth::Eight);
+        let png = encode_png((256, 1), format, &data, |encoder| {
+            encoder.set_source_gamma(ScaledFloat::new(1.0));
+        });
+        let (_, converted) = decode_png(&png, Transformations::SRGB);
+        for (&sample, &converted) in data.iter().zip(&converted) {
+            let expected = super::srgb_encode(f64::from(sample) / 255.0) * 255.0;
+            assert!((f64::from(converted) - expected).abs() <= 0.5);
+        }
+
+        let linear = decode_linear(&png);
+        for (&sample, &linear) in data.iter().zip(&linear) {
+            assert!((linear - f32::from(sample) / 255.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn convert_indexed_after_expansion() {
+        let format = (ColorType::Indexed, BitDepth::Eight);
+        let png = encode_png((2, 1), format, &[0, 1], |encoder| {
+            encoder.set_palette(vec![0, 0, 0, 255, 255, 255]);
+            encoder.set_source_gamma(ScaledFloat::new(1.0 / 2.2));
+        });
+
+        // Indices are not converted.
+        assert_eq!(decode_png(&png, Transformations::SRGB).1, [0, 1]);
+        let expand = Transformations::SRGB | Transformations::EXPAND;
+        assert_eq!(decode_png(&png, expand).1, [0, 0, 0, 255, 255, 255]);
+        assert_eq!(decode_linear(&png), [0.0, 0.0, 0.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn pq_image() {
+        let png = std::fs::read("tests/bugfixes/cicp_pq.png").unwrap();
+        // The image is a red of peak brightness, which is far brighter than the reference white.
+        let linear = decode_linear(&png);
+        assert!(linear.chunks_exact(3).all(|rgb| rgb[0] > 10.0));
+        let (_, converted) = decode_png(&png, Transformations::SRGB);
+        assert!(converted.chunks_exact(3).all(|rgb| rgb == [255, 0, 0]));
+    }
+}
@@ -0,0 +1,296 @@
+//! Converting transformed samples to floating point, see [`Transformations::F32`] and
+//! [`Transformations::LINEAR`].
+
+use super::color::{ColorSpace, Linearization};
+use crate::common::{ColorType, Info, Transformations};
+use crate::decoder::expanded_color_type;
+
+/// Widens each 8 or 16 bit sample of a row to a native endian `f32` in place.
+pub(super) struct FloatConversion {
+    /// Whether the input has 16 bit samples.
+    sixteen: bool,
+    /// The number of samples per pixel.
+    samples: usize,
+    /// Whether the color samples are multiplied by the alpha sample.
+    premultiply: bool,
+    /// Converts the samples to linear sRGB instead of only normalizing them.
+    linear: Option<Linearization>,
+}
+
+impl FloatConversion {
+    /// Returns the conversion requested by `transform`, or `None` if it keeps integer samples.
+    pub fn new(info: &Info, transform: Transformations) -> Option<Self> {
+        if !transform.intersects(Transformations::F32 | Transformations::LINEAR) {
+            return None;
+        }
+        // Both imply expansion, so that there are no packed samples or palette indices.
+        let (color_type, bit_depth) = expanded_color_type(info, transform);
+        let alpha = matches!(color_type, ColorType::GrayscaleAlpha | ColorType::Rgba);
+        Some(FloatConversion {
+            sixteen: bit_depth as u8 == 16,
+            samples: color_type.samples(),
+            premultiply: alpha && transform.contains(Transformations::PREMULTIPLY),
+            linear: transform
+                .contains(Transformations::LINEAR)
+                .then(|| Linearization::new(ColorSpace::of_image(info), color_type, bit_depth)),
+        })
+    }
+
+    /// The number of bits per pixel of the converted row.
+    pub fn bits_per_pixel(&self) -> usize {
+        self.samples * 32
+    }
+
+    /// Converts the first `pixels` pixels of `row`, which starts with the integer samples.
+    pub fn apply(&self, pixels: usize, row: &mut [u8]) {
+        let samples = self.samples;
+        let input_bytes = samples << usize::from(self.sixteen);
+        let max = if self.sixteen { 65535.0 } else { 255.0 };
+        let mut values = [0u16; 4];
+        let mut floats = [0f32; 4];
+
+        // Widen the pixels from the back, so that none is overwritten before it is read.
+        for idx in (0..pixels).rev() {
+            let input = &row[idx * input_bytes..][..input_bytes];
+            for (i, value) in values[..samples].iter_mut().enumerate() {
+                *value = if self.sixteen {
+                    u16::from_be_bytes([input[2 * i], input[2 * i + 1]])
+                } else {
+                    u16::from(input[i])
+                };
+            }
+
+            match &self.linear {
+                Some(linear) => linear.apply(&values[..samples], &mut floats[..samples]),
+                None => {
+                    for (float, &value) in floats.iter_mut().zip(&values[..samples]) {
+                        *float = f32::from(value) / max;
+                    }
+                }
+            }
+            if self.premultiply {
+                let alpha = floats[samples - 1];
+                for float in &mut floats[..samples - 1] {
+                    *float *= alpha;
+                }
+            }
+
+            let output = &mut row[idx * samples * 4..][..samples * 4];
+            for (bytes, float) in output.chunks_exact_mut(4).zip(floats) {
+                bytes.copy_from_slice(&float.to_ne_bytes());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::FloatConversion;
+    use crate::test_codec::{decode_png, encode_png};
+    use crate::{
+        BitDepth, ColorType, Decoder, Downscale, Info, ScaledFloat, SrgbRenderingIntent,
+        Transformations,
+    };
+
+    fn floats(bytes: &[u8]) -> Vec<f32> {
+        bytes
+            .chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes(bytes.try_into().unwrap()))
+            .collect()
+    }
+
+    /// Converts the samples of `data` of an image with a gamma of 1.0.
+    fn convert(
+        (color_type, bit_depth): (ColorType, BitDepth),
+        transform: Transformations,
+        pixels: usize,
+        data: &[u8],
+    ) -> Vec<f32> {
+        let mut info = Info::with_size(pixels as u32, 1);
+        info.color_type = color_type;
+        info.bit_depth = bit_depth;
+        info.gama_chunk = Some(ScaledFloat::new(1.0));
+        let conversion = FloatConversion::new(&info, transform).unwrap();
+        let mut row = data.to_vec();
+        row.resize(pixels * conversion.bits_per_pixel() / 8, 0);
+        conversion.apply(pixels, &mut row);
+        floats(&row)
+    }
+
+    #[test]
+    fn normalized_samples() {
+        let format = (ColorType::GrayscaleAlpha, BitDepth::Eight);
+        let floats = convert(format, Transformations::F32, 2, &[0, 255, 51, 0]);
+        assert_eq!(floats, [0.0, 1.0, 0.2, 0.0]);
+
+        let format = (ColorType::Rgb, BitDepth::Sixteen);
+        let data = [0, 0, 0x80, 0x00, 0xff, 0xff];
+        let floats = convert(format, Transformations::F32, 1, &data);
+        assert_eq!(floats, [0.0, 32768.0 / 65535.0, 1.0]);
+
+        // Packed samples are expanded to 8 bits first.
+        let format = (ColorType::Grayscale, BitDepth::Two);
+        let floats = convert(format, Transformations::F32, 3, &[0, 85, 255]);
+        assert_eq!(floats, [0.0, 85.0 / 255.0, 1.0]);
+    }
+
+    #[test]
+    fn premultiplied_samples() {
+        let format = (ColorType::GrayscaleAlpha, BitDepth::Eight);
+        let transform = Transformations::F32 | Transformations::PREMULTIPLY;
+        assert_eq!(convert(format, transform, 1, &[51, 51]), [0.2 * 0.2, 0.2]);
+        // The image has a gamma of 1.0, so its samples are already linear.
+        let transform = Transformations::LINEAR | Transformations::PREMULTIPLY;
+        assert_eq!(convert(format, transform, 1, &[51, 51]), [0.2 * 0.2, 0.2]);
+        // Without alpha there is nothing to premultiply with.
+        let format = (ColorType::Rgb, BitDepth::Eight);
+        assert_eq!(
+            convert(format, transform, 1, &[0, 51, 255]),
+            [0.0, 0.2, 1.0]
+        );
+    }
+
+    #[test]
+    fn premultiplied_layout() {
+        let format = (ColorType::Rgba, BitDepth::Eight);
+        let png = encode_png((1, 1), format, &[255, 51, 0, 51], |_| {});
+        let transform = Transformations::F32
+            | Transformations::PREMULTIPLY
+            | Transformations::BGR
+            | Transformations::SWAP_ALPHA
+            | Transformations::INVERT_ALPHA;
+        assert_eq!(
+            floats(&decode_png(&png, transform).1),
+            [0.8, 0.0, 0.2 * 0.2, 0.2]
+        );
+
+        let format = (ColorType::Rgb, BitDepth::Eight);
+        let png = encode_png((1, 1), format, &[0, 51, 255], |_| {});
+        let (info, buf) = decode_png(&png, Transformations::F32 | Transformations::FILLER);
+        assert_eq!(info.color_type, ColorType::Rgba);
+        assert_eq!(floats(&buf), [0.0, 0.2, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn linear_srgb_samples() {
+        // Samples of sRGB are decoded with its transfer function, `GAMMA` is ignored.
+        let format = (ColorType::Grayscale, BitDepth::Eight);
+        let png = encode_png((4, 1), format, &[0, 10, 128, 255], |encoder| {
+            encoder.set_source_srgb(SrgbRenderingIntent::Perceptual);
+        });
+        let srgb = floats(&decode_png(&png, Transformations::F32).1);
+        let transform = Transformations::LINEAR | Transformations::GAMMA;
+        let linear = floats(&decode_png(&png, transform).1);
+        for (srgb, linear) in srgb.into_iter().zip(linear) {
+            let expected = if srgb <= 0.04045 {
+                srgb / 12.92
+            } else {
+                ((srgb + 0.055) / 1.055).powf(2.4)
+            };
+            assert!((linear - expected).abs() < 1e-5, "{linear} {expected}");
+        }
+    }
+
+    #[test]
+    fn typed_frames() {
+        let read = |name: &str| std::fs::read(format!("tests/pngsuite/{name}.png")).unwrap();
+        let reader = |png: &[u8], transform| {
+            let mut decoder = Decoder::new(Cursor::new(png.to_vec()));
+            decoder.set_transformations(transform);
+            decoder.read_info().unwrap()
+        };
+
+        for name in ["basn6a16", "basi6a16", "basn2c08", "basn0g04", "basi3p02"] {
+            let png = read(name);
+            let mut bytes = reader(&png, Transformations::EXPAND);
+            let mut buf = vec![0; bytes.output_buffer_size().unwrap()];
+            let info = bytes.next_frame(&mut buf).unwrap();
+            let expected: Vec<u16> = if info.bit_depth == BitDepth::Sixteen {
+                buf.chunks_exact(2)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                    .collect()
+            } else {
+                buf.iter().map(|&b| u16::from(b) * 257).collect()
+            };
+
+            let mut samples = vec![0; expected.len()];
+            let mut typed = reader(&png, Transformations::IDENTITY);
+            assert_eq!(typed.next_frame_u16(&mut samples).unwrap(), info, "{name}");
+            assert_eq!(samples, expected, "{name}");
+            // The configured transformations are restored.
+            assert_eq!(
+                typed.output_color_type(),
+                reader(&png, Transformations::IDENTITY).output_color_type()
+            );
+
+            let mut floats = vec![0.0; expected.len()];
+            let mut typed = reader(&png, Transformations::IDENTITY);
+            typed.next_frame_f32(&mut floats).unwrap();
+            let max = if info.bit_depth == BitDepth::Sixteen {
+                65535.0
+            } else {
+                255.0
+            };
+            let normalized: Vec<f32> = expected
+                .iter()
+                .map(|&sample| {
+                    let sample = if info.bit_depth == BitDepth::Sixteen {
+                        sample
+                    } else {
+                        sample / 257
+                    };
+                    f32::from(sample) / max
+                })
+                .collect();
+            assert_eq!(floats, normalized, "{name}");
+        }
+
+        let mut typed = reader(&read("basn6a16"), Transformations::IDENTITY);
+        let mut samples = vec![0; 32 * 32 * 4 - 1];
+        assert!(typed.next_frame_u16(&mut samples).is_err());
+
+        // Downscaled frames are converted as well.
+        let mut decoder = Decoder::new(Cursor::new(read("basn2c08")));
+        decoder.set_downscale(Downscale::Half);
+        let mut typed = decoder.read_info().unwrap();
+        let mut samples = vec![0; 16 * 16 * 3];
+        let info = typed.next_frame_u16(&mut samples).unwrap();
+        assert_eq!((info.width, info.height), (16, 16));
+    }
+
+    #[test]
+    fn downscaled_and_interlaced() {
+        let decode = |name: &str, transform, downscale| {
+            let png = std::fs::read(format!("tests/pngsuite/{name}.png")).unwrap();
+            let mut decoder = Decoder::new(Cursor::new(png));
+            decoder.set_transformations(transform);
+            decoder.set_downscale(downscale);
+            let mut reader = decoder.read_info().unwrap();
+            let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+            reader.next_frame(&mut buf).unwrap();
+            buf
+        };
+
+        // Interlaced frames are placed like any other.
+        for (plain, interlaced) in [("basn6a08", "basi6a08"), ("basn3p02", "basi3p02")] {
+            let transform = Transformations::F32 | Transformations::BGR;
+            assert_eq!(
+                decode(plain, transform, Downscale::None),
+                decode(interlaced, transform, Downscale::None),
+                "{plain}"
+            );
+        }
+
+        // Blocks of `f32` samples are averaged like integers, without rounding.
+        let transform = Transformations::F32 | Transformations::STRIP_16;
+        let floats = decode("basn6a16", transform, Downscale::Quarter);
+        let bytes = decode("basn6a16", Transformations::STRIP_16, Downscale::Quarter);
+        assert_eq!(bytes.len() * 4, floats.len());
+        for (&byte, float) in bytes.iter().zip(floats.chunks_exact(4)) {
+            let float = f32::from_ne_bytes(float.try_into().unwrap());
+            assert_eq!(byte, (float * 255.0).round() as u8);
+        }
+    }
+}
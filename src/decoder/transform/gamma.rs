@@ -0,0 +1,271 @@
+//! Gamma correction of transformed rows, see [`Transformations::GAMMA`].
+
+use crate::common::{ColorType, Info, ScaledFloat, Transformations};
+use crate::decoder::expanded_color_type;
+
+/// Lookup tables that correct the color samples of output rows from the gamma of the image to
+/// the display gamma, or through any other curve that maps each sample on its own.
+pub(super) struct GammaCorrection {
+    /// The bit depth of the output samples, with 1, 2 and 4 bits only for grayscale.
+    bit_depth: u8,
+    /// The number of samples per pixel of the output.
+    samples: usize,
+    /// For each color sample of a pixel, the table and the shift that drops the insignificant
+    /// bits of the sample to index it. Alpha samples are not corrected.
+    channels: Vec<(Vec<u16>, u32)>,
+}
+
+impl GammaCorrection {
+    /// Returns the correction for the output of `transform`, or `None` if the image has no gamma
+    /// or the output is already in the display gamma.
+    pub fn new(
+        info: &Info,
+        transform: Transformations,
+        display_gamma: ScaledFloat,
+    ) -> Option<Self> {
+        let file_gamma = info.gamma()?;
+        if display_gamma == file_gamma {
+            return None;
+        }
+        // Samples are encoded as `linear ^ gamma`, decoding with the file gamma and encoding with
+        // the display gamma is a single power.
+        let exponent = f64::from(display_gamma.into_value()) / f64::from(file_gamma.into_value());
+        Self::with_curve(info, transform, |sample| sample.powf(exponent))
+    }
+
+    /// Returns the correction that maps each color sample of the output of `transform` through
+    /// `curve`, which takes and returns samples normalized to `0.0..=1.0`.
+    pub fn with_curve(
+        info: &Info,
+        transform: Transformations,
+        curve: impl Fn(f64) -> f64,
+    ) -> Option<Self> {
+        let (color_type, bit_depth) = expanded_color_type(info, transform);
+        let color_channels = match color_type {
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => 1,
+            ColorType::Rgb | ColorType::Rgba => 3,
+            // The indices of a palette can not be corrected.
+            ColorType::Indexed => return None,
+        };
+
+        // Samples are expanded by repeating their bits, so the significant bits of the source
+        // remain the highest bits of the output.
+        let source_depth = match info.color_type {
+            ColorType::Indexed => 8,
+            _ => info.bit_depth as u8,
+        };
+        let output_depth = bit_depth as u8;
+        let channels = (0..color_channels)
+            .map(|channel| {
+                let sbit = info.sbit.as_deref().and_then(|sbit| {
+                    // A grayscale source has a single significant bit depth for all channels.
+                    sbit.get(if sbit.len() < 3 { 0 } else { channel })
+                });
+                let significant = sbit
+                    .copied()
+                    .filter(|&bits| bits > 0 && bits <= source_depth)
+                    .unwrap_or(source_depth)
+                    .min(output_depth);
+                let table = create_table(significant, output_depth, &curve);
+                (table, u32::from(output_depth - significant))
+            })
+            .collect();
+
+        Some(GammaCorrection {
+            bit_depth: output_depth,
+            samples: color_type.samples(),
+            channels,
+        })
+    }
+
+    /// Corrects the color samples of a transformed row in place.
+    pub fn apply(&self, row: &mut [u8]) {
+        match self.bit_depth {
+            8 => {
+                for pixel in row.chunks_exact_mut(self.samples) {
+                    for (sample, (table, shift)) in pixel.iter_mut().zip(&self.channels) {
+                        *sample = table[usize::from(*sample >> shift)] as u8;
+                    }
+                }
+            }
+            16 => {
+                for pixel in row.chunks_exact_mut(self.samples * 2) {
+                    for (sample, (table, shift)) in pixel.chunks_exact_mut(2).zip(&self.channels) {
+                        let value = u16::from_be_bytes([sample[0], sample[1]]);
+                        sample.copy_from_slice(&table[usize::from(value >> shift)].to_be_bytes());
+                    }
+                }
+            }
+            bits => {
+                // Packed grayscale samples, the padding bits of the last byte stay zero.
+                let (table, shift) = &self.channels[0];
+                let mask = (1u8 << bits) - 1;
+                for byte in row {
+                    let mut corrected = 0;
+                    for offset in (0..8).step_by(usize::from(bits)) {
+                        let sample = (*byte >> offset) & mask;
+                        corrected |= (table[usize::from(sample >> shift)] as u8) << offset;
+                    }
+                    *byte = corrected;
+                }
+            }
+        }
+    }
+}
+
+/// Maps each sample of `significant` bits to a corrected sample of `output_depth` bits.
+fn create_table(significant: u8, output_depth: u8, curve: impl Fn(f64) -> f64) -> Vec<u16> {
+    let max_in = f64::from((1u32 << significant) - 1);
+    let max_out = f64::from((1u32 << output_depth) - 1);
+    (0..1u32 << significant)
+        .map(|sample| (curve(f64::from(sample) / max_in).clamp(0.0, 1.0) * max_out).round() as u16)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::create_transform_fn;
+    use crate::{BitDepth, ColorType, Decoder, Info, ScaledFloat, Transformations};
+
+    fn create_info(color_type: ColorType, bit_depth: BitDepth, gamma: f32) -> Info<'static> {
+        Info {
+            color_type,
+            bit_depth,
+            gama_chunk: Some(ScaledFloat::new(gamma)),
+            ..Info::default()
+        }
+    }
+
+    fn correct(info: &Info, transform: Transformations, display: f32, src: &[u8]) -> Vec<u8> {
+        let (color_type, bit_depth) = crate::decoder::output_color_type(info, transform);
+        let pixels = src.len() * 8 / (info.color_type.samples() * info.bit_depth as usize);
+        let len = (pixels * color_type.samples() * bit_depth as usize).div_ceil(8);
+        let mut dst = vec![0; len];
+        let transform_fn = create_transform_fn(
+            info,
+            transform | Transformations::GAMMA,
+            ScaledFloat::new(display),
+        )
+        .unwrap();
+        transform_fn(src, &mut dst, info);
+        dst
+    }
+
+    fn expected(sample: f64, max: f64, exponent: f64) -> f64 {
+        ((sample / max).powf(exponent) * max).round()
+    }
+
+    #[test]
+    fn same_gamma_is_identity() {
+        let info = create_info(ColorType::Rgb, BitDepth::Eight, 0.45455);
+        let src: Vec<u8> = (0..=254).collect();
+        assert_eq!(
+            correct(&info, Transformations::IDENTITY, 0.45455, &src),
+            src
+        );
+        let info = Info {
+            gama_chunk: None,
+            ..info
+        };
+        assert_eq!(correct(&info, Transformations::IDENTITY, 1.0, &src), src);
+    }
+
+    #[test]
+    fn correct_8_bit() {
+        let info = create_info(ColorType::Rgb, BitDepth::Eight, 0.5);
+        let src: Vec<u8> = (0..=254).collect();
+        let dst = correct(&info, Transformations::IDENTITY, 1.0, &src);
+        for (&sample, &corrected) in src.iter().zip(&dst) {
+            assert_eq!(f64::from(corrected), expected(sample.into(), 255.0, 2.0));
+        }
+    }
+
+    #[test]
+    fn correct_16_bit() {
+        let info = create_info(ColorType::Grayscale, BitDepth::Sixteen, 1.0);
+        let src: Vec<u8> = (0..=u16::MAX)
+            .step_by(257)
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        let dst = correct(&info, Transformations::IDENTITY, 0.5, &src);
+        for (sample, corrected) in src.chunks_exact(2).zip(dst.chunks_exact(2)) {
+            let sample = u16::from_be_bytes([sample[0], sample[1]]);
+            let corrected = u16::from_be_bytes([corrected[0], corrected[1]]);
+            assert_eq!(f64::from(corrected), expected(sample.into(), 65535.0, 0.5));
+        }
+    }
+
+    #[test]
+    fn alpha_is_not_corrected() {
+        let info = create_info(ColorType::GrayscaleAlpha, BitDepth::Eight, 0.5);
+        let dst = correct(&info, Transformations::IDENTITY, 1.0, &[128, 128, 255, 64]);
+        assert_eq!(dst, [64, 128, 255, 64]);
+
+        // Alpha added from `tRNS` is not corrected either.
+        let info = Info {
+            trns: Some((&[128][..]).into()),
+            ..create_info(ColorType::Grayscale, BitDepth::Eight, 0.5)
+        };
+        let dst = correct(&info, Transformations::EXPAND, 1.0, &[128, 0]);
+        assert_eq!(dst, [64, 0, 0, 255]);
+    }
+
+    #[test]
+    fn packed_grayscale() {
+        let info = create_info(ColorType::Grayscale, BitDepth::Two, 0.5);
+        let dst = correct(&info, Transformations::IDENTITY, 1.0, &[0b00_01_10_11]);
+        // 1/3 and 2/3 squared are 1/9 and 4/9, which round to 0 and 1 of 3.
+        assert_eq!(dst, [0b00_00_01_11]);
+
+        let dst = correct(&info, Transformations::EXPAND, 1.0, &[0b00_01_10_11]);
+        assert_eq!(dst, [0, 28, 113, 255]);
+    }
+
+    #[test]
+    fn significant_bits() {
+        let info = Info {
+            sbit: Some((&[4, 4, 4][..]).into()),
+            ..create_info(ColorType::Rgb, BitDepth::Eight, 0.5)
+        };
+        // The insignificant low bits do not change the result.
+        let dst = correct(&info, Transformations::IDENTITY, 1.0, &[0x80, 0x8f, 0x88]);
+        let expected = (((8.0f64 / 15.0).powi(2)) * 255.0).round() as u8;
+        assert_eq!(dst, [expected; 3]);
+    }
+
+    #[test]
+    fn decode_pngsuite() {
+        for name in ["g03n2c08", "g25n0g16", "g04n3p04"] {
+            let path = format!("tests/pngsuite/{name}.png");
+            let decode = |transform| {
+                let mut decoder =
+                    Decoder::new(std::io::BufReader::new(std::fs::File::open(&path).unwrap()));
+                decoder.set_transformations(transform);
+                decoder.set_display_gamma(ScaledFloat::new(1.0));
+                let mut reader = decoder.read_info().unwrap();
+                let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+                let info = reader.next_frame(&mut buf).unwrap();
+                let gamma = reader.info().gamma().unwrap().into_value();
+                buf.truncate(info.buffer_size());
+                (buf, info.bit_depth, f64::from(gamma))
+            };
+            let (raw, bit_depth, gamma) = decode(Transformations::EXPAND);
+            let (linear, _, _) = decode(Transformations::EXPAND | Transformations::GAMMA);
+
+            let exponent = 1.0 / gamma;
+            if bit_depth == BitDepth::Sixteen {
+                for (sample, corrected) in raw.chunks_exact(2).zip(linear.chunks_exact(2)) {
+                    let sample = u16::from_be_bytes([sample[0], sample[1]]);
+                    let corrected = u16::from_be_bytes([corrected[0], corrected[1]]);
+                    let expected = expected(sample.into(), 65535.0, exponent);
+                    assert!((f64::from(corrected) - expected).abs() <= 1.0, "{name}");
+                }
+            } else {
+                for (&sample, &corrected) in raw.iter().zip(&linear) {
+                    let expected = expected(sample.into(), 255.0, exponent);
+                    assert!((f64::from(corrected) - expected).abs() <= 1.0, "{name}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Changing the order and number of channels of transformed rows, see [`Transformations::BGR`],
+//! [`Transformations::SWAP_ALPHA`], [`Transformations::INVERT_ALPHA`] and
+//! [`Transformations::FILLER`].
+
+use crate::common::{ColorType, Info, Transformations};
+use crate::decoder::expanded_color_type;
+
+/// Rearranges the samples of each pixel of a row of 8 or 16 bit, or `f32` samples in place.
+pub(super) struct ChannelLayout {
+    /// The number of bytes per sample.
+    sample_bytes: usize,
+    /// Whether the samples are `f32`.
+    float: bool,
+    /// The number of samples per pixel before the filler is added.
+    samples: usize,
+    /// Whether a filler sample is added after the color samples.
+    filler: bool,
+    /// Whether the red and blue samples are swapped.
+    bgr: bool,
+    /// Whether the alpha or filler sample is moved in front of the color samples.
+    swap_alpha: bool,
+    /// Whether the alpha or filler sample is inverted.
+    invert_alpha: bool,
+}
+
+impl ChannelLayout {
+    /// Returns the layout requested by `transform`, or `None` if it keeps every sample in place.
+    ///
+    /// Packed samples of less than 8 bits are never rearranged.
+    pub fn new(info: &Info, transform: Transformations) -> Option<Self> {
+        let (color_type, bit_depth) = expanded_color_type(info, transform);
+        if (bit_depth as u8) < 8 || color_type == ColorType::Indexed {
+            return None;
+        }
+        let rgb = matches!(color_type, ColorType::Rgb | ColorType::Rgba);
+        let filler = transform.contains(Transformations::FILLER)
+            && matches!(color_type, ColorType::Grayscale | ColorType::Rgb);
+        let alpha = filler || matches!(color_type, ColorType::GrayscaleAlpha | ColorType::Rgba);
+
+        let float = transform.intersects(Transformations::F32 | Transformations::LINEAR);
+        let layout = ChannelLayout {
+            sample_bytes: if float { 4 } else { bit_depth as usize / 8 },
+            float,
+            samples: color_type.samples(),
+            filler,
+            bgr: rgb && transform.contains(Transformations::BGR),
+            swap_alpha: alpha && transform.contains(Transformations::SWAP_ALPHA),
+            invert_alpha: alpha && transform.contains(Transformations::INVERT_ALPHA),
+        };
+        (layout.filler || layout.bgr || layout.swap_alpha || layout.invert_alpha).then_some(layout)
+    }
+
+    /// The number of bits per pixel of the rearranged row.
+    pub fn bits_per_pixel(&self) -> usize {
+        (self.samples + usize::from(self.filler)) * self.sample_bytes * 8
+    }
+
+    /// Rearranges the first `pixels` pixels of `row`, which starts with the pixels before the
+    /// filler is added.
+    pub fn apply(&self, pixels: usize, row: &mut [u8]) {
+        let sample = self.sample_bytes;
+        let pixel_bytes = (self.samples + usize::from(self.filler)) * sample;
+        let row = &mut row[..pixels * pixel_bytes];
+
+        if self.filler {
+            // Spread the pixels from the back, so that none is overwritten before it is moved.
+            let color_bytes = self.samples * sample;
+            for idx in (0..pixels).rev() {
+                row.copy_within(
+                    idx * color_bytes..(idx + 1) * color_bytes,
+                    idx * pixel_bytes,
+                );
+                let filler = &mut row[idx * pixel_bytes + color_bytes..][..sample];
+                if self.float {
+                    filler.copy_from_slice(&1.0f32.to_ne_bytes());
+                } else {
+                    filler.fill(0xFF);
+                }
+            }
+        }
+
+        for pixel in row.chunks_exact_mut(pixel_bytes) {
+            if self.bgr {
+                for byte in 0..sample {
+                    pixel.swap(byte, 2 * sample + byte);
+                }
+            }
+            if self.invert_alpha {
+                let alpha = &mut pixel[pixel_bytes - sample..];
+                if self.float {
+                    let value = f32::from_ne_bytes([alpha[0], alpha[1], alpha[2], alpha[3]]);
+                    alpha.copy_from_slice(&(1.0 - value).to_ne_bytes());
+                } else {
+                    for byte in alpha {
+                        *byte = !*byte;
+                    }
+                }
+            }
+            if self.swap_alpha {
+                pixel.rotate_right(sample);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChannelLayout;
+    use crate::test_codec::{decode_png, encode_png};
+    use crate::{BitDepth, ColorType, Info, Transformations};
+
+    /// Rearranges the pixels of `data`, or returns `None` if `transform` keeps them in place.
+    fn rearrange(
+        (color_type, bit_depth): (ColorType, BitDepth),
+        transform: Transformations,
+        pixels: usize,
+        data: &[u8],
+    ) -> Option<Vec<u8>> {
+        let mut info = Info::with_size(pixels as u32, 1);
+        info.color_type = color_type;
+        info.bit_depth = bit_depth;
+        let layout = ChannelLayout::new(&info, transform)?;
+        let mut row = data.to_vec();
+        row.resize(pixels * layout.bits_per_pixel() / 8, 0);
+        layout.apply(pixels, &mut row);
+        Some(row)
+    }
+
+    #[test]
+    fn rgba_orders() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8];
+        let cases = [
+            (Transformations::BGR, [3, 2, 1, 4, 7, 6, 5, 8]),
+            (Transformations::SWAP_ALPHA, [4, 1, 2, 3, 8, 5, 6, 7]),
+            (
+                Transformations::BGR | Transformations::SWAP_ALPHA,
+                [4, 3, 2, 1, 8, 7, 6, 5],
+            ),
+            (Transformations::INVERT_ALPHA, [1, 2, 3, 251, 5, 6, 7, 247]),
+        ];
+        let format = (ColorType::Rgba, BitDepth::Eight);
+        for (transform, expected) in cases {
+            let row = rearrange(format, transform, 2, &data);
+            assert_eq!(row.as_deref(), Some(&expected[..]), "{transform:?}");
+        }
+        // The filler is only added to output without alpha.
+        assert!(rearrange(format, Transformations::FILLER, 2, &data).is_none());
+    }
+
+    #[test]
+    fn sixteen_bit_samples() {
+        let transform =
+            Transformations::BGR | Transformations::FILLER | Transformations::SWAP_ALPHA;
+        let format = (ColorType::Rgb, BitDepth::Sixteen);
+        assert_eq!(
+            rearrange(format, transform, 1, &[1, 2, 3, 4, 5, 6]).unwrap(),
+            [255, 255, 5, 6, 3, 4, 1, 2]
+        );
+
+        let transform = Transformations::SWAP_ALPHA | Transformations::INVERT_ALPHA;
+        let format = (ColorType::GrayscaleAlpha, BitDepth::Sixteen);
+        assert_eq!(
+            rearrange(format, transform, 1, &[1, 2, 3, 4]).unwrap(),
+            [252, 251, 1, 2]
+        );
+    }
+
+    #[test]
+    fn filler() {
+        let format = (ColorType::Rgb, BitDepth::Eight);
+        let data = [1, 2, 3, 4, 5, 6];
+        assert_eq!(
+            rearrange(format, Transformations::FILLER, 2, &data).unwrap(),
+            [1, 2, 3, 255, 4, 5, 6, 255]
+        );
+        let transform = Transformations::FILLER | Transformations::SWAP_ALPHA;
+        assert_eq!(
+            rearrange(format, transform, 2, &data).unwrap(),
+            [255, 1, 2, 3, 255, 4, 5, 6]
+        );
+
+        // Packed samples are not rearranged, unless they are expanded first.
+        let format = (ColorType::Grayscale, BitDepth::Two);
+        assert!(rearrange(format, Transformations::FILLER, 3, &[0b00_01_11_00]).is_none());
+        let transform = Transformations::EXPAND | Transformations::FILLER;
+        assert_eq!(
+            rearrange(format, transform, 3, &[0, 85, 255]).unwrap(),
+            [0, 255, 85, 255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn float_samples() {
+        let transform = Transformations::F32 | Transformations::FILLER;
+        let format = (ColorType::Grayscale, BitDepth::Eight);
+        let row = rearrange(format, transform, 1, &0.5f32.to_ne_bytes()).unwrap();
+        assert_eq!(row, [0.5f32, 1.0].map(f32::to_ne_bytes).concat());
+
+        let transform = Transformations::F32 | Transformations::INVERT_ALPHA;
+        let format = (ColorType::GrayscaleAlpha, BitDepth::Eight);
+        let data = [0.5f32, 0.25].map(f32::to_ne_bytes).concat();
+        let row = rearrange(format, transform, 1, &data).unwrap();
+        assert_eq!(row, [0.5f32, 0.75].map(f32::to_ne_bytes).concat());
+    }
+
+    #[test]
+    fn expanded_palette() {
+        let format = (ColorType::Indexed, BitDepth::Four);
+        let png = encode_png((3, 1), format, &[0x10, 0x00], |encoder| {
+            encoder.set_palette(vec![1, 2, 3, 4, 5, 6]);
+            encoder.set_trns(vec![7]);
+        });
+        let (info, buf) = decode_png(&png, Transformations::EXPAND | Transformations::BGR);
+        assert_eq!(info.color_type, ColorType::Rgba);
+        assert_eq!(buf, [6, 5, 4, 255, 3, 2, 1, 7, 3, 2, 1, 7]);
+        let (info, buf) = decode_png(&png, Transformations::BGR);
+        assert_eq!(info.color_type, ColorType::Indexed);
+        assert_eq!(buf, [0x10, 0x00]);
+    }
+
+    #[test]
+    fn interlaced_images() {
+        for name in ["basi2c08", "basi6a16", "basi3p02"] {
+            let png = std::fs::read(format!("tests/pngsuite/{name}.png")).unwrap();
+            let (info, plain) = decode_png(&png, Transformations::EXPAND);
+            let transform =
+                Transformations::EXPAND | Transformations::BGR | Transformations::FILLER;
+            let (_, swapped) = decode_png(&png, transform);
+            let sample = if name.ends_with("16") { 2 } else { 1 };
+            let samples = info.color_type.samples();
+            for (plain, swapped) in plain
+                .chunks_exact(samples * sample)
+                .zip(swapped.chunks_exact(4 * sample))
+            {
+                assert_eq!(plain[..sample], swapped[2 * sample..][..sample], "{name}");
+                assert_eq!(plain[2 * sample..][..sample], swapped[..sample], "{name}");
+                if samples == 4 {
+                    assert_eq!(plain[3 * sample..], swapped[3 * sample..], "{name}");
+                } else {
+                    assert!(swapped[3 * sample..].iter().all(|&b| b == 255), "{name}");
+                }
+            }
+        }
+    }
+}
@@ -16,6 +16,7 @@
 //! Based on X and Y, we can try to calculate the breakeven point.  It seems
 //! that memoization is a net benefit for images bigger than around 13x13 pixels.
 
+use super::premultiply::premultiply8;
 use super::{unpack_bits, TransformFn};
 use crate::{BitDepth, Info};
 
@@ -29,8 +30,14 @@ pub fn create_expansion_into_rgb8(info: &Info) -> TransformFn {
     }
 }
 
-pub fn create_expansion_into_rgba8(info: &Info) -> TransformFn {
-    let rgba_palette = create_rgba_palette(info);
+/// Returns the expansion into RGBA, with colors premultiplied by alpha if `premultiply` is set.
+pub fn create_expansion_into_rgba8(info: &Info, premultiply: bool) -> TransformFn {
+    let mut rgba_palette = create_rgba_palette(info);
+    if premultiply {
+        for rgba in rgba_palette.iter_mut() {
+            premultiply8::<4>(rgba);
+        }
+    }
     Box::new(move |input, output, info| {
         expand_paletted_into_rgba8(input, output, info, &rgba_palette)
     })
@@ -202,8 +209,12 @@ mod test {
         let samples_count = src.len() * samples_count_per_byte;
 
         let mut dst = vec![0; samples_count * output_bytes_per_input_sample];
-        let transform_fn =
-            super::super::create_transform_fn(&info, Transformations::EXPAND).unwrap();
+        let transform_fn = super::super::create_transform_fn(
+            &info,
+            Transformations::EXPAND,
+            crate::srgb::substitute_gamma(),
+        )
+        .unwrap();
         transform_fn(src, dst.as_mut_slice(), &info);
 
         {
@@ -338,10 +349,10 @@ mod test {
         fn create_expected_rgba_palette(plte: &[u8], trns: &[u8]) -> [[u8; 4]; 256] {
             let mut rgba = [[1, 2, 3, 4]; 256];
             for (i, rgba) in rgba.iter_mut().enumerate() {
-                rgba[0] = plte.get(i * 3 + 0).map(|&r| r).unwrap_or(0);
-                rgba[1] = plte.get(i * 3 + 1).map(|&g| g).unwrap_or(0);
-                rgba[2] = plte.get(i * 3 + 2).map(|&b| b).unwrap_or(0);
-                rgba[3] = trns.get(i * 1 + 0).map(|&a| a).unwrap_or(0xFF);
+                rgba[0] = plte.get(i * 3).copied().unwrap_or(0);
+                rgba[1] = plte.get(i * 3 + 1).copied().unwrap_or(0);
+                rgba[2] = plte.get(i * 3 + 2).copied().unwrap_or(0);
+                rgba[3] = trns.get(i).copied().unwrap_or(0xFF);
             }
             rgba
         }
@@ -0,0 +1,173 @@
+//! Premultiplying color samples with alpha, see [`Transformations::PREMULTIPLY`].
+//!
+//! The loops have no branches and work on fixed size pixels, so that they are vectorized.
+
+use crate::common::{ColorType, Info, Transformations};
+use crate::decoder::expanded_color_type;
+
+/// Returns the premultiplication of the output of `transform`, or `None` if it has no alpha.
+///
+/// Packed samples of less than 8 bits never have alpha.
+pub(super) fn create_premultiply_fn(
+    info: &Info,
+    transform: Transformations,
+) -> Option<fn(&mut [u8])> {
+    if !transform.contains(Transformations::PREMULTIPLY) {
+        return None;
+    }
+    let (color_type, bit_depth) = expanded_color_type(info, transform);
+    let sixteen = bit_depth as u8 == 16;
+    match color_type {
+        ColorType::GrayscaleAlpha if sixteen => Some(premultiply16::<4>),
+        ColorType::GrayscaleAlpha => Some(premultiply8::<2>),
+        ColorType::Rgba if sixteen => Some(premultiply16::<8>),
+        ColorType::Rgba => Some(premultiply8::<4>),
+        _ => None,
+    }
+}
+
+/// Returns `value / 255` rounded to the nearest integer, for products of two 8 bit samples.
+#[inline(always)]
+fn div_255(value: u16) -> u8 {
+    let value = value + 128;
+    ((value + (value >> 8)) >> 8) as u8
+}
+
+/// Returns `value / 65535` rounded to the nearest integer, for products of two 16 bit samples.
+#[inline(always)]
+fn div_65535(value: u32) -> u16 {
+    let value = value + 32768;
+    ((value + (value >> 16)) >> 16) as u16
+}
+
+/// Premultiplies pixels of `N` 8 bit samples, with alpha last.
+pub(super) fn premultiply8<const N: usize>(row: &mut [u8]) {
+    for pixel in row.chunks_exact_mut(N) {
+        let alpha = u16::from(pixel[N - 1]);
+        for sample in &mut pixel[..N - 1] {
+            *sample = div_255(u16::from(*sample) * alpha);
+        }
+    }
+}
+
+/// Premultiplies pixels of `N` bytes of big endian 16 bit samples, with alpha last.
+fn premultiply16<const N: usize>(row: &mut [u8]) {
+    for pixel in row.chunks_exact_mut(N) {
+        let alpha = u32::from(u16::from_be_bytes([pixel[N - 2], pixel[N - 1]]));
+        for sample in pixel[..N - 2].chunks_exact_mut(2) {
+            let value = u32::from(u16::from_be_bytes([sample[0], sample[1]]));
+            sample.copy_from_slice(&div_65535(value * alpha).to_be_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_premultiply_fn, div_255, div_65535, premultiply16, premultiply8};
+    use crate::test_codec::{decode_png, encode_png};
+    use crate::{BitDepth, ColorType, Info, ScaledFloat, Transformations};
+
+    const PREMULTIPLY: Transformations = Transformations::PREMULTIPLY;
+
+    #[test]
+    fn rounding_is_exact() {
+        for a in 0..=255u16 {
+            for b in 0..=255u16 {
+                let expected = (f64::from(a * b) / 255.0).round() as u8;
+                assert_eq!(div_255(a * b), expected, "{a} * {b}");
+            }
+        }
+        for a in (0..=65535u32).step_by(7).chain([65535]) {
+            for b in [0, 1, 2, 255, 256, 32767, 32768, 65534, 65535] {
+                let expected = (a as f64 * b as f64 / 65535.0).round() as u16;
+                assert_eq!(div_65535(a * b), expected, "{a} * {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn premultiply_8_bit() {
+        let mut row = [200, 100, 1, 128, 200, 100, 1, 0, 200, 100, 1, 255];
+        premultiply8::<4>(&mut row);
+        assert_eq!(row, [100, 50, 1, 128, 0, 0, 0, 0, 200, 100, 1, 255]);
+
+        let mut row = [255, 51];
+        premultiply8::<2>(&mut row);
+        assert_eq!(row, [51, 51]);
+    }
+
+    #[test]
+    fn premultiply_16_bit() {
+        let mut row = [255, 255, 0, 200, 128, 0, 128, 0];
+        premultiply16::<8>(&mut row);
+        assert_eq!(row, [128, 0, 0, 100, 64, 0, 128, 0]);
+
+        let mut row = [255, 255, 0, 0];
+        premultiply16::<4>(&mut row);
+        assert_eq!(row, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn only_alpha_is_premultiplied() {
+        let mut info = Info::with_size(1, 1);
+        info.color_type = ColorType::Rgb;
+        assert!(create_premultiply_fn(&info, Transformations::PREMULTIPLY).is_none());
+        let transform = Transformations::PREMULTIPLY | Transformations::ALPHA;
+        assert!(create_premultiply_fn(&info, transform).is_some());
+        info.color_type = ColorType::Rgba;
+        assert!(create_premultiply_fn(&info, Transformations::IDENTITY).is_none());
+    }
+
+    #[test]
+    fn premultiply_before_other_transforms() {
+        let format = (ColorType::Rgba, BitDepth::Eight);
+        let png = encode_png((1, 1), format, &[200, 100, 1, 128], |_| {});
+        // Alpha is moved after it premultiplied the colors.
+        assert_eq!(
+            decode_png(&png, PREMULTIPLY | Transformations::SWAP_ALPHA).1,
+            [128, 100, 50, 1]
+        );
+
+        let format = (ColorType::Rgba, BitDepth::Sixteen);
+        let png = encode_png((1, 1), format, &[255, 255, 0, 200, 128, 0, 128, 0], |_| {});
+        assert_eq!(
+            decode_png(&png, PREMULTIPLY | Transformations::STRIP_16).1,
+            [128, 0, 64, 128]
+        );
+    }
+
+    #[test]
+    fn premultiply_expanded() {
+        let format = (ColorType::Indexed, BitDepth::Eight);
+        let png = encode_png((2, 1), format, &[0, 1], |encoder| {
+            encoder.set_palette(vec![200, 100, 50, 10, 20, 30]);
+            encoder.set_trns(vec![128]);
+        });
+        assert_eq!(
+            decode_png(&png, PREMULTIPLY | Transformations::EXPAND).1,
+            [100, 50, 25, 128, 10, 20, 30, 255]
+        );
+        // Indices are not premultiplied.
+        assert_eq!(decode_png(&png, PREMULTIPLY).1, [0, 1]);
+
+        // Colors are corrected before they are premultiplied.
+        let png = encode_png((1, 1), format, &[0], |encoder| {
+            encoder.set_palette(vec![128, 128, 128]);
+            encoder.set_trns(vec![128]);
+            encoder.set_source_gamma(ScaledFloat::new(1.0));
+        });
+        // Linear 128 is 188 in sRGB.
+        assert_eq!(
+            decode_png(&png, PREMULTIPLY | Transformations::EXPAND).1,
+            [64, 64, 64, 128]
+        );
+        assert_eq!(
+            decode_png(
+                &png,
+                PREMULTIPLY | Transformations::EXPAND | Transformations::SRGB
+            )
+            .1,
+            [94, 94, 94, 128]
+        );
+    }
+}
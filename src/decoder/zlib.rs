@@ -67,6 +67,14 @@ pub(super) struct ZlibStream {
     ///
     /// This flag should not be modified after decompression has started.
     ignore_adler32: bool,
+    /// Report data past the end of the zlib stream or of the image as an error, see
+    /// [`DecodeOptions::set_strict`](crate::DecodeOptions::set_strict).
+    strict: bool,
+    /// Whether all image data has been output, so that anything but the end of the zlib stream
+    /// is extra data.
+    complete: bool,
+    /// Whether extra data was found and the rest of the stream is ignored.
+    extra_data: bool,
 }
 
 impl ZlibStream {
@@ -75,11 +83,16 @@ impl ZlibStream {
             state: Box::new(Decompressor::new()),
             started: false,
             ignore_adler32: true,
+            strict: false,
+            complete: false,
+            extra_data: false,
         }
     }
 
     pub(crate) fn reset(&mut self) {
         self.started = false;
+        self.complete = false;
+        self.extra_data = false;
         *self.state = Decompressor::new();
     }
 
@@ -99,11 +112,37 @@ impl ZlibStream {
         }
     }
 
+    /// Set the `strict` flag.
+    pub(crate) fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
     /// Return the `ignore_adler32` flag.
     pub(crate) fn ignore_adler32(&self) -> bool {
         self.ignore_adler32
     }
 
+    /// Marks all image data as output, so that further data can only end the zlib stream.
+    pub(crate) fn set_complete(&mut self) {
+        self.complete = true;
+    }
+
+    /// Whether extra data was found and ignored since the last reset.
+    pub(crate) fn extra_data(&self) -> bool {
+        self.extra_data
+    }
+
+    /// Reports extra data as an error in strict mode, or else ignores the rest of the stream.
+    fn found_extra_data(&mut self) -> Result<(), DecodingError> {
+        if self.strict {
+            return Err(DecodingError::Format(
+                FormatErrorInner::ExtraImageData.into(),
+            ));
+        }
+        self.extra_data = true;
+        Ok(())
+    }
+
     /// Fill the decoded buffer as far as possible from `data`.
     /// On success returns the number of consumed input bytes.
     pub(crate) fn decompress(
@@ -112,9 +151,14 @@ impl ZlibStream {
         image_data: &mut UnfilterBuf<'_>,
     ) -> Result<usize, DecodingError> {
         // There may be more data past the adler32 checksum at the end of the deflate stream. We
-        // match libpng's default behavior and ignore any trailing data. In the future we may want
-        // to add a flag to control this behavior.
+        // match libpng's default behavior and ignore any trailing data, unless in strict mode.
+        if self.extra_data {
+            return Ok(data.len());
+        }
         if self.state.is_done() {
+            if !data.is_empty() {
+                self.found_extra_data()?;
+            }
             return Ok(data.len());
         }
 
@@ -122,10 +166,21 @@ impl ZlibStream {
             self.state.ignore_adler32();
         }
 
-        let in_consumed = image_data.decompress(&mut self.state, data)?;
+        let result = image_data.decompress(&mut self.state, data);
         self.started = true;
+        if !self.complete {
+            return result;
+        }
 
-        Ok(in_consumed)
+        // Once the image is complete, the decompressor only stops without consuming input if it
+        // needs room for more output.
+        match result {
+            Ok(0) if !data.is_empty() && !self.state.is_done() => {}
+            Err(_) if !self.strict => {}
+            result => return result,
+        }
+        self.found_extra_data()?;
+        Ok(data.len())
     }
 
     /// Output any remaining buffered data within the decompressor.
@@ -139,7 +194,7 @@ impl ZlibStream {
         &mut self,
         image_data: &mut UnfilterBuf<'_>,
     ) -> Result<bool, DecodingError> {
-        if !self.started || self.state.is_done() {
+        if !self.started || self.state.is_done() || self.extra_data {
             return Ok(true);
         }
 
@@ -148,6 +203,7 @@ impl ZlibStream {
         // checksum is missing. Those aren't allowed by the spec, but libpng
         // generally doesn't treat them as fatal.
         if *image_data.filled == image_data.buffer.len() {
+            self.found_extra_data()?;
             return Ok(true);
         }
 
@@ -6,10 +6,13 @@ use std::{borrow, error, fmt, io, mem, ops, result};
 use crc32fast::Hasher as Crc32;
 use flate2::write::ZlibEncoder;
 
+use crate::adam7::{extract_pass, Adam7Iterator};
 use crate::chunk::{self, ChunkType};
 use crate::common::{
-    AnimationControl, BitDepth, BlendOp, BytesPerPixel, ColorType, Compression, DisposeOp,
-    FrameControl, Info, ParameterError, ParameterErrorKind, PixelDimensions, ScaledFloat, Unit,
+    AnimationControl, BitDepth, BlendOp, BytesPerPixel, ChunkPosition, CodingIndependentCodePoints,
+    ColorType, Compression, ContentLightLevelInfo, DisposeOp, FrameControl, Info,
+    LastModificationTime, MasteringDisplayColorVolume, ParameterError, ParameterErrorKind,
+    PixelDimensions, ScaledFloat, Unit,
 };
 use crate::filter::{filter, Filter};
 use crate::text_metadata::{
@@ -18,6 +21,13 @@ use crate::text_metadata::{
 use crate::traits::WriteBytesExt;
 use crate::DeflateCompression;
 
+mod animation;
+#[cfg(feature = "async")]
+mod async_writer;
+pub use self::animation::AnimationEncoder;
+#[cfg(feature = "async")]
+pub use self::async_writer::{AsyncStreamWriter, AsyncWriter};
+
 pub type Result<T> = result::Result<T, EncodingError>;
 
 #[derive(Debug)]
@@ -34,7 +44,7 @@ pub struct FormatError {
 }
 
 #[derive(Debug)]
-enum FormatErrorKind {
+pub(crate) enum FormatErrorKind {
     ZeroWidth,
     ZeroHeight,
     InvalidColorCombination(BitDepth, ColorType),
@@ -49,6 +59,10 @@ enum FormatErrorKind {
     MissingData(usize),
     Unrecoverable,
     BadTextEncoding(TextEncodingError),
+    CriticalUnknownChunk(ChunkType),
+    /// The contents of a chunk are invalid for the color type and bit depth of the image.
+    InvalidChunkData(ChunkType),
+    UnsupportedAnimation,
 }
 
 impl error::Error for EncodingError {
@@ -108,6 +122,15 @@ impl fmt::Display for FormatError {
                     write!(fmt, "Unable to compress text metadata")
                 }
             },
+            CriticalUnknownChunk(kind) => {
+                write!(fmt, "unknown chunk {:?} must not be critical", kind)
+            }
+            UnsupportedAnimation => write!(fmt, "animated images are not supported"),
+            InvalidChunkData(kind) => write!(
+                fmt,
+                "invalid {:?} chunk for the color type and bit depth of the image",
+                kind
+            ),
         }
     }
 }
@@ -146,7 +169,7 @@ impl From<TextEncodingError> for EncodingError {
 ///
 /// FIXME: Configuring APNG might be easier (less individual errors) if we had an _adapter_ which
 /// borrows this mutably but guarantees that `info.frame_control` is not `None`.
-pub struct Encoder<'a, W: Write> {
+pub struct Encoder<'a, W> {
     w: W,
     info: Info<'a>,
     options: Options,
@@ -181,13 +204,24 @@ impl<'a, W: Write> Encoder<'a, W> {
             }
         }
 
+        if let Some(unknown) = info
+            .unknown_chunks
+            .iter()
+            .find(|chunk| chunk::is_critical(chunk.type_))
+        {
+            let kind = FormatErrorKind::CriticalUnknownChunk(unknown.type_);
+            return Err(EncodingError::Format(kind.into()));
+        }
+
         Ok(Encoder {
             w,
             info,
             options: Options::default(),
         })
     }
+}
 
+impl<'a, W> Encoder<'a, W> {
     /// Specify that the image is animated.
     ///
     /// `num_frames` controls how many frames the animation has, while
@@ -279,7 +313,10 @@ impl<'a, W: Write> Encoder<'a, W> {
     /// Start encoding by writing the header data.
     ///
     /// The remaining data can be supplied by methods on the returned [`Writer`].
-    pub fn write_header(self) -> Result<Writer<W>> {
+    pub fn write_header(self) -> Result<Writer<W>>
+    where
+        W: Write,
+    {
         Writer::new(self.w, PartialInfo::new(&self.info), self.options).init(&self.info)
     }
 
@@ -297,6 +334,15 @@ impl<'a, W: Write> Encoder<'a, W> {
         self.info.bit_depth = depth;
     }
 
+    /// Enable or disable Adam7 interlacing of the image data.
+    ///
+    /// Interlaced images can be displayed progressively while they are being loaded, at the cost
+    /// of a somewhat larger file. The image data is still supplied in the usual row-major order.
+    /// Note that a [`StreamWriter`] needs to buffer a whole frame when interlacing is enabled.
+    pub fn set_interlaced(&mut self, interlaced: bool) {
+        self.info.interlaced = interlaced;
+    }
+
     /// Set compression parameters, see [`Compression`] for the available options.
     pub fn set_compression(&mut self, compression: Compression) {
         self.set_deflate_compression(DeflateCompression::from_simple(compression));
@@ -412,6 +458,46 @@ impl<'a, W: Write> Encoder<'a, W> {
     pub fn set_pixel_dims(&mut self, pixel_dims: Option<PixelDimensions>) {
         self.info.pixel_dims = pixel_dims
     }
+
+    /// Sets the raw byte contents of the sBIT chunk, the number of significant bits of each
+    /// channel.
+    ///
+    /// There is one byte per channel, or three for indexed images (the significant bits of the
+    /// palette's red, green and blue channels).
+    pub fn set_sbit<T: Into<Cow<'a, [u8]>>>(&mut self, sbit: T) {
+        self.info.sbit = Some(sbit.into());
+    }
+
+    /// Sets the raw byte contents of the bKGD chunk, the preferred background color.
+    ///
+    /// This is a palette index for indexed images, a big-endian 16-bit gray level for grayscale
+    /// images, and three big-endian 16-bit samples otherwise.
+    pub fn set_bkgd<T: Into<Cow<'a, [u8]>>>(&mut self, bkgd: T) {
+        self.info.bkgd = Some(bkgd.into());
+    }
+
+    /// Set the coding-independent code points (cICP) identifying the color space of the image.
+    pub fn set_coding_independent_code_points(&mut self, cicp: CodingIndependentCodePoints) {
+        self.info.coding_independent_code_points = Some(cicp);
+    }
+
+    /// Set the color volume of the display that was used to master the image (mDCV).
+    pub fn set_mastering_display_color_volume(&mut self, mdcv: MasteringDisplayColorVolume) {
+        self.info.mastering_display_color_volume = Some(mdcv);
+    }
+
+    /// Set the content light level information of the image (cLLI).
+    pub fn set_content_light_level(&mut self, clli: ContentLightLevelInfo) {
+        self.info.content_light_level = Some(clli);
+    }
+
+    /// Set the time of the last image modification, written as a `tIME` chunk.
+    ///
+    /// Use [`LastModificationTime::from_system_time`] to record the current time.
+    pub fn set_last_modification_time(&mut self, time: Option<LastModificationTime>) {
+        self.info.last_modification_time = time;
+    }
+
     /// Convenience function to add tEXt chunks to [`Info`] struct
     pub fn add_text_chunk(&mut self, keyword: String, text: String) -> Result<()> {
         let text_chunk = TEXtChunk::new(keyword, text);
@@ -481,9 +567,12 @@ struct PartialInfo {
     height: u32,
     bit_depth: BitDepth,
     color_type: ColorType,
+    interlaced: bool,
     frame_control: Option<FrameControl>,
     animation_control: Option<AnimationControl>,
     has_palette: bool,
+    /// Unknown chunks that are written after the image data, just before `IEND`.
+    trailing_chunks: Vec<(ChunkType, Vec<u8>)>,
 }
 
 impl PartialInfo {
@@ -493,9 +582,16 @@ impl PartialInfo {
             height: info.height,
             bit_depth: info.bit_depth,
             color_type: info.color_type,
+            interlaced: info.interlaced,
             frame_control: info.frame_control,
             animation_control: info.animation_control,
             has_palette: info.palette.is_some(),
+            trailing_chunks: info
+                .unknown_chunks
+                .iter()
+                .filter(|chunk| chunk.position == ChunkPosition::AfterIdat)
+                .map(|chunk| (chunk.type_, chunk.data.to_vec()))
+                .collect(),
         }
     }
 
@@ -519,6 +615,37 @@ impl PartialInfo {
 
 const DEFAULT_BUFFER_LENGTH: usize = 4 * 1024;
 
+/// Splits a frame into its Adam7 passes.
+///
+/// Each pass is a reduced image of its own. It is returned as its packed rows paired with the
+/// byte length of a single row, excluding the filter byte. Empty passes are skipped.
+fn adam7_passes(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    bit_depth: BitDepth,
+) -> Vec<(Vec<u8>, usize)> {
+    let bits_pp = color_type.bits_per_pixel(bit_depth) as u8;
+    let img_row_stride = color_type.raw_row_length_from_width(bit_depth, width) - 1;
+
+    let mut passes: Vec<(Vec<u8>, usize)> = Vec::new();
+    for adam7 in Adam7Iterator::new(width, height) {
+        if adam7.line == 0 {
+            let line_len = color_type.raw_row_length_from_width(bit_depth, adam7.samples) - 1;
+            passes.push((Vec::new(), line_len));
+        }
+
+        // `Adam7Iterator` always starts a pass with its first line, so there is a last pass.
+        let (rows, line_len) = passes.last_mut().unwrap();
+        let start = rows.len();
+        rows.resize(start + *line_len, 0);
+        extract_pass(data, img_row_stride, &mut rows[start..], &adam7, bits_pp);
+    }
+
+    passes
+}
+
 pub(crate) fn write_chunk<W: Write>(mut w: W, name: chunk::ChunkType, data: &[u8]) -> Result<()> {
     w.write_be(data.len() as u32)?;
     w.write_all(&name.0)?;
@@ -562,11 +689,76 @@ impl<W: Write> Writer<W> {
             ));
         }
 
+        self.validate_metadata(info)?;
         self.encode_header(info)?;
 
         Ok(self)
     }
 
+    /// Checks the ancillary chunks whose contents depend on the color type and bit depth.
+    fn validate_metadata(&self, info: &Info<'_>) -> Result<()> {
+        let invalid = |kind| {
+            Err(EncodingError::Format(
+                FormatErrorKind::InvalidChunkData(kind).into(),
+            ))
+        };
+        let color_type = self.info.color_type;
+        let bit_depth = self.info.bit_depth as u8;
+
+        if let Some(sbit) = &info.sbit {
+            // The sample depth of indexed images is that of the palette, i.e. 8 bits.
+            let sample_depth = if color_type == ColorType::Indexed {
+                8
+            } else {
+                bit_depth
+            };
+            let expected_len = if color_type == ColorType::Indexed {
+                3
+            } else {
+                color_type.samples()
+            };
+            if sbit.len() != expected_len || sbit.iter().any(|&s| s == 0 || s > sample_depth) {
+                return invalid(chunk::sBIT);
+            }
+        }
+
+        if let Some(bkgd) = &info.bkgd {
+            let valid = match color_type {
+                ColorType::Indexed => {
+                    let entries = info.palette.as_ref().map_or(0, |p| p.len() / 3);
+                    bkgd.len() == 1 && usize::from(bkgd[0]) < entries
+                }
+                _ => {
+                    // Gray and RGB samples, even for images with an alpha channel.
+                    let samples = if color_type.samples() < 3 { 1 } else { 3 };
+                    let max = (1u32 << bit_depth) - 1;
+                    bkgd.len() == 2 * samples
+                        && bkgd
+                            .chunks_exact(2)
+                            .all(|s| u32::from(u16::from_be_bytes([s[0], s[1]])) <= max)
+                }
+            };
+            if !valid {
+                return invalid(chunk::bKGD);
+            }
+        }
+
+        if let Some(cicp) = info.coding_independent_code_points {
+            // RGB is the only color model of PNG, which requires the identity matrix.
+            if cicp.matrix_coefficients != 0 {
+                return invalid(chunk::cICP);
+            }
+        }
+
+        if let Some(mdcv) = info.mastering_display_color_volume {
+            if mdcv.to_be_bytes().is_none() {
+                return invalid(chunk::mDCV);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Encode PNG signature, IHDR, and then chunks that were added to the `Info`
     fn encode_header(&mut self, info: &Info<'_>) -> Result<()> {
         self.w.write_all(&[137, 80, 78, 71, 13, 10, 26, 10])?; // PNG signature
@@ -617,14 +809,39 @@ impl<W: Write> Writer<W> {
             }
         }
 
+        if let Some(cicp) = info.coding_independent_code_points {
+            cicp.encode(&mut self.w)?;
+        }
+
+        if let Some(mdcv) = info.mastering_display_color_volume {
+            // Checked by `validate_metadata`.
+            let data = mdcv.to_be_bytes().unwrap();
+            self.write_chunk(chunk::mDCV, &data)?;
+        }
+
+        if let Some(clli) = info.content_light_level {
+            clli.encode(&mut self.w)?;
+        }
+
+        // sBIT, like the chunks above, must come before PLTE.
+        if let Some(sbit) = &info.sbit {
+            self.write_chunk(chunk::sBIT, sbit)?;
+        }
+
         if let Some(exif) = &info.exif_metadata {
             self.write_chunk(chunk::eXIf, exif)?;
         }
 
+        if let Some(time) = info.last_modification_time {
+            time.encode(&mut self.w)?;
+        }
+
         if let Some(actl) = info.animation_control {
             actl.encode(&mut self.w)?;
         }
 
+        self.write_unknown_chunks(info, ChunkPosition::BeforePlte)?;
+
         // The position of the PLTE chunk is important, it must come before the tRNS chunk and after
         // many of the other metadata chunks.
         if let Some(p) = &info.palette {
@@ -635,6 +852,11 @@ impl<W: Write> Writer<W> {
             self.write_chunk(chunk::tRNS, t)?;
         }
 
+        // bKGD must come after PLTE, as it may refer to a palette entry.
+        if let Some(bkgd) = &info.bkgd {
+            self.write_chunk(chunk::bKGD, bkgd)?;
+        }
+
         for text_chunk in &info.uncompressed_latin1_text {
             self.write_text_chunk(text_chunk)?;
         }
@@ -647,6 +869,17 @@ impl<W: Write> Writer<W> {
             self.write_text_chunk(text_chunk)?;
         }
 
+        self.write_unknown_chunks(info, ChunkPosition::BeforeIdat)?;
+
+        Ok(())
+    }
+
+    fn write_unknown_chunks(&mut self, info: &Info<'_>, position: ChunkPosition) -> Result<()> {
+        for chunk in &info.unknown_chunks {
+            if chunk.position == position {
+                self.write_chunk(chunk.type_, &chunk.data)?;
+            }
+        }
         Ok(())
     }
 
@@ -768,49 +1001,75 @@ impl<W: Write> Writer<W> {
             ));
         }
 
-        let prev = vec![0; in_len];
-        let mut prev = prev.as_slice();
+        // Interlaced frames are split into Adam7 passes. Each pass is a reduced image of its own,
+        // filtered independently, while all of them are compressed into one zlib stream.
+        let passes: Vec<(Cow<[u8]>, usize)> = if self.info.interlaced {
+            adam7_passes(
+                data,
+                width as u32,
+                height as u32,
+                self.info.color_type,
+                self.info.bit_depth,
+            )
+            .into_iter()
+            .map(|(rows, line_len)| (Cow::Owned(rows), line_len))
+            .collect()
+        } else {
+            vec![(Cow::Borrowed(data), in_len)]
+        };
+        let rows = || {
+            passes.iter().flat_map(|(rows, line_len)| {
+                rows.chunks(*line_len)
+                    .enumerate()
+                    .map(|(idx, line)| (idx == 0, line))
+            })
+        };
+
+        let zeros = vec![0; in_len];
+        let mut prev = zeros.as_slice();
 
         let bpp = self.info.bpp_in_prediction();
         let filter_method = self.options.filter;
 
-        let zlib_encoded = match self.options.compression {
-            DeflateCompression::NoCompression => {
-                let mut compressor =
-                    fdeflate::StoredOnlyCompressor::new(std::io::Cursor::new(Vec::new()))?;
-                for line in data.chunks(in_len) {
-                    compressor.write_data(&[0])?;
-                    compressor.write_data(line)?;
-                }
-                compressor.finish()?.into_inner()
+        let store_uncompressed = || -> Result<Vec<u8>> {
+            let mut compressor =
+                fdeflate::StoredOnlyCompressor::new(std::io::Cursor::new(Vec::new()))?;
+            for (_, line) in rows() {
+                compressor.write_data(&[0])?;
+                compressor.write_data(line)?;
             }
+            Ok(compressor.finish()?.into_inner())
+        };
+
+        let zlib_encoded = match self.options.compression {
+            DeflateCompression::NoCompression => store_uncompressed()?,
             DeflateCompression::FdeflateUltraFast => {
                 let mut compressor = fdeflate::Compressor::new(std::io::Cursor::new(Vec::new()))?;
 
                 let mut current = vec![0; in_len + 1];
-                for line in data.chunks(in_len) {
+                let mut filtered_size = 0;
+                for (first_in_pass, line) in rows() {
+                    if first_in_pass {
+                        prev = &zeros[..line.len()];
+                    }
+                    let current = &mut current[..line.len() + 1];
                     let filter_type = filter(filter_method, bpp, prev, line, &mut current[1..]);
 
                     current[0] = filter_type as u8;
-                    compressor.write_data(&current)?;
+                    compressor.write_data(current)?;
+                    filtered_size += current.len();
                     prev = line;
                 }
 
                 let compressed = compressor.finish()?.into_inner();
                 if compressed.len()
-                    > fdeflate::StoredOnlyCompressor::<()>::compressed_size((in_len + 1) * height)
+                    > fdeflate::StoredOnlyCompressor::<()>::compressed_size(filtered_size)
                 {
                     // Write uncompressed data since the result from fast compression would take
                     // more space than that.
                     //
                     // This is essentially a fallback to NoCompression.
-                    let mut compressor =
-                        fdeflate::StoredOnlyCompressor::new(std::io::Cursor::new(Vec::new()))?;
-                    for line in data.chunks(in_len) {
-                        compressor.write_data(&[0])?;
-                        compressor.write_data(line)?;
-                    }
-                    compressor.finish()?.into_inner()
+                    store_uncompressed()?
                 } else {
                     compressed
                 }
@@ -820,15 +1079,24 @@ impl<W: Write> Writer<W> {
 
                 let mut zlib =
                     ZlibEncoder::new(Vec::new(), flate2::Compression::new(u32::from(level)));
-                for line in data.chunks(in_len) {
-                    let filter_type = filter(filter_method, bpp, prev, line, &mut current);
+                for (first_in_pass, line) in rows() {
+                    if first_in_pass {
+                        prev = &zeros[..line.len()];
+                    }
+                    let current = &mut current[..line.len()];
+                    let filter_type = filter(filter_method, bpp, prev, line, current);
 
                     zlib.write_all(&[filter_type as u8])?;
-                    zlib.write_all(&current)?;
+                    zlib.write_all(current)?;
                     prev = line;
                 }
                 zlib.finish()?
             }
+            #[cfg(feature = "parallel")]
+            DeflateCompression::Parallel(level) => {
+                let rows: Vec<_> = rows().collect();
+                crate::parallel_deflate::compress_rows(&rows, filter_method, bpp, level)?
+            }
         };
 
         match self.info.frame_control {
@@ -877,6 +1145,9 @@ impl<W: Write> Writer<W> {
 
     fn write_iend(&mut self) -> Result<()> {
         self.iend_written = true;
+        for (name, data) in mem::take(&mut self.info.trailing_chunks) {
+            self.write_chunk(name, &data)?;
+        }
         self.write_chunk(chunk::IEND, &[])
     }
 
@@ -1198,21 +1469,23 @@ impl<'a, W: Write> ChunkWriter<'a, W> {
     /// the next one
     fn next_frame_info(&self) -> (usize, usize) {
         let wrt = self.writer.deref();
+        let (width, height) = self.next_frame_dimensions();
+
+        let in_len = wrt.info.raw_row_length_from_width(width) - 1;
+        let data_size = in_len * height as usize;
+
+        (in_len, data_size)
+    }
+
+    /// Returns the width and height of the next frame
+    fn next_frame_dimensions(&self) -> (u32, u32) {
+        let wrt = self.writer.deref();
 
-        let width: usize;
-        let height: usize;
         if let Some(fctl) = wrt.info.frame_control {
-            width = fctl.width as usize;
-            height = fctl.height as usize;
+            (fctl.width, fctl.height)
         } else {
-            width = wrt.info.width as usize;
-            height = wrt.info.height as usize;
+            (wrt.info.width, wrt.info.height)
         }
-
-        let in_len = wrt.info.raw_row_length_from_width(width as u32) - 1;
-        let data_size = in_len * height;
-
-        (in_len, data_size)
     }
 
     /// NOTE: this bypasses the internal buffer so the flush method should be called before this
@@ -1283,8 +1556,7 @@ impl<'a, W: Write> Write for ChunkWriter<'a, W> {
 
             // Prepare the next animated frame, if any.
             let no_fctl = wrt.should_skip_frame_control_on_default_image();
-            if wrt.info.frame_control.is_some() && !no_fctl {
-                let fctl = wrt.info.frame_control.as_mut().unwrap();
+            if let Some(fctl) = wrt.info.frame_control.as_mut().filter(|_| !no_fctl) {
                 self.buffer[0..4].copy_from_slice(&fctl.sequence_number.to_be_bytes());
                 fctl.sequence_number += 1;
                 self.index = 4;
@@ -1357,6 +1629,12 @@ impl<'a, W: Write> Wrapper<'a, W> {
                 writer,
                 flate2::Compression::new(u32::from(level)),
             )),
+            // Rows arrive one at a time, there are no bands to compress in parallel.
+            #[cfg(feature = "parallel")]
+            DeflateCompression::Parallel(level) => Wrapper::Flate2(ZlibEncoder::new(
+                writer,
+                flate2::Compression::new(u32::from(level)),
+            )),
         })
     }
 
@@ -1392,7 +1670,16 @@ pub struct StreamWriter<'a, W: Write> {
 
     width: u32,
     height: u32,
+    /// Dimensions of the frame currently being written
+    frame_width: u32,
+    frame_height: u32,
 
+    color_type: ColorType,
+    bit_depth: BitDepth,
+    /// Adam7 passes need rows from all over the frame, so interlaced frames are buffered whole
+    interlaced: bool,
+    /// The complete rows of the current interlaced frame
+    frame_buf: Vec<u8>,
     bpp: BytesPerPixel,
     filter: Filter,
     fctl: Option<FrameControl>,
@@ -1412,12 +1699,16 @@ impl<'a, W: Write> StreamWriter<'a, W> {
         let in_len = writer.info.raw_row_length() - 1;
         let filter = writer.options.filter;
         let compression = writer.options.compression;
+        let color_type = writer.info.color_type;
+        let bit_depth = writer.info.bit_depth;
+        let interlaced = writer.info.interlaced;
         let prev_buf = vec![0; in_len];
         let curr_buf = vec![0; in_len];
         let filtered_buf = vec![0; in_len];
 
         let mut chunk_writer = ChunkWriter::new(writer, buf_len);
         let (line_len, to_write) = chunk_writer.next_frame_info();
+        let (frame_width, frame_height) = chunk_writer.next_frame_dimensions();
         chunk_writer.write_header()?;
 
         Ok(StreamWriter {
@@ -1430,6 +1721,12 @@ impl<'a, W: Write> StreamWriter<'a, W> {
             filter,
             width,
             height,
+            frame_width,
+            frame_height,
+            color_type,
+            bit_depth,
+            interlaced,
+            frame_buf: Vec::new(),
             line_len,
             to_write,
             fctl,
@@ -1437,6 +1734,43 @@ impl<'a, W: Write> StreamWriter<'a, W> {
         })
     }
 
+    /// Filters and compresses the Adam7 passes of a fully buffered interlaced frame.
+    fn write_interlaced_frame(&mut self) -> io::Result<()> {
+        let passes = adam7_passes(
+            &self.frame_buf,
+            self.frame_width,
+            self.frame_height,
+            self.color_type,
+            self.bit_depth,
+        );
+
+        for (rows, line_len) in passes {
+            let prev_buf = &mut self.prev_buf[..line_len];
+            prev_buf.fill(0);
+
+            for line in rows.chunks(line_len) {
+                let filtered_buf = &mut self.filtered_buf[..line_len];
+                let filter_type = filter(self.filter, self.bpp, prev_buf, line, filtered_buf);
+                // This can't fail as the other variant is used only to allow the zlib encoder to finish
+                match &mut self.writer {
+                    Wrapper::Flate2(wrt) => {
+                        wrt.write_all(&[filter_type as u8])?;
+                        wrt.write_all(filtered_buf)?;
+                    }
+                    Wrapper::FDeflate(wrt) => {
+                        wrt.write_data(&[filter_type as u8])?;
+                        wrt.write_data(filtered_buf)?;
+                    }
+                    _ => unreachable!(),
+                };
+
+                prev_buf.copy_from_slice(line);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Set the used filter type.
     ///
     /// The default filter is [`Filter::Adaptive`] which automatically selects the best filter
@@ -1661,6 +1995,7 @@ impl<'a, W: Write> StreamWriter<'a, W> {
         let (scansize, size) = wrt.next_frame_info();
         self.line_len = scansize;
         self.to_write = size;
+        (self.frame_width, self.frame_height) = wrt.next_frame_dimensions();
 
         wrt.write_header()?;
         wrt.writer.increment_images_written();
@@ -1721,7 +2056,15 @@ impl<'a, W: Write> Write for StreamWriter<'a, W> {
         self.index += written;
         self.to_write -= written;
 
-        if self.index == self.line_len {
+        if self.index == self.line_len && self.interlaced {
+            self.frame_buf
+                .extend_from_slice(&self.curr_buf[..self.line_len]);
+            if self.to_write == 0 {
+                self.write_interlaced_frame()?;
+                self.frame_buf.clear();
+            }
+            self.index = 0;
+        } else if self.index == self.line_len {
             let filter_type = filter(
                 self.filter,
                 self.bpp,
@@ -1780,7 +2123,7 @@ impl<W: Write> Drop for StreamWriter<'_, W> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Decoder;
+    use crate::{Decoder, SourceChromaticities, UnknownChunk};
 
     use io::BufReader;
     use rand::{rng, Rng};
@@ -1912,6 +2255,431 @@ mod tests {
         }
     }
 
+    #[test]
+    fn roundtrip_interlaced() {
+        for path in glob::glob("tests/pngsuite/*.png")
+            .unwrap()
+            .map(|r| r.unwrap())
+        {
+            if path.file_name().unwrap().to_str().unwrap().starts_with('x') {
+                // x* files are expected to fail to decode
+                continue;
+            }
+            // Decode image
+            let decoder = Decoder::new(BufReader::new(File::open(path).unwrap()));
+            let mut reader = decoder.read_info().unwrap();
+            let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+            let info = reader.next_frame(&mut buf).unwrap();
+            use DeflateCompression::*;
+            for compression in [NoCompression, FdeflateUltraFast, Level(4)] {
+                for streaming in [false, true] {
+                    // Encode decoded image with interlacing
+                    let mut out = Vec::new();
+                    {
+                        let mut encoder = Encoder::new(&mut out, info.width, info.height);
+                        encoder.set_color(info.color_type);
+                        encoder.set_depth(info.bit_depth);
+                        encoder.set_interlaced(true);
+                        encoder.set_deflate_compression(compression);
+                        if let Some(palette) = &reader.info().palette {
+                            encoder.set_palette(palette.clone());
+                        }
+                        let mut encoder = encoder.write_header().unwrap();
+                        if streaming {
+                            let mut stream_writer = encoder.stream_writer().unwrap();
+                            let mut outer_wrapper = RandomChunkWriter {
+                                rng: rng(),
+                                w: &mut stream_writer,
+                            };
+                            outer_wrapper.write_all(&buf).unwrap();
+                            stream_writer.finish().unwrap();
+                        } else {
+                            encoder.write_image_data(&buf).unwrap();
+                        }
+                    }
+                    // Decode encoded decoded image
+                    let decoder = Decoder::new(Cursor::new(&*out));
+                    let mut reader = decoder.read_info().unwrap();
+                    assert!(reader.info().interlaced);
+                    let mut buf2 = vec![0; reader.output_buffer_size().unwrap()];
+                    reader.next_frame(&mut buf2).unwrap();
+                    // check if the encoded image is ok:
+                    assert_eq!(buf, buf2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn roundtrip_parallel() {
+        for path in glob::glob("tests/pngsuite/*.png")
+            .unwrap()
+            .map(|r| r.unwrap())
+        {
+            if path.file_name().unwrap().to_str().unwrap().starts_with('x') {
+                // x* files are expected to fail to decode
+                continue;
+            }
+            // Decode image
+            let decoder = Decoder::new(BufReader::new(File::open(path).unwrap()));
+            let mut reader = decoder.read_info().unwrap();
+            let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+            let info = reader.next_frame(&mut buf).unwrap();
+            for interlaced in [false, true] {
+                let mut out = Vec::new();
+                {
+                    let mut encoder = Encoder::new(&mut out, info.width, info.height);
+                    encoder.set_color(info.color_type);
+                    encoder.set_depth(info.bit_depth);
+                    encoder.set_interlaced(interlaced);
+                    encoder.set_deflate_compression(DeflateCompression::Parallel(6));
+                    if let Some(palette) = &reader.info().palette {
+                        encoder.set_palette(palette.clone());
+                    }
+                    let mut encoder = encoder.write_header().unwrap();
+                    encoder.write_image_data(&buf).unwrap();
+                }
+                let decoder = Decoder::new(Cursor::new(&*out));
+                let mut reader = decoder.read_info().unwrap();
+                let mut buf2 = vec![0; reader.output_buffer_size().unwrap()];
+                reader.next_frame(&mut buf2).unwrap();
+                assert_eq!(buf, buf2);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn roundtrip_parallel_large() {
+        let (width, height) = (1024, 1024);
+        let pixels: Vec<u8> = (0..width * height * 4)
+            .map(|i| (i ^ (i >> 11)) as u8)
+            .collect();
+        for interlaced in [false, true] {
+            let mut out = Vec::new();
+            let mut encoder = Encoder::new(&mut out, width as u32, height as u32);
+            encoder.set_color(ColorType::Rgba);
+            encoder.set_interlaced(interlaced);
+            encoder.set_deflate_compression(DeflateCompression::Parallel(3));
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&pixels).unwrap();
+            writer.finish().unwrap();
+
+            let decoder = Decoder::new(Cursor::new(&*out));
+            let mut reader = decoder.read_info().unwrap();
+            let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+            reader.next_frame(&mut buf).unwrap();
+            assert_eq!(buf, pixels);
+        }
+    }
+
+    #[test]
+    fn interlaced_stream_flushing() -> Result<()> {
+        let pixels: Vec<u8> = (0..=255).collect();
+        let mut cursor = Cursor::new(Vec::new());
+
+        let mut encoder = Encoder::new(&mut cursor, 8, 8);
+        encoder.set_color(ColorType::Rgba);
+        encoder.set_interlaced(true);
+        let mut writer = encoder.write_header()?;
+        let mut stream = writer.stream_writer()?;
+
+        for row in pixels.chunks(32) {
+            stream.write_all(row)?;
+            stream.flush()?;
+        }
+        stream.finish()?;
+        drop(writer);
+
+        cursor.set_position(0);
+        let mut decoder = Decoder::new(cursor).read_info().expect("A valid image");
+        let mut buffer = [0u8; 256];
+        decoder.next_frame(&mut buffer[..]).expect("Valid read");
+        assert_eq!(buffer[..], pixels[..]);
+
+        Ok(())
+    }
+
+    /// Lists the chunk types of an encoded PNG, in order.
+    fn chunk_types(png: &[u8]) -> Vec<ChunkType> {
+        let mut types = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            types.push(ChunkType(rest[4..8].try_into().unwrap()));
+            rest = &rest[12 + length..];
+        }
+        types
+    }
+
+    #[test]
+    fn roundtrip_unknown_chunks() -> Result<()> {
+        let mut info = Info::with_size(2, 2);
+        info.color_type = ColorType::Indexed;
+        info.palette = Some(Cow::Borrowed(&[0, 0, 0, 255, 255, 255]));
+        info.unknown_chunks = vec![
+            UnknownChunk {
+                type_: ChunkType(*b"vpAg"),
+                data: Cow::Borrowed(b"after idat"),
+                position: ChunkPosition::AfterIdat,
+            },
+            UnknownChunk {
+                type_: ChunkType(*b"caNv"),
+                data: Cow::Borrowed(b"before idat"),
+                position: ChunkPosition::BeforeIdat,
+            },
+            UnknownChunk {
+                type_: ChunkType(*b"prVW"),
+                data: Cow::Borrowed(b"before plte"),
+                position: ChunkPosition::BeforePlte,
+            },
+        ];
+
+        for streaming in [false, true] {
+            let mut out = Vec::new();
+            {
+                let encoder = Encoder::with_info(&mut out, info.clone())?;
+                let mut writer = encoder.write_header()?;
+                if streaming {
+                    let mut stream_writer = writer.stream_writer()?;
+                    stream_writer.write_all(&[0, 1, 1, 0])?;
+                    stream_writer.finish()?;
+                } else {
+                    writer.write_image_data(&[0, 1, 1, 0])?;
+                    writer.finish()?;
+                }
+            }
+
+            let expected: Vec<ChunkType> = [
+                chunk::IHDR,
+                ChunkType(*b"prVW"),
+                chunk::PLTE,
+                ChunkType(*b"caNv"),
+                chunk::IDAT,
+                ChunkType(*b"vpAg"),
+                chunk::IEND,
+            ]
+            .into();
+            let mut types = chunk_types(&out);
+            types.dedup();
+            assert_eq!(types, expected);
+
+            // Decode and re-encode again, the chunks must survive byte-for-byte.
+            let mut decoder = Decoder::new(Cursor::new(&*out));
+            decoder.set_keep_unknown_chunks(true);
+            let mut reader = decoder.read_info().unwrap();
+            let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+            reader.next_frame(&mut buf).unwrap();
+            reader.finish().unwrap();
+            let decoded = reader.info().clone();
+
+            // The decoder reports chunks in file order.
+            let mut in_file_order = info.unknown_chunks.clone();
+            in_file_order.reverse();
+            assert_eq!(decoded.unknown_chunks, in_file_order);
+
+            let mut out2 = Vec::new();
+            let mut writer = Encoder::with_info(&mut out2, decoded)?.write_header()?;
+            if streaming {
+                let mut stream_writer = writer.stream_writer()?;
+                stream_writer.write_all(&buf)?;
+                stream_writer.finish()?;
+            } else {
+                writer.write_image_data(&buf)?;
+            }
+            drop(writer);
+            assert_eq!(out, out2);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_last_modification_time() -> Result<()> {
+        let time = LastModificationTime::new(2024, 2, 29, 13, 37, 0);
+        let mut out = Vec::new();
+        {
+            let mut encoder = Encoder::new(&mut out, 1, 1);
+            encoder.set_last_modification_time(time);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&[0])?;
+        }
+
+        let reader = Decoder::new(Cursor::new(out)).read_info().unwrap();
+        assert_eq!(reader.info().last_modification_time, time);
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_color_metadata() -> Result<()> {
+        let cicp = CodingIndependentCodePoints {
+            color_primaries: 9,
+            transfer_function: 16,
+            matrix_coefficients: 0,
+            is_video_full_range_image: true,
+        };
+        let mdcv = MasteringDisplayColorVolume {
+            chromaticities: SourceChromaticities::new(
+                (0.3127, 0.3290),
+                (0.708, 0.292),
+                (0.170, 0.797),
+                (0.131, 0.046),
+            ),
+            max_luminance: 10_000_000,
+            min_luminance: 50,
+        };
+        let clli = ContentLightLevelInfo {
+            max_content_light_level: 4_000_000,
+            max_frame_average_light_level: 1_000_000,
+        };
+
+        let mut out = Vec::new();
+        {
+            let mut encoder = Encoder::new(&mut out, 1, 1);
+            encoder.set_color(ColorType::Rgb);
+            encoder.set_depth(BitDepth::Sixteen);
+            encoder.set_sbit(&[10, 10, 10][..]);
+            encoder.set_bkgd(&[0x03, 0xff, 0, 0, 0, 1][..]);
+            encoder.set_coding_independent_code_points(cicp);
+            encoder.set_mastering_display_color_volume(mdcv);
+            encoder.set_content_light_level(clli);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&[0; 6])?;
+        }
+
+        let reader = Decoder::new(Cursor::new(out)).read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.sbit.as_deref(), Some(&[10, 10, 10][..]));
+        assert_eq!(info.bkgd.as_deref(), Some(&[0x03, 0xff, 0, 0, 0, 1][..]));
+        assert_eq!(info.coding_independent_code_points, Some(cicp));
+        assert_eq!(info.mastering_display_color_volume, Some(mdcv));
+        assert_eq!(info.content_light_level, Some(clli));
+        Ok(())
+    }
+
+    #[test]
+    fn color_metadata_ordering() -> Result<()> {
+        let mut out = Vec::new();
+        {
+            let mut encoder = Encoder::new(&mut out, 1, 1);
+            encoder.set_color(ColorType::Indexed);
+            encoder.set_palette(&[0, 0, 0, 255, 255, 255][..]);
+            encoder.set_sbit(&[5, 6, 5][..]);
+            encoder.set_bkgd(&[1][..]);
+            encoder.set_coding_independent_code_points(CodingIndependentCodePoints {
+                color_primaries: 1,
+                transfer_function: 13,
+                matrix_coefficients: 0,
+                is_video_full_range_image: true,
+            });
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&[0])?;
+        }
+
+        let types = chunk_types(&out);
+        let position = |kind| types.iter().position(|&t| t == kind).unwrap();
+        assert!(position(chunk::cICP) < position(chunk::PLTE));
+        assert!(position(chunk::sBIT) < position(chunk::PLTE));
+        assert!(position(chunk::PLTE) < position(chunk::bKGD));
+        assert!(position(chunk::bKGD) < position(chunk::IDAT));
+
+        let reader = Decoder::new(Cursor::new(out)).read_info().unwrap();
+        assert_eq!(reader.info().sbit.as_deref(), Some(&[5, 6, 5][..]));
+        assert_eq!(reader.info().bkgd.as_deref(), Some(&[1][..]));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_color_metadata_is_rejected() {
+        fn write_header(color_type: ColorType, bit_depth: BitDepth, info: Info<'_>) -> Result<()> {
+            let mut info = Info {
+                width: 1,
+                height: 1,
+                color_type,
+                bit_depth,
+                ..info
+            };
+            if color_type == ColorType::Indexed {
+                info.palette = Some(Cow::Borrowed(&[0, 0, 0, 255, 255, 255]));
+            }
+            Encoder::with_info(Vec::new(), info)?.write_header()?;
+            Ok(())
+        }
+        fn assert_invalid(color_type: ColorType, bit_depth: BitDepth, info: Info<'_>) {
+            assert!(matches!(
+                write_header(color_type, bit_depth, info),
+                Err(EncodingError::Format(_))
+            ));
+        }
+        let with_sbit = |sbit: &'static [u8]| Info {
+            sbit: Some(Cow::Borrowed(sbit)),
+            ..Info::default()
+        };
+        let with_bkgd = |bkgd: &'static [u8]| Info {
+            bkgd: Some(Cow::Borrowed(bkgd)),
+            ..Info::default()
+        };
+
+        use BitDepth::*;
+        use ColorType::*;
+        assert!(write_header(GrayscaleAlpha, Eight, with_sbit(&[8, 1])).is_ok());
+        assert_invalid(GrayscaleAlpha, Eight, with_sbit(&[8]));
+        assert_invalid(Grayscale, Four, with_sbit(&[5]));
+        assert_invalid(Rgb, Eight, with_sbit(&[8, 0, 8]));
+        assert!(write_header(Indexed, Two, with_sbit(&[8, 8, 8])).is_ok());
+        assert_invalid(Indexed, Two, with_sbit(&[8]));
+
+        assert!(write_header(Grayscale, Four, with_bkgd(&[0, 15])).is_ok());
+        assert_invalid(Grayscale, Four, with_bkgd(&[0, 16]));
+        assert_invalid(Rgba, Eight, with_bkgd(&[0, 1, 1, 0, 0, 1]));
+        assert_invalid(Rgb, Sixteen, with_bkgd(&[0, 1]));
+        assert!(write_header(Indexed, Eight, with_bkgd(&[1])).is_ok());
+        assert_invalid(Indexed, Eight, with_bkgd(&[2]));
+
+        let cicp = CodingIndependentCodePoints {
+            color_primaries: 1,
+            transfer_function: 13,
+            matrix_coefficients: 1,
+            is_video_full_range_image: true,
+        };
+        let info = Info {
+            coding_independent_code_points: Some(cicp),
+            ..Info::default()
+        };
+        assert_invalid(Rgb, Eight, info);
+
+        let mdcv = MasteringDisplayColorVolume {
+            chromaticities: SourceChromaticities::new(
+                (1.5, 0.3),
+                (0.6, 0.3),
+                (0.3, 0.6),
+                (0.15, 0.06),
+            ),
+            max_luminance: 1,
+            min_luminance: 0,
+        };
+        let info = Info {
+            mastering_display_color_volume: Some(mdcv),
+            ..Info::default()
+        };
+        assert_invalid(Rgb, Eight, info);
+    }
+
+    #[test]
+    fn critical_unknown_chunk_is_rejected() {
+        let mut info = Info::with_size(1, 1);
+        info.unknown_chunks.push(UnknownChunk {
+            type_: ChunkType(*b"CRIT"),
+            data: Cow::Borrowed(&[]),
+            position: ChunkPosition::BeforeIdat,
+        });
+        assert!(matches!(
+            Encoder::with_info(Vec::new(), info),
+            Err(EncodingError::Format(_))
+        ));
+    }
+
     #[test]
     fn image_palette() -> Result<()> {
         for &bit_depth in &[1u8, 2, 4, 8] {
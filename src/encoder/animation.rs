@@ -0,0 +1,638 @@
+//! Encoding full canvas frames as minimal APNG subframes.
+//!
+//! Each frame is held back until the next one arrives, because the dispose operation of a frame
+//! is written in its own `fcTL` chunk but only pays off depending on the frame that follows it.
+use std::io::Write;
+use std::ops::Range;
+
+use super::{Encoder, EncodingError, FormatErrorKind, Result, Writer};
+use crate::common::{BlendOp, ColorType, DisposeOp, ParameterErrorKind};
+use crate::decoder::region::copy_pixels;
+
+/// Encodes an animation from full canvas frames, writing only what changed between them.
+///
+/// Every frame covers the whole image. For each frame the encoder writes the bounding box of the
+/// pixels that differ from what is displayed before it, choosing the [`DisposeOp`] of the
+/// previous frame that keeps this box smallest. With
+/// [`set_transparent_unchanged`](Self::set_transparent_unchanged) pixels in the box that did not
+/// change are made transparent and blended with [`BlendOp::Over`], which usually compresses
+/// better.
+///
+/// The number of frames is configured with [`Encoder::set_animated`] and checked when the
+/// animation is finished.
+///
+/// ```
+/// use png::{AnimationEncoder, BitDepth, ColorType, Encoder};
+///
+/// let mut png = Vec::new();
+/// let mut encoder = Encoder::new(&mut png, 2, 2);
+/// encoder.set_color(ColorType::Rgba);
+/// encoder.set_depth(BitDepth::Eight);
+/// encoder.set_animated(2, 0).unwrap();
+///
+/// let mut animation = AnimationEncoder::new(encoder).unwrap();
+/// animation.set_transparent_unchanged(true);
+/// let mut frame = [255; 16];
+/// animation.write_frame(&frame, 1, 10).unwrap();
+/// frame[12..].copy_from_slice(&[0, 0, 255, 255]);
+/// // Only the changed pixel in the bottom right corner is written.
+/// animation.write_frame(&frame, 1, 10).unwrap();
+/// animation.finish().unwrap();
+/// ```
+pub struct AnimationEncoder<W: Write> {
+    writer: Writer<W>,
+    width: u32,
+    height: u32,
+    bits_pp: usize,
+    /// The number of bytes of the alpha sample, for color types with an alpha channel.
+    alpha_bytes: Option<usize>,
+    transparent_unchanged: bool,
+    /// The last frame, written once the dispose operation for it is known.
+    pending: Option<Frame>,
+}
+
+/// A frame that has not been written yet.
+struct Frame {
+    /// The full canvas as it is displayed with this frame.
+    canvas: Vec<u8>,
+    /// The canvas that this frame is drawn onto, or `None` if it is cleared and the color type
+    /// has no alpha channel, so that no pixel value matches it.
+    base: Option<Vec<u8>>,
+    region: Region,
+    blend_op: BlendOp,
+    delay_num: u16,
+    delay_den: u16,
+}
+
+/// A rectangle of the canvas, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Region {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Region {
+    fn area(self) -> u64 {
+        u64::from(self.width) * u64::from(self.height)
+    }
+}
+
+impl<W: Write> AnimationEncoder<W> {
+    /// Writes the header of the image and starts the animation.
+    ///
+    /// Returns an error if the encoder was not configured with [`Encoder::set_animated`]. A
+    /// separate default image is not supported, the first frame is always part of the animation.
+    pub fn new(mut encoder: Encoder<'_, W>) -> Result<Self> {
+        if encoder.info.animation_control.is_none() {
+            return Err(EncodingError::Format(FormatErrorKind::NotAnimated.into()));
+        }
+        encoder.options.sep_def_img = false;
+        encoder.options.validate_sequence = true;
+
+        let (width, height) = (encoder.info.width, encoder.info.height);
+        let (color_type, bit_depth) = (encoder.info.color_type, encoder.info.bit_depth);
+        let alpha_bytes = match color_type {
+            ColorType::GrayscaleAlpha | ColorType::Rgba => {
+                Some(color_type.bytes_per_pixel(bit_depth) / color_type.samples())
+            }
+            _ => None,
+        };
+        Ok(AnimationEncoder {
+            writer: encoder.write_header()?,
+            width,
+            height,
+            bits_pp: color_type.bits_per_pixel(bit_depth),
+            alpha_bytes,
+            transparent_unchanged: false,
+            pending: None,
+        })
+    }
+
+    /// Make pixels that did not change transparent and blend frames with [`BlendOp::Over`].
+    ///
+    /// This only applies to color types with an alpha channel, and to frames where blending
+    /// reproduces the frame exactly. It is disabled by default.
+    pub fn set_transparent_unchanged(&mut self, transparent_unchanged: bool) {
+        self.transparent_unchanged = transparent_unchanged;
+    }
+
+    /// Adds a frame covering the whole image, shown for `delay_num / delay_den` seconds.
+    ///
+    /// The frame is written when the next frame is added or the animation is finished.
+    pub fn write_frame(&mut self, data: &[u8], delay_num: u16, delay_den: u16) -> Result<()> {
+        let expected = self.line_size(self.width) * self.height as usize;
+        if data.len() != expected {
+            return Err(EncodingError::Parameter(
+                ParameterErrorKind::ImageBufferSize {
+                    expected,
+                    actual: data.len(),
+                }
+                .into(),
+            ));
+        }
+
+        let Some(last) = self.pending.take() else {
+            self.pending = Some(Frame {
+                canvas: data.to_vec(),
+                base: self.alpha_bytes.map(|_| vec![0; data.len()]),
+                region: self.full_region(),
+                blend_op: BlendOp::Source,
+                delay_num,
+                delay_den,
+            });
+            return Ok(());
+        };
+
+        // Pick the disposal of the last frame that leaves the fewest pixels to write.
+        let mut dispose_op = DisposeOp::None;
+        let mut region = self.changed_region(&last.canvas, data);
+        let cleared = self.alpha_bytes.map(|_| {
+            let mut cleared = last.canvas.clone();
+            for range in self.region_rows(last.region) {
+                cleared[range].fill(0);
+            }
+            cleared
+        });
+        if let Some(cleared) = &cleared {
+            let cleared_region = self.changed_region(cleared, data);
+            if area(cleared_region) < area(region) {
+                (dispose_op, region) = (DisposeOp::Background, cleared_region);
+            }
+        }
+        if let Some(base) = &last.base {
+            let restored_region = self.changed_region(base, data);
+            if area(restored_region) < area(region) {
+                (dispose_op, region) = (DisposeOp::Previous, restored_region);
+            }
+        }
+
+        self.write_subframe(&last, dispose_op)?;
+
+        let base = match dispose_op {
+            DisposeOp::None => Some(last.canvas),
+            DisposeOp::Background => cleared,
+            DisposeOp::Previous => last.base,
+        };
+        // An identical frame still needs a subframe of at least a single pixel.
+        let region = region.unwrap_or(Region {
+            x: 0,
+            y: 0,
+            width: 1,
+            height: 1,
+        });
+        let blend_op = if self.transparent_unchanged
+            && base
+                .as_deref()
+                .is_some_and(|base| self.can_blend_over(base, data, region))
+        {
+            BlendOp::Over
+        } else {
+            BlendOp::Source
+        };
+        self.pending = Some(Frame {
+            canvas: data.to_vec(),
+            base,
+            region,
+            blend_op,
+            delay_num,
+            delay_den,
+        });
+        Ok(())
+    }
+
+    /// Writes the last frame and finishes the image.
+    ///
+    /// Returns an error if fewer frames were added than configured with
+    /// [`Encoder::set_animated`].
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(last) = self.pending.take() {
+            self.write_subframe(&last, DisposeOp::None)?;
+        }
+        self.writer.finish()
+    }
+
+    fn write_subframe(&mut self, frame: &Frame, dispose_op: DisposeOp) -> Result<()> {
+        let region = frame.region;
+        let line_size = self.line_size(region.width);
+        let mut data = vec![0; line_size * region.height as usize];
+        let stride = self.line_size(self.width);
+        let rows = frame.canvas.chunks_exact(stride).skip(region.y as usize);
+        for (y, (row, output)) in rows.zip(data.chunks_exact_mut(line_size)).enumerate() {
+            copy_pixels(row, region.x, region.width, self.bits_pp, output);
+            if let (BlendOp::Over, Some(base)) = (frame.blend_op, &frame.base) {
+                // Unchanged pixels are left to the canvas below.
+                let base = &base[(region.y as usize + y) * stride..][..stride];
+                let pixel_bytes = self.bits_pp / 8;
+                let start = region.x as usize * pixel_bytes;
+                let changed = row[start..]
+                    .chunks_exact(pixel_bytes)
+                    .zip(base[start..].chunks_exact(pixel_bytes));
+                for ((pixel, before), output) in changed.zip(output.chunks_exact_mut(pixel_bytes)) {
+                    if pixel == before {
+                        output.fill(0);
+                    }
+                }
+            }
+        }
+
+        let writer = &mut self.writer;
+        writer.set_frame_delay(frame.delay_num, frame.delay_den)?;
+        writer.reset_frame_position()?;
+        writer.set_frame_dimension(region.width, region.height)?;
+        writer.set_frame_position(region.x, region.y)?;
+        writer.set_blend_op(frame.blend_op)?;
+        writer.set_dispose_op(dispose_op)?;
+        writer.write_image_data(&data)
+    }
+
+    fn line_size(&self, width: u32) -> usize {
+        (width as usize * self.bits_pp).div_ceil(8)
+    }
+
+    fn full_region(&self) -> Region {
+        Region {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Returns the byte ranges of the rows of a region of the canvas, which must be byte aligned.
+    fn region_rows(&self, region: Region) -> impl Iterator<Item = Range<usize>> {
+        let pixel_bytes = self.bits_pp / 8;
+        let stride = self.line_size(self.width);
+        let start = region.x as usize * pixel_bytes;
+        let len = region.width as usize * pixel_bytes;
+        (region.y as usize..)
+            .take(region.height as usize)
+            .map(move |y| y * stride + start..y * stride + start + len)
+    }
+
+    /// Returns the bounding box of the pixels that differ between two canvases.
+    fn changed_region(&self, before: &[u8], after: &[u8]) -> Option<Region> {
+        let stride = self.line_size(self.width);
+        let width = self.width as usize;
+        let mut bounds: Option<(usize, usize, usize, usize)> = None;
+        let rows = before.chunks_exact(stride).zip(after.chunks_exact(stride));
+        for (y, (before, after)) in rows.enumerate() {
+            if before == after {
+                continue;
+            }
+            let same = |x: &usize| same_pixel(before, after, *x, self.bits_pp);
+            let left = (0..width).find(|x| !same(x)).unwrap();
+            let right = (0..width).rev().find(|x| !same(x)).unwrap();
+            bounds = Some(match bounds {
+                None => (left, right, y, y),
+                Some((l, r, top, _)) => (l.min(left), r.max(right), top, y),
+            });
+        }
+        bounds.map(|(left, right, top, bottom)| Region {
+            x: left as u32,
+            y: top as u32,
+            width: (right - left + 1) as u32,
+            height: (bottom - top + 1) as u32,
+        })
+    }
+
+    /// Whether blending the region of `canvas`, with unchanged pixels made transparent, over
+    /// `base` reproduces `canvas` exactly.
+    fn can_blend_over(&self, base: &[u8], canvas: &[u8], region: Region) -> bool {
+        let Some(alpha_bytes) = self.alpha_bytes else {
+            return false;
+        };
+        let pixel_bytes = self.bits_pp / 8;
+        self.region_rows(region).all(|range| {
+            let pixels = canvas[range.clone()].chunks_exact(pixel_bytes);
+            let before = base[range].chunks_exact(pixel_bytes);
+            pixels.zip(before).all(|(pixel, before)| {
+                // Opaque pixels replace the canvas, and so does any visible pixel drawn onto a
+                // transparent one.
+                let alpha = &pixel[pixel_bytes - alpha_bytes..];
+                let below = &before[pixel_bytes - alpha_bytes..];
+                pixel == before
+                    || alpha.iter().all(|&b| b == 0xff)
+                    || (below.iter().all(|&b| b == 0) && alpha.iter().any(|&b| b != 0))
+            })
+        })
+    }
+}
+
+fn area(region: Option<Region>) -> u64 {
+    region.map_or(0, Region::area)
+}
+
+/// Whether the pixel at `idx` is the same in both rows.
+fn same_pixel(a: &[u8], b: &[u8], idx: usize, bits_pp: usize) -> bool {
+    if bits_pp % 8 == 0 {
+        let bytes_pp = bits_pp / 8;
+        a[idx * bytes_pp..][..bytes_pp] == b[idx * bytes_pp..][..bytes_pp]
+    } else {
+        let bit = idx * bits_pp;
+        let mask = ((1u8 << bits_pp) - 1) << (8 - bits_pp - bit % 8);
+        (a[bit / 8] ^ b[bit / 8]) & mask == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{same_pixel, AnimationEncoder, Region};
+    use crate::test_codec::encode_png;
+    use crate::{
+        AnimationCompositor, BitDepth, BlendOp, ColorType, Decoder, DisposeOp, Encoder,
+        FrameControl,
+    };
+
+    /// Writes the frames with an [`AnimationEncoder`], configured like
+    /// [`encode_png`] configures a still image.
+    fn encode(
+        (width, height): (u32, u32),
+        (color, depth): (ColorType, BitDepth),
+        frames: &[Vec<u8>],
+        transparent_unchanged: bool,
+        configure: impl FnOnce(&mut Encoder<&mut Vec<u8>>),
+    ) -> Vec<u8> {
+        let mut png = Vec::new();
+        let mut encoder = Encoder::new(&mut png, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        configure(&mut encoder);
+        encoder.set_animated(frames.len() as u32, 0).unwrap();
+        let mut animation = AnimationEncoder::new(encoder).unwrap();
+        animation.set_transparent_unchanged(transparent_unchanged);
+        for (idx, frame) in frames.iter().enumerate() {
+            animation.write_frame(frame, idx as u16, 100).unwrap();
+        }
+        animation.finish().unwrap();
+        png
+    }
+
+    /// Composes all frames, as they would be displayed.
+    fn composited(png: &[u8]) -> Vec<(Vec<u8>, u16)> {
+        let mut compositor = AnimationCompositor::new(Decoder::new(Cursor::new(png))).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = compositor.next_frame().unwrap() {
+            frames.push((frame.buffer.to_vec(), frame.delay_num));
+        }
+        compositor.finish().unwrap();
+        frames
+    }
+
+    fn frame_controls(png: &[u8]) -> Vec<FrameControl> {
+        let mut reader = Decoder::new(Cursor::new(png)).read_info().unwrap();
+        let mut controls = vec![reader.info().frame_control.unwrap()];
+        let num_frames = reader.info().animation_control.unwrap().num_frames;
+        for _ in 1..num_frames {
+            controls.push(*reader.next_frame_info().unwrap());
+        }
+        controls
+    }
+
+    /// Encodes the frames and checks that they are displayed exactly as given.
+    fn check_roundtrip(size: (u32, u32), format: (ColorType, BitDepth), frames: &[Vec<u8>]) {
+        let set_palette = |encoder: &mut Encoder<&mut Vec<u8>>| {
+            if format.0 == ColorType::Indexed {
+                let palette = (0..=255).flat_map(|i| [i, i / 2, 255 - i]).collect();
+                encoder.set_palette::<Vec<u8>>(palette);
+            }
+        };
+        let expected: Vec<_> = frames
+            .iter()
+            .enumerate()
+            .map(|(idx, frame)| {
+                let png = encode_png(size, format, frame, set_palette);
+                (composited(&png).remove(0).0, idx as u16)
+            })
+            .collect();
+
+        for transparent_unchanged in [false, true] {
+            let png = encode(size, format, frames, transparent_unchanged, set_palette);
+            assert_eq!(composited(&png), expected, "{format:?}");
+        }
+    }
+
+    /// Frames of a moving square, with a few changes to the background.
+    fn moving_square(size: (u32, u32), pixel: &dyn Fn(u32, u32, u32) -> Vec<u8>) -> Vec<Vec<u8>> {
+        (0..6)
+            .map(|frame| {
+                (0..size.1)
+                    .flat_map(|y| (0..size.0).flat_map(move |x| pixel(x, y, frame)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn in_square(x: u32, y: u32, frame: u32) -> bool {
+        let (left, top) = (frame * 2, 1 + frame % 3);
+        (left..left + 3).contains(&x) && (top..top + 3).contains(&y)
+    }
+
+    #[test]
+    fn roundtrip_rgba() {
+        let frames = moving_square((16, 8), &|x, y, frame| {
+            if in_square(x, y, frame) {
+                vec![255, 0, 0, if frame == 3 { 128 } else { 255 }]
+            } else if frame == 4 && x == 15 {
+                vec![0, 255, 0, 255]
+            } else {
+                vec![0; 4]
+            }
+        });
+        check_roundtrip((16, 8), (ColorType::Rgba, BitDepth::Eight), &frames);
+    }
+
+    #[test]
+    fn roundtrip_opaque_and_wide() {
+        let frames = moving_square((16, 8), &|x, y, frame| {
+            let value = if in_square(x, y, frame) {
+                200
+            } else {
+                (x * y) as u8
+            };
+            vec![value, 1, value, 2, 3, value]
+        });
+        check_roundtrip((16, 8), (ColorType::Rgb, BitDepth::Sixteen), &frames);
+
+        let frames = moving_square((16, 8), &|x, y, frame| {
+            let gray = if in_square(x, y, frame) { 0x12 } else { 0x80 };
+            let alpha = if (x + frame) % 5 == 0 { 0 } else { 0xff };
+            vec![gray, gray, alpha, alpha]
+        });
+        check_roundtrip(
+            (16, 8),
+            (ColorType::GrayscaleAlpha, BitDepth::Sixteen),
+            &frames,
+        );
+    }
+
+    #[test]
+    fn roundtrip_subbyte() {
+        let frames = moving_square((13, 8), &|x, y, frame| {
+            vec![if in_square(x, y, frame) {
+                3
+            } else {
+                ((x + y) % 3) as u8
+            }]
+        });
+        let pack = |frame: &Vec<u8>| -> Vec<u8> {
+            frame
+                .chunks(13)
+                .flat_map(|row| {
+                    row.chunks(4).map(|pixels| {
+                        pixels
+                            .iter()
+                            .enumerate()
+                            .fold(0, |byte, (idx, &p)| byte | p << (6 - 2 * idx))
+                    })
+                })
+                .collect()
+        };
+        let frames: Vec<_> = frames.iter().map(pack).collect();
+        check_roundtrip((13, 8), (ColorType::Grayscale, BitDepth::Two), &frames);
+        check_roundtrip((13, 8), (ColorType::Indexed, BitDepth::Two), &frames);
+    }
+
+    #[test]
+    fn roundtrip_black_without_alpha() {
+        // A cleared canvas is transparent, which must not be mistaken for black or index 0.
+        let frames = vec![vec![255; 4 * 3], vec![255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+        check_roundtrip((4, 1), (ColorType::Rgb, BitDepth::Eight), &frames);
+
+        let frames = vec![vec![255; 4], vec![255, 0, 0, 0], vec![0, 0, 255, 0]];
+        check_roundtrip((4, 1), (ColorType::Grayscale, BitDepth::Eight), &frames);
+        check_roundtrip((4, 1), (ColorType::Indexed, BitDepth::Eight), &frames);
+    }
+
+    #[test]
+    fn writes_changed_region() {
+        let (width, height) = (8, 8);
+        let frames = moving_square((width, height), &|x, y, frame| {
+            if in_square(x, y, frame) {
+                vec![255; 4]
+            } else {
+                vec![0; 4]
+            }
+        });
+        let png = encode(
+            (width, height),
+            (ColorType::Rgba, BitDepth::Eight),
+            &frames[..3],
+            true,
+            |_| {},
+        );
+        let controls = frame_controls(&png);
+        let regions: Vec<_> = controls
+            .iter()
+            .map(|c| (c.x_offset, c.y_offset, c.width, c.height))
+            .collect();
+        // The square is cleared after each frame and only drawn at its new position.
+        assert_eq!(regions, [(0, 0, 8, 8), (2, 2, 3, 3), (4, 3, 3, 3)]);
+        assert_eq!(controls[0].blend_op, BlendOp::Source);
+        assert_eq!(controls[0].dispose_op, DisposeOp::Background);
+        assert_eq!(controls[1].dispose_op, DisposeOp::Background);
+        assert_eq!(controls[2].dispose_op, DisposeOp::None);
+
+        // On an opaque background the previous canvas is restored instead.
+        let mut frames = moving_square((width, height), &|x, y, frame| {
+            if in_square(x, y, frame) {
+                vec![255; 4]
+            } else {
+                vec![0x40; 4]
+            }
+        });
+        frames[0].fill(0x40);
+        let png = encode(
+            (width, height),
+            (ColorType::Rgba, BitDepth::Eight),
+            &frames[..3],
+            false,
+            |_| {},
+        );
+        let controls = frame_controls(&png);
+        assert_eq!((controls[1].width, controls[1].height), (3, 3));
+        assert_eq!((controls[2].width, controls[2].height), (3, 3));
+        assert_eq!(controls[0].dispose_op, DisposeOp::None);
+        assert_eq!(controls[1].dispose_op, DisposeOp::Previous);
+        assert_eq!(controls[1].blend_op, BlendOp::Source);
+    }
+
+    #[test]
+    fn identical_frames() {
+        let frames = vec![vec![7; 4 * 3], vec![7; 4 * 3]];
+        let png = encode(
+            (2, 2),
+            (ColorType::Rgb, BitDepth::Eight),
+            &frames,
+            false,
+            |_| {},
+        );
+        let controls = frame_controls(&png);
+        assert_eq!((controls[1].width, controls[1].height), (1, 1));
+        assert_eq!(composited(&png).len(), 2);
+    }
+
+    #[test]
+    fn frame_count_and_size_are_checked() {
+        let mut encoder = Encoder::new(Vec::new(), 2, 2);
+        assert!(AnimationEncoder::new(encoder).is_err());
+
+        encoder = Encoder::new(Vec::new(), 2, 2);
+        encoder.set_animated(2, 0).unwrap();
+        let mut animation = AnimationEncoder::new(encoder).unwrap();
+        assert!(animation.write_frame(&[0; 3], 1, 1).is_err());
+        animation.write_frame(&[0; 4], 1, 1).unwrap();
+        assert!(animation.finish().is_err());
+    }
+
+    fn animation(size: (u32, u32), format: (ColorType, BitDepth)) -> AnimationEncoder<Vec<u8>> {
+        let mut encoder = Encoder::new(Vec::new(), size.0, size.1);
+        encoder.set_color(format.0);
+        encoder.set_depth(format.1);
+        encoder.set_animated(2, 0).unwrap();
+        AnimationEncoder::new(encoder).unwrap()
+    }
+
+    #[test]
+    fn same_pixel_masks_subbyte_pixels() {
+        // The second pixel of a 2-bit row differs, the others are the same.
+        let (a, b) = ([0b00_01_10_11], [0b00_11_10_11]);
+        let same: Vec<_> = (0..4).map(|x| same_pixel(&a, &b, x, 2)).collect();
+        assert_eq!(same, [true, false, true, true]);
+        assert!(!same_pixel(&[1, 2, 3], &[1, 2, 4], 0, 24));
+    }
+
+    #[test]
+    fn changed_region_is_bounding_box() {
+        let encoder = animation((4, 3), (ColorType::Grayscale, BitDepth::Eight));
+        let before = [0; 12];
+        assert_eq!(encoder.changed_region(&before, &before), None);
+
+        let mut after = before;
+        after[4 + 2] = 1;
+        after[8 + 1] = 1;
+        let region = Region {
+            x: 1,
+            y: 1,
+            width: 2,
+            height: 2,
+        };
+        assert_eq!(encoder.changed_region(&before, &after), Some(region));
+    }
+
+    #[test]
+    fn blend_over_needs_opaque_or_uncovered_pixels() {
+        let encoder = animation((2, 1), (ColorType::GrayscaleAlpha, BitDepth::Eight));
+        let region = encoder.full_region();
+        // Opaque pixels and pixels drawn onto transparent ones replace the canvas.
+        assert!(encoder.can_blend_over(&[9, 9, 9, 0], &[1, 255, 2, 9], region));
+        assert!(encoder.can_blend_over(&[9, 0, 9, 9], &[9, 0, 9, 9], region));
+        // A translucent pixel would be blended with the canvas below it.
+        assert!(!encoder.can_blend_over(&[9, 255, 9, 9], &[1, 128, 9, 9], region));
+        // A transparent pixel can't clear the canvas.
+        assert!(!encoder.can_blend_over(&[9, 255, 9, 9], &[0, 0, 9, 9], region));
+    }
+}
@@ -0,0 +1,515 @@
+//! Encoding to a [`futures_io::AsyncWrite`] without blocking on I/O.
+//!
+//! The chunks are produced by a regular [`Writer`] into an in-memory buffer, which keeps all of
+//! its validation, and the buffer is handed to the async sink after every operation. Only the
+//! data of the current operation is buffered: a single frame for
+//! [`AsyncWriter::write_image_data`] and a single chunk for [`AsyncStreamWriter`].
+use std::future::poll_fn;
+use std::io::{self, Write};
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+
+use futures_io::AsyncWrite;
+
+use super::{Encoder, PartialInfo, Result, StreamWriter, Writer};
+use crate::chunk::ChunkType;
+use crate::common::{BlendOp, DisposeOp, Info};
+use crate::filter::Filter;
+use crate::text_metadata::EncodableTextChunk;
+
+/// The output of the synchronous [`Writer`], collected until it is sent to the async sink.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Moves the collected bytes to the end of `out`.
+    fn drain_into(&self, out: &mut Vec<u8>) {
+        let mut buffer = self.0.lock().unwrap_or_else(|poison| poison.into_inner());
+        if out.is_empty() {
+            mem::swap(out, &mut buffer);
+        } else {
+            out.append(&mut buffer);
+        }
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut buffer = self.0.lock().unwrap_or_else(|poison| poison.into_inner());
+        buffer.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The async sink together with the bytes that are not yet written to it.
+struct Output<W> {
+    sink: W,
+    buffer: SharedBuffer,
+    pending: Vec<u8>,
+    written: usize,
+}
+
+impl<W: AsyncWrite + Unpin> Output<W> {
+    /// Writes everything collected so far to the sink.
+    ///
+    /// Bytes stay in `pending` until the sink accepted them, so this can be resumed after the
+    /// future driving it was dropped.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            if self.written == self.pending.len() {
+                self.pending.clear();
+                self.written = 0;
+                self.buffer.drain_into(&mut self.pending);
+                if self.pending.is_empty() {
+                    return Poll::Ready(Ok(()));
+                }
+            }
+            let n = ready!(Pin::new(&mut self.sink).poll_write(cx, &self.pending[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.written += n;
+        }
+    }
+
+    async fn drain(&mut self) -> io::Result<()> {
+        poll_fn(|cx| self.poll_drain(cx)).await
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        self.drain().await?;
+        poll_fn(|cx| Pin::new(&mut self.sink).poll_flush(cx)).await
+    }
+}
+
+impl<W: AsyncWrite + Unpin> Encoder<'static, W> {
+    /// Create an encoder that writes to an async sink with [`Encoder::write_header_async`].
+    ///
+    /// Only available with the `async` feature.
+    pub fn new_async(w: W, width: u32, height: u32) -> Self {
+        Encoder {
+            w,
+            info: Info::with_size(width, height),
+            options: Default::default(),
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> Encoder<'_, W> {
+    /// Start encoding to an async sink by writing the header data.
+    ///
+    /// The encoder is created with [`Encoder::new_async`] and configured as usual. The remaining
+    /// data can be supplied by methods on the returned [`AsyncWriter`]. Only available with the
+    /// `async` feature.
+    ///
+    /// ```
+    /// # futures_executor::block_on(async {
+    /// use png::{ColorType, Encoder};
+    ///
+    /// let mut png = Vec::new();
+    /// let mut encoder = Encoder::new_async(&mut png, 2, 1);
+    /// encoder.set_color(ColorType::Rgb);
+    /// let mut writer = encoder.write_header_async().await.unwrap();
+    /// writer.write_image_data(&[255, 0, 0, 0, 0, 255]).await.unwrap();
+    /// writer.finish().await.unwrap();
+    /// assert_eq!(&png[1..4], b"PNG");
+    /// # });
+    /// ```
+    pub async fn write_header_async(self) -> Result<AsyncWriter<W>> {
+        let buffer = SharedBuffer::default();
+        let writer = Writer::new(buffer.clone(), PartialInfo::new(&self.info), self.options)
+            .init(&self.info)?;
+        let mut output = Output {
+            sink: self.w,
+            buffer,
+            pending: Vec::new(),
+            written: 0,
+        };
+        output.drain().await?;
+        Ok(AsyncWriter { writer, output })
+    }
+}
+
+/// Async counterpart of [`Writer`], created by [`Encoder::write_header_async`].
+///
+/// Unlike [`Writer`], dropping this does not write the `IEND` chunk, so [`AsyncWriter::finish`]
+/// must be awaited to complete the image. Only available with the `async` feature.
+pub struct AsyncWriter<W: AsyncWrite + Unpin> {
+    writer: Writer<SharedBuffer>,
+    output: Output<W>,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWriter<W> {
+    /// Writes the next image data, see [`Writer::write_image_data`].
+    pub async fn write_image_data(&mut self, data: &[u8]) -> Result<()> {
+        self.writer.write_image_data(data)?;
+        Ok(self.output.drain().await?)
+    }
+
+    /// Write a raw chunk of PNG data, see [`Writer::write_chunk`].
+    pub async fn write_chunk(&mut self, name: ChunkType, data: &[u8]) -> Result<()> {
+        self.writer.write_chunk(name, data)?;
+        Ok(self.output.drain().await?)
+    }
+
+    /// Write a text chunk, see [`Writer::write_text_chunk`].
+    pub async fn write_text_chunk<T: EncodableTextChunk>(&mut self, text_chunk: &T) -> Result<()> {
+        self.writer.write_text_chunk(text_chunk)?;
+        Ok(self.output.drain().await?)
+    }
+
+    /// Set the used filter type, see [`Writer::set_filter`].
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.writer.set_filter(filter);
+    }
+
+    /// Set the display time of the following frames, see [`Writer::set_frame_delay`].
+    pub fn set_frame_delay(&mut self, numerator: u16, denominator: u16) -> Result<()> {
+        self.writer.set_frame_delay(numerator, denominator)
+    }
+
+    /// Set the dimension of the following frames, see [`Writer::set_frame_dimension`].
+    pub fn set_frame_dimension(&mut self, width: u32, height: u32) -> Result<()> {
+        self.writer.set_frame_dimension(width, height)
+    }
+
+    /// Set the position of the following frames, see [`Writer::set_frame_position`].
+    pub fn set_frame_position(&mut self, x: u32, y: u32) -> Result<()> {
+        self.writer.set_frame_position(x, y)
+    }
+
+    /// See [`Writer::reset_frame_dimension`].
+    pub fn reset_frame_dimension(&mut self) -> Result<()> {
+        self.writer.reset_frame_dimension()
+    }
+
+    /// See [`Writer::reset_frame_position`].
+    pub fn reset_frame_position(&mut self) -> Result<()> {
+        self.writer.reset_frame_position()
+    }
+
+    /// Set the blend operation for the following frames, see [`Writer::set_blend_op`].
+    pub fn set_blend_op(&mut self, op: BlendOp) -> Result<()> {
+        self.writer.set_blend_op(op)
+    }
+
+    /// Set the dispose operation for the following frames, see [`Writer::set_dispose_op`].
+    pub fn set_dispose_op(&mut self, op: DisposeOp) -> Result<()> {
+        self.writer.set_dispose_op(op)
+    }
+
+    /// Create a stream writer, see [`Writer::stream_writer`].
+    pub fn stream_writer(&mut self) -> Result<AsyncStreamWriter<'_, W>> {
+        self.stream_writer_with_size(super::DEFAULT_BUFFER_LENGTH)
+    }
+
+    /// Create a stream writer with custom buffer size, see [`Writer::stream_writer_with_size`].
+    pub fn stream_writer_with_size(&mut self, size: usize) -> Result<AsyncStreamWriter<'_, W>> {
+        Ok(AsyncStreamWriter {
+            writer: Some(self.writer.stream_writer_with_size(size)?),
+            output: OutputRef::Borrowed(&mut self.output),
+        })
+    }
+
+    /// Turn this into a stream writer for image data, see [`Writer::into_stream_writer`].
+    pub fn into_stream_writer(self) -> Result<AsyncStreamWriter<'static, W>> {
+        self.into_stream_writer_with_size(super::DEFAULT_BUFFER_LENGTH)
+    }
+
+    /// Turn this into a stream writer with custom buffer size, see
+    /// [`Writer::into_stream_writer_with_size`].
+    pub fn into_stream_writer_with_size(
+        self,
+        size: usize,
+    ) -> Result<AsyncStreamWriter<'static, W>> {
+        Ok(AsyncStreamWriter {
+            writer: Some(self.writer.into_stream_writer_with_size(size)?),
+            output: OutputRef::Owned(self.output),
+        })
+    }
+
+    /// Write the `IEND` chunk after validating the chunk sequence, see [`Writer::finish`].
+    pub async fn finish(mut self) -> Result<()> {
+        self.writer.validate_sequence_done()?;
+        self.writer.write_iend()?;
+        Ok(self.output.flush().await?)
+    }
+}
+
+enum OutputRef<'a, W> {
+    Borrowed(&'a mut Output<W>),
+    Owned(Output<W>),
+}
+
+impl<W> Deref for OutputRef<'_, W> {
+    type Target = Output<W>;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            OutputRef::Borrowed(output) => output,
+            OutputRef::Owned(output) => output,
+        }
+    }
+}
+
+impl<W> DerefMut for OutputRef<'_, W> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            OutputRef::Borrowed(output) => output,
+            OutputRef::Owned(output) => output,
+        }
+    }
+}
+
+/// Async counterpart of [`StreamWriter`], writing image data as it is supplied through
+/// [`AsyncWrite`].
+///
+/// Flushing behaves like [`StreamWriter`]'s `flush`. Closing finishes the image data like
+/// [`AsyncStreamWriter::finish`]; the underlying sink is only closed when the stream writer owns
+/// it. Only available with the `async` feature.
+pub struct AsyncStreamWriter<'a, W: AsyncWrite + Unpin> {
+    /// `None` once the image data has been finished.
+    writer: Option<StreamWriter<'a, SharedBuffer>>,
+    output: OutputRef<'a, W>,
+}
+
+impl<'a, W: AsyncWrite + Unpin> AsyncStreamWriter<'a, W> {
+    /// Consume the stream writer with validation, see [`StreamWriter::finish`].
+    ///
+    /// A stream writer created by [`AsyncWriter::into_stream_writer`] also writes the `IEND`
+    /// chunk.
+    pub async fn finish(mut self) -> Result<()> {
+        if let Some(writer) = self.writer.take() {
+            // Dropping an owned `Writer` writes `IEND` into the buffer.
+            writer.finish()?;
+        }
+        Ok(self.output.flush().await?)
+    }
+
+    fn writer(&mut self) -> io::Result<&mut StreamWriter<'a, SharedBuffer>> {
+        match &mut self.writer {
+            Some(writer) => Ok(writer),
+            None => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "stream writer was already closed",
+            )),
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for AsyncStreamWriter<'_, W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.output.poll_drain(cx))?;
+        Poll::Ready(this.writer()?.write(data))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Some(writer) = &mut this.writer {
+            writer.flush()?;
+        }
+        ready!(this.output.poll_drain(cx))?;
+        Pin::new(&mut this.output.sink).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Some(writer) = this.writer.take() {
+            writer.finish()?;
+        }
+        ready!(this.output.poll_drain(cx))?;
+        match &mut this.output {
+            OutputRef::Borrowed(output) => Pin::new(&mut output.sink).poll_flush(cx),
+            OutputRef::Owned(output) => Pin::new(&mut output.sink).poll_close(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use futures_executor::block_on;
+
+    use super::*;
+    use crate::common::{ColorType, FrameControl};
+    use crate::{Decoder, EncodingError};
+
+    /// Accepts a few bytes at a time and is only ready on every other poll.
+    #[derive(Default)]
+    struct Trickle {
+        data: Rc<RefCell<Vec<u8>>>,
+        ready: bool,
+    }
+
+    impl AsyncWrite for Trickle {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            this.ready = !this.ready;
+            if !this.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let len = buf.len().min(5);
+            this.data.borrow_mut().extend_from_slice(&buf[..len]);
+            Poll::Ready(Ok(len))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn configure<W>(mut encoder: Encoder<'static, W>) -> Encoder<'static, W> {
+        encoder.set_color(ColorType::Rgb);
+        encoder
+            .add_text_chunk("Title".into(), "async".into())
+            .unwrap();
+        encoder
+    }
+
+    fn pixels(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height * 3).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn writer_matches_sync_writer() {
+        let pixels = pixels(40, 30);
+        let mut expected = Vec::new();
+        let mut writer = configure(Encoder::new(&mut expected, 40, 30))
+            .write_header()
+            .unwrap();
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+
+        let sink = Trickle::default();
+        let data = sink.data.clone();
+        block_on(async {
+            let mut writer = configure(Encoder::new_async(sink, 40, 30))
+                .write_header_async()
+                .await
+                .unwrap();
+            writer.write_image_data(&pixels).await.unwrap();
+            writer.finish().await.unwrap();
+        });
+        assert_eq!(*data.borrow(), expected);
+    }
+
+    #[test]
+    fn stream_writer_matches_sync_stream_writer() {
+        let pixels = pixels(40, 30);
+        let mut expected = Vec::new();
+        let mut writer = configure(Encoder::new(&mut expected, 40, 30))
+            .write_header()
+            .unwrap();
+        let mut stream = writer.stream_writer_with_size(100).unwrap();
+        for row in pixels.chunks(17) {
+            stream.write_all(row).unwrap();
+        }
+        stream.finish().unwrap();
+        writer.finish().unwrap();
+
+        let sink = Trickle::default();
+        let data = sink.data.clone();
+        block_on(async {
+            let mut writer = configure(Encoder::new_async(sink, 40, 30))
+                .write_header_async()
+                .await
+                .unwrap();
+            let mut stream = writer.stream_writer_with_size(100).unwrap();
+            for row in pixels.chunks(17) {
+                let mut row = row;
+                while !row.is_empty() {
+                    let n = poll_fn(|cx| Pin::new(&mut stream).poll_write(cx, row))
+                        .await
+                        .unwrap();
+                    row = &row[n..];
+                }
+            }
+            stream.finish().await.unwrap();
+            writer.finish().await.unwrap();
+        });
+        assert_eq!(*data.borrow(), expected);
+
+        let sink = Trickle::default();
+        let data = sink.data.clone();
+        block_on(async {
+            let writer = configure(Encoder::new_async(sink, 40, 30))
+                .write_header_async()
+                .await
+                .unwrap();
+            let mut stream = writer.into_stream_writer_with_size(100).unwrap();
+            let mut data = &pixels[..];
+            while !data.is_empty() {
+                let n = poll_fn(|cx| Pin::new(&mut stream).poll_write(cx, data))
+                    .await
+                    .unwrap();
+                data = &data[n..];
+            }
+            poll_fn(|cx| Pin::new(&mut stream).poll_close(cx))
+                .await
+                .unwrap();
+        });
+        assert_eq!(*data.borrow(), expected);
+    }
+
+    #[test]
+    fn animation_is_validated() {
+        let pixels = pixels(8, 8);
+        let mut png = Vec::new();
+        block_on(async {
+            let mut encoder = configure(Encoder::new_async(&mut png, 8, 8));
+            encoder.set_animated(2, 0).unwrap();
+            encoder.validate_sequence(true);
+            let mut writer = encoder.write_header_async().await.unwrap();
+            writer.set_frame_delay(1, 10).unwrap();
+            writer.write_image_data(&pixels).await.unwrap();
+            writer.write_image_data(&pixels).await.unwrap();
+            assert!(matches!(
+                writer.write_image_data(&pixels).await,
+                Err(EncodingError::Format(_))
+            ));
+            writer.finish().await.unwrap();
+        });
+
+        let mut reader = Decoder::new(io::Cursor::new(&png)).read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        for _ in 0..2 {
+            reader.next_frame(&mut buf).unwrap();
+            assert_eq!(buf, pixels);
+            let fctl: FrameControl = *reader.info().frame_control.as_ref().unwrap();
+            assert_eq!((fctl.delay_num, fctl.delay_den), (1, 10));
+        }
+
+        let result = block_on(async {
+            let mut encoder = configure(Encoder::new_async(Vec::new(), 8, 8));
+            encoder.set_animated(2, 0).unwrap();
+            encoder.validate_sequence(true);
+            let mut writer = encoder.write_header_async().await.unwrap();
+            writer.write_image_data(&pixels).await.unwrap();
+            writer.finish().await
+        });
+        assert!(matches!(result, Err(EncodingError::Format(_))));
+    }
+}
@@ -586,8 +586,9 @@ fn entropy(buf: &[u8]) -> u64 {
     }
 
     // Consolidate the counts.
-    for i in 0..256 {
-        counts[0][i] += counts[1][i] + counts[2][i] + counts[3][i];
+    let [total_counts, counts1, counts2, counts3] = &mut counts;
+    for (i, count) in total_counts.iter_mut().enumerate() {
+        *count += counts1[i] + counts2[i] + counts3[i];
     }
 
     // Compute the entropy.
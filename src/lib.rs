@@ -74,6 +74,9 @@ mod common;
 mod decoder;
 mod encoder;
 mod filter;
+pub mod optimize;
+#[cfg(feature = "parallel")]
+mod parallel_deflate;
 mod srgb;
 pub mod text_metadata;
 mod traits;
@@ -84,12 +87,23 @@ pub use crate::adam7::{
 
 pub use crate::adam7::{Adam7Info, Adam7Variant};
 pub use crate::common::*;
-pub use crate::decoder::stream::{DecodeOptions, Decoded, DecodingError, StreamingDecoder};
+pub use crate::decoder::stream::{
+    DecodeOptions, DecodeWarning, DecodeWarningKind, Decoded, DecodingError, FormatError,
+    FormatErrorKind, StreamingDecoder,
+};
+pub use crate::decoder::{AnimationCompositor, CompositedFrame, FrameIndex, IndexedFrame};
+#[cfg(feature = "async")]
+pub use crate::decoder::{AsyncDecoder, AsyncReader};
 pub use crate::decoder::{Decoder, InterlaceInfo, InterlacedRow, Limits, OutputInfo, Reader};
+pub use crate::decoder::{SliceDecoder, SliceReader};
 pub use crate::decoder::{UnfilterBuf, UnfilterRegion};
-pub use crate::encoder::{Encoder, EncodingError, StreamWriter, Writer};
+pub use crate::encoder::{AnimationEncoder, Encoder, EncodingError, StreamWriter, Writer};
+#[cfg(feature = "async")]
+pub use crate::encoder::{AsyncStreamWriter, AsyncWriter};
 pub use crate::filter::Filter;
 
+#[cfg(test)]
+pub(crate) mod test_codec;
 #[cfg(test)]
 pub(crate) mod test_utils;
 
@@ -0,0 +1,864 @@
+//! # Lossless optimization of PNG images
+//!
+//! The [`Optimizer`] takes the decoded pixels of an image together with its [`Info`] and searches
+//! for the smallest file that decodes to exactly the same pixels. This works in two steps:
+//!
+//!  1. The image is reduced to the simplest color type and bit depth that can represent it
+//!     without loss: an alpha channel that is fully opaque is dropped (or replaced by a `tRNS`
+//!     color key), RGB images where all pixels are gray become grayscale, 16-bit samples that
+//!     don't use the low byte become 8-bit, grayscale bit depths are reduced where possible, and
+//!     images with at most 256 colors are additionally tried as `Indexed`, with a sorted palette
+//!     and a trimmed `tRNS` chunk.
+//!  2. Every candidate is encoded with each of the configured [`Filter`] and
+//!     [`DeflateCompression`] combinations, and the smallest output is kept.
+//!
+//! The pixel data has to be in the format described by the `Info`, as it is produced by a
+//! [`Reader`](crate::Reader) with [`Transformations::IDENTITY`](crate::Transformations::IDENTITY).
+//! The output is never interlaced. Ancillary chunks are preserved, and those that depend on the
+//! pixel format (`sBIT`, `bKGD`) are converted to the new format, formats that can't represent
+//! the background color are not considered. Images with an ICC profile stay grayscale or color,
+//! as the profile is only valid for one of them. Unknown chunks that are not
+//! [safe to copy](crate::chunk::safe_to_copy) are dropped, as the image data changes.
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! let decoder = png::Decoder::new(BufReader::new(File::open("tests/pngsuite/basn6a08.png").unwrap()));
+//! let mut reader = decoder.read_info().unwrap();
+//! let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+//! let frame = reader.next_frame(&mut buf).unwrap();
+//!
+//! let optimized = png::optimize::optimize(reader.info(), &buf[..frame.buffer_size()]).unwrap();
+//! ```
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::common::{BitDepth, ColorType, DeflateCompression, Info, ParameterErrorKind};
+use crate::encoder::{Encoder, EncodingError, FormatErrorKind, Result};
+use crate::filter::Filter;
+
+/// Searches for the smallest lossless encoding of an image.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone, Debug)]
+pub struct Optimizer {
+    filters: Vec<Filter>,
+    compressions: Vec<DeflateCompression>,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Optimizer {
+            filters: vec![
+                Filter::NoFilter,
+                Filter::Paeth,
+                Filter::Adaptive,
+                Filter::MinEntropy,
+            ],
+            compressions: vec![DeflateCompression::Level(9)],
+        }
+    }
+}
+
+impl Optimizer {
+    /// Creates an optimizer with the default set of trials.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the filters that are tried for every candidate representation.
+    ///
+    /// Defaults to `NoFilter`, `Paeth`, `Adaptive` and `MinEntropy`. An empty list is treated
+    /// like the default filter of the encoder.
+    pub fn set_filters(&mut self, filters: &[Filter]) {
+        self.filters = filters.to_vec();
+    }
+
+    /// Sets the compression settings that are tried for every candidate representation.
+    ///
+    /// Defaults to `Level(9)`. An empty list is treated like the default compression of the
+    /// encoder.
+    pub fn set_compressions(&mut self, compressions: &[DeflateCompression]) {
+        self.compressions = compressions.to_vec();
+    }
+
+    /// Returns the smallest encoded PNG file that was found for the image.
+    ///
+    /// `data` holds the non-interlaced pixels in the color type and bit depth of `info`. Animated
+    /// images are not supported.
+    pub fn optimize(&self, info: &Info<'_>, data: &[u8]) -> Result<Vec<u8>> {
+        if info.animation_control.is_some() || info.frame_control.is_some() {
+            return Err(EncodingError::Format(
+                FormatErrorKind::UnsupportedAnimation.into(),
+            ));
+        }
+
+        let image = Image::from_info(info, data)?;
+        let mut candidates = vec![image.reduce_true_color()];
+        candidates.extend(image.reduce_indexed());
+
+        let filters = if self.filters.is_empty() {
+            vec![Filter::default()]
+        } else {
+            self.filters.clone()
+        };
+        let compressions = if self.compressions.is_empty() {
+            vec![DeflateCompression::default()]
+        } else {
+            self.compressions.clone()
+        };
+
+        let mut best: Option<Vec<u8>> = None;
+        for candidate in &candidates {
+            let candidate_info = candidate.to_info(info);
+            for &filter in &filters {
+                for &compression in &compressions {
+                    let mut out = Vec::new();
+                    let mut encoder = Encoder::with_info(&mut out, candidate_info.clone())?;
+                    encoder.set_filter(filter);
+                    encoder.set_deflate_compression(compression);
+                    let mut writer = encoder.write_header()?;
+                    writer.write_image_data(&candidate.data)?;
+                    writer.finish()?;
+
+                    if best.as_ref().map_or(true, |best| out.len() < best.len()) {
+                        best = Some(out);
+                    }
+                }
+            }
+        }
+
+        // There is always at least one candidate, filter and compression.
+        Ok(best.unwrap())
+    }
+}
+
+/// Returns the smallest lossless encoding of an image, using the default [`Optimizer`].
+pub fn optimize(info: &Info<'_>, data: &[u8]) -> Result<Vec<u8>> {
+    Optimizer::default().optimize(info, data)
+}
+
+/// An RGBA color with samples of either 8 or 16 bits.
+type Rgba = [u16; 4];
+
+/// The image in a canonical RGBA representation.
+struct Image {
+    width: u32,
+    height: u32,
+    /// Whether the samples use 16 bits, otherwise 8 bits.
+    sixteen_bit: bool,
+    pixels: Vec<Rgba>,
+    /// The significant bits of the red, green, blue and alpha samples.
+    sbit: Option<[u8; 4]>,
+    /// The background color, alpha is always opaque.
+    bkgd: Option<Rgba>,
+    /// Whether the image has to stay grayscale (`true`) or color (`false`), because its ICC
+    /// profile is only valid for one of them.
+    fixed_gray: Option<bool>,
+}
+
+/// A reduced representation of the image, ready to be encoded.
+struct Candidate {
+    color_type: ColorType,
+    bit_depth: BitDepth,
+    palette: Option<Vec<u8>>,
+    trns: Option<Vec<u8>>,
+    sbit: Option<Vec<u8>>,
+    bkgd: Option<Vec<u8>>,
+    data: Vec<u8>,
+}
+
+impl Image {
+    fn from_info(info: &Info<'_>, data: &[u8]) -> Result<Self> {
+        let color_type = info.color_type;
+        let bit_depth = info.bit_depth;
+        let depth = bit_depth as u8;
+        let width = info.width as usize;
+        let row_len = color_type.raw_row_length_from_width(bit_depth, info.width) - 1;
+        let expected = row_len * info.height as usize;
+        if data.len() != expected {
+            return Err(EncodingError::Parameter(
+                ParameterErrorKind::ImageBufferSize {
+                    expected,
+                    actual: data.len(),
+                }
+                .into(),
+            ));
+        }
+        let palette = match (color_type, &info.palette) {
+            (ColorType::Indexed, Some(palette)) => Some(palette),
+            (ColorType::Indexed, None) => {
+                return Err(EncodingError::Format(FormatErrorKind::NoPalette.into()))
+            }
+            _ => None,
+        };
+
+        // Samples of less than 8 bits are scaled up to 8 bits, so they can be treated alike.
+        let sixteen_bit = bit_depth == BitDepth::Sixteen;
+        let max = if sixteen_bit { 0xffff } else { 0xff };
+        let scale = |sample: u16| match depth {
+            1 | 2 | 4 => sample * (0xff / ((1 << depth) - 1)),
+            _ => sample,
+        };
+        let trns = info.trns.as_deref().and_then(|trns| {
+            // The decoder stores the color key of images with less than 16 bits using a single
+            // byte per sample, while the chunk itself always uses two.
+            let samples = match color_type {
+                ColorType::Grayscale => 1,
+                ColorType::Rgb => 3,
+                _ => return None,
+            };
+            let key: Vec<u16> = if trns.len() >= 2 * samples {
+                trns.chunks_exact(2)
+                    .take(samples)
+                    .map(|s| u16::from_be_bytes([s[0], s[1]]))
+                    .collect()
+            } else if trns.len() == samples && !sixteen_bit {
+                trns.iter().map(|&s| u16::from(s)).collect()
+            } else {
+                return None;
+            };
+            Some(key)
+        });
+
+        let samples_per_pixel = color_type.samples();
+        let mut pixels = Vec::with_capacity(width * info.height as usize);
+        let mut samples = Vec::with_capacity(width * samples_per_pixel);
+        for row in data.chunks_exact(row_len.max(1)).take(info.height as usize) {
+            samples.clear();
+            samples.extend(unpack_row(row, depth).take(width * samples_per_pixel));
+            for pixel in samples.chunks_exact(samples_per_pixel) {
+                let rgba = match color_type {
+                    ColorType::Grayscale => {
+                        let alpha = match &trns {
+                            Some(key) if key[0] == pixel[0] => 0,
+                            _ => max,
+                        };
+                        let gray = scale(pixel[0]);
+                        [gray, gray, gray, alpha]
+                    }
+                    ColorType::Rgb => {
+                        let alpha = match &trns {
+                            Some(key) if key[..] == pixel[..] => 0,
+                            _ => max,
+                        };
+                        [pixel[0], pixel[1], pixel[2], alpha]
+                    }
+                    ColorType::GrayscaleAlpha => [pixel[0], pixel[0], pixel[0], pixel[1]],
+                    ColorType::Rgba => [pixel[0], pixel[1], pixel[2], pixel[3]],
+                    ColorType::Indexed => {
+                        let palette = palette.unwrap();
+                        lookup_palette(palette, info.trns.as_deref(), pixel[0])
+                    }
+                };
+                pixels.push(rgba);
+            }
+        }
+
+        let sbit = info.sbit.as_deref().and_then(|sbit| {
+            let full = if sixteen_bit { 16 } else { 8 };
+            match (color_type, sbit) {
+                (ColorType::Grayscale, &[g]) => Some([g, g, g, full]),
+                (ColorType::GrayscaleAlpha, &[g, a]) => Some([g, g, g, a]),
+                (ColorType::Rgb | ColorType::Indexed, &[r, g, b]) => Some([r, g, b, full]),
+                (ColorType::Rgba, &[r, g, b, a]) => Some([r, g, b, a]),
+                _ => None,
+            }
+        });
+
+        let bkgd = info.bkgd.as_deref().and_then(|bkgd| {
+            let sample = |i: usize| u16::from_be_bytes([bkgd[2 * i], bkgd[2 * i + 1]]);
+            match (color_type, bkgd.len()) {
+                (ColorType::Indexed, 1) => {
+                    let [r, g, b, _] = lookup_palette(palette.unwrap(), None, bkgd[0].into());
+                    Some([r, g, b, max])
+                }
+                (ColorType::Grayscale | ColorType::GrayscaleAlpha, 2) => {
+                    let gray = scale(sample(0));
+                    Some([gray, gray, gray, max])
+                }
+                (ColorType::Rgb | ColorType::Rgba, 6) => {
+                    Some([sample(0), sample(1), sample(2), max])
+                }
+                _ => None,
+            }
+        });
+
+        let mut image = Image {
+            width: info.width,
+            height: info.height,
+            sixteen_bit,
+            pixels,
+            sbit,
+            bkgd,
+            fixed_gray: info
+                .icc_profile
+                .as_ref()
+                .map(|_| matches!(color_type, ColorType::Grayscale | ColorType::GrayscaleAlpha)),
+        };
+        image.reduce_sixteen_bit();
+        Ok(image)
+    }
+
+    /// Converts 16-bit samples to 8 bits, if all of them are representable.
+    fn reduce_sixteen_bit(&mut self) {
+        let fits = |color: &Rgba| color.iter().all(|&s| s % 257 == 0);
+        if !self.sixteen_bit || !self.pixels.iter().all(fits) || !self.bkgd.iter().all(fits) {
+            return;
+        }
+
+        self.sixteen_bit = false;
+        for color in self.pixels.iter_mut().chain(self.bkgd.as_mut()) {
+            for sample in color.iter_mut() {
+                *sample /= 257;
+            }
+        }
+        if let Some(sbit) = &mut self.sbit {
+            for bits in sbit.iter_mut() {
+                *bits = (*bits).min(8);
+            }
+        }
+    }
+
+    fn max(&self) -> u16 {
+        if self.sixteen_bit {
+            0xffff
+        } else {
+            0xff
+        }
+    }
+
+    /// Finds the smallest grayscale or RGB representation.
+    fn reduce_true_color(&self) -> Candidate {
+        let max = self.max();
+        let is_gray = |&[r, g, b, _]: &Rgba| r == g && g == b;
+        let gray = self.fixed_gray != Some(false) && self.pixels.iter().all(is_gray);
+
+        // Transparency can be stored as a color key if every transparent pixel has the same
+        // color, and no opaque pixel uses that color.
+        let mut key: Option<Rgba> = None;
+        let mut needs_alpha = false;
+        for &pixel in &self.pixels {
+            match pixel[3] {
+                a if a == max => {}
+                0 if key.map_or(true, |key| key == pixel) => key = Some(pixel),
+                _ => {
+                    needs_alpha = true;
+                    break;
+                }
+            }
+        }
+        if let Some(key) = key.filter(|_| !needs_alpha) {
+            let [r, g, b, _] = key;
+            if self
+                .pixels
+                .iter()
+                .any(|&[pr, pg, pb, pa]| pa == max && [pr, pg, pb] == [r, g, b])
+            {
+                needs_alpha = true;
+            }
+        }
+        let key = key.filter(|_| !needs_alpha);
+        // A color key or background of a grayscale image must be gray as well.
+        let gray = gray && key.iter().chain(&self.bkgd).all(is_gray);
+
+        let color_type = match (gray, needs_alpha) {
+            (true, false) => ColorType::Grayscale,
+            (true, true) => ColorType::GrayscaleAlpha,
+            (false, false) => ColorType::Rgb,
+            (false, true) => ColorType::Rgba,
+        };
+
+        // Grayscale images can use fewer bits if all values are scaled up exactly from them.
+        let mut depth = if self.sixteen_bit { 16 } else { 8 };
+        if color_type == ColorType::Grayscale && depth == 8 {
+            let values = || {
+                self.pixels
+                    .iter()
+                    .chain(key.as_ref())
+                    .chain(self.bkgd.as_ref())
+                    .map(|pixel| pixel[0])
+            };
+            for candidate in [1, 2, 4] {
+                let step = 0xff / ((1 << candidate) - 1);
+                if values().all(|value| value % step == 0) {
+                    depth = candidate;
+                    break;
+                }
+            }
+        }
+        let step = if depth < 8 {
+            0xff / ((1 << depth) - 1)
+        } else {
+            1
+        };
+
+        let channels: &[usize] = match color_type {
+            ColorType::Grayscale => &[0],
+            ColorType::GrayscaleAlpha => &[0, 3],
+            ColorType::Rgb => &[0, 1, 2],
+            _ => &[0, 1, 2, 3],
+        };
+        let data = self.pack(depth, |pixel, out| {
+            out.extend(channels.iter().map(|&c| pixel[c] / step));
+        });
+
+        let color_samples = if gray { &[0][..] } else { &[0, 1, 2][..] };
+        let encode_color = |color: Rgba| -> Vec<u8> {
+            color_samples
+                .iter()
+                .flat_map(|&c| (color[c] / step).to_be_bytes())
+                .collect()
+        };
+        let trns = key.map(encode_color);
+        let bkgd = self.bkgd.map(encode_color);
+        let sbit = self.sbit.map(|[r, g, b, a]| {
+            let mut sbit = if gray {
+                vec![r.max(g).max(b)]
+            } else {
+                vec![r, g, b]
+            };
+            if needs_alpha {
+                sbit.push(a);
+            }
+            sbit.iter_mut().for_each(|bits| *bits = (*bits).min(depth));
+            sbit
+        });
+
+        Candidate {
+            color_type,
+            bit_depth: BitDepth::from_u8(depth).unwrap(),
+            palette: None,
+            trns,
+            sbit,
+            bkgd,
+            data,
+        }
+    }
+
+    /// Builds an indexed representation if the image has at most 256 colors.
+    fn reduce_indexed(&self) -> Option<Candidate> {
+        if self.sixteen_bit || self.fixed_gray == Some(true) {
+            return None;
+        }
+
+        let mut counts: HashMap<Rgba, usize> = HashMap::new();
+        for &pixel in &self.pixels {
+            *counts.entry(pixel).or_default() += 1;
+            if counts.len() > 256 {
+                return None;
+            }
+        }
+        let max = self.max();
+        // The background color refers to a palette entry, so it may need an entry of its own.
+        if let Some(bkgd) = self.bkgd {
+            if !counts.keys().any(|color| color[..3] == bkgd[..3]) {
+                counts.insert(bkgd, 0);
+                if counts.len() > 256 {
+                    return None;
+                }
+            }
+        }
+
+        // Transparent entries go first so that `tRNS` can omit the opaque remainder. Frequent
+        // colors get small indices, which tends to compress better.
+        let mut entries: Vec<(Rgba, usize)> = counts.into_iter().collect();
+        entries.sort_by(|(a, a_count), (b, b_count)| {
+            let a_opaque = a[3] == max;
+            let b_opaque = b[3] == max;
+            a_opaque
+                .cmp(&b_opaque)
+                .then(a[3].cmp(&b[3]))
+                .then(b_count.cmp(a_count))
+                .then(a.cmp(b))
+        });
+
+        let index: HashMap<Rgba, u8> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, &(color, _))| (color, i as u8))
+            .collect();
+        let depth = match entries.len() {
+            0..=2 => 1,
+            3..=4 => 2,
+            5..=16 => 4,
+            _ => 8,
+        };
+
+        let palette = entries
+            .iter()
+            .flat_map(|&([r, g, b, _], _)| [r as u8, g as u8, b as u8])
+            .collect();
+        let trns: Vec<u8> = entries
+            .iter()
+            .take_while(|&&(color, _)| color[3] != max)
+            .map(|&(color, _)| color[3] as u8)
+            .collect();
+        let bkgd = self.bkgd.map(|bkgd| {
+            let position = entries
+                .iter()
+                .position(|(color, _)| color[..3] == bkgd[..3])
+                .unwrap();
+            vec![position as u8]
+        });
+        let sbit = self.sbit.map(|[r, g, b, _]| vec![r, g, b]);
+        let data = self.pack(depth, |pixel, out| out.push(u16::from(index[pixel])));
+
+        Some(Candidate {
+            color_type: ColorType::Indexed,
+            bit_depth: BitDepth::from_u8(depth).unwrap(),
+            palette: Some(palette),
+            trns: (!trns.is_empty()).then_some(trns),
+            sbit,
+            bkgd,
+            data,
+        })
+    }
+
+    /// Packs rows of samples with the given bit depth, `samples` maps a pixel to its samples.
+    fn pack(&self, depth: u8, samples: impl Fn(&Rgba, &mut Vec<u16>)) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut row = Vec::new();
+        for pixels in self.pixels.chunks_exact(self.width as usize) {
+            row.clear();
+            for pixel in pixels {
+                samples(pixel, &mut row);
+            }
+            pack_row(&row, depth, &mut data);
+        }
+        debug_assert_eq!(
+            self.pixels.len(),
+            self.width as usize * self.height as usize
+        );
+        data
+    }
+}
+
+impl Candidate {
+    fn to_info<'a>(&self, info: &Info<'a>) -> Info<'a> {
+        let mut info = info.clone();
+        info.unknown_chunks
+            .retain(|chunk| crate::chunk::safe_to_copy(chunk.type_));
+        info.color_type = self.color_type;
+        info.bit_depth = self.bit_depth;
+        info.interlaced = false;
+        info.palette = self.palette.clone().map(Cow::Owned);
+        info.trns = self.trns.clone().map(Cow::Owned);
+        info.sbit = self.sbit.clone().map(Cow::Owned);
+        info.bkgd = self.bkgd.clone().map(Cow::Owned);
+        info
+    }
+}
+
+/// Looks up a palette entry as an 8-bit RGBA color. Indices outside of the palette are black.
+fn lookup_palette(palette: &[u8], trns: Option<&[u8]>, index: u16) -> Rgba {
+    let index = usize::from(index);
+    let rgb = palette.get(3 * index..3 * index + 3).unwrap_or(&[0, 0, 0]);
+    let alpha = trns
+        .and_then(|trns| trns.get(index))
+        .copied()
+        .unwrap_or(0xff);
+    [rgb[0].into(), rgb[1].into(), rgb[2].into(), alpha.into()]
+}
+
+/// Iterates over the samples of a packed row.
+fn unpack_row(row: &[u8], depth: u8) -> impl Iterator<Item = u16> + '_ {
+    let per_byte = match depth {
+        1 | 2 | 4 => 8 / depth,
+        _ => 1,
+    };
+    let sixteen_bit = depth == 16;
+    let mut bytes = row.iter();
+    let mut current = 0u8;
+    let mut remaining = 0u8;
+    std::iter::from_fn(move || {
+        if sixteen_bit {
+            let hi = *bytes.next()?;
+            let lo = *bytes.next()?;
+            return Some(u16::from_be_bytes([hi, lo]));
+        }
+        if depth == 8 {
+            return bytes.next().map(|&b| u16::from(b));
+        }
+        if remaining == 0 {
+            current = *bytes.next()?;
+            remaining = per_byte;
+        }
+        remaining -= 1;
+        let mask = (1u8 << depth) - 1;
+        Some(u16::from((current >> (remaining * depth)) & mask))
+    })
+}
+
+/// Appends samples as a packed row, padding the last byte with zero bits.
+fn pack_row(samples: &[u16], depth: u8, out: &mut Vec<u8>) {
+    match depth {
+        16 => out.extend(samples.iter().flat_map(|s| s.to_be_bytes())),
+        8 => out.extend(samples.iter().map(|&s| s as u8)),
+        _ => {
+            let per_byte = usize::from(8 / depth);
+            for chunk in samples.chunks(per_byte) {
+                let mut byte = 0u8;
+                for (i, &sample) in chunk.iter().enumerate() {
+                    byte |= (sample as u8) << (8 - depth as usize * (i + 1));
+                }
+                out.push(byte);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Decoder, Transformations};
+
+    use std::fs::File;
+    use std::io::{BufReader, Cursor};
+
+    /// Decodes into 16-bit RGBA, so that images of different formats can be compared.
+    fn decode_rgba(png: impl std::io::BufRead) -> (Info<'static>, Vec<u8>) {
+        let mut decoder = Decoder::new(png);
+        decoder.set_transformations(Transformations::EXPAND | Transformations::ALPHA);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        let frame = reader.next_frame(&mut buf).unwrap();
+        buf.truncate(frame.buffer_size());
+        if frame.bit_depth == BitDepth::Eight {
+            buf = buf.iter().flat_map(|&b| [b, b]).collect();
+        }
+        // `ALPHA` always adds an alpha channel, so the output is either gray+alpha or RGBA.
+        let info = reader.info().clone();
+        if frame.color_type == ColorType::GrayscaleAlpha {
+            buf = buf
+                .chunks_exact(4)
+                .flat_map(|ga| [ga[0], ga[1], ga[0], ga[1], ga[0], ga[1], ga[2], ga[3]])
+                .collect();
+        }
+        (info, buf)
+    }
+
+    fn decode_raw(png: impl std::io::BufRead) -> (Info<'static>, Vec<u8>) {
+        let mut reader = Decoder::new(png).read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        let frame = reader.next_frame(&mut buf).unwrap();
+        buf.truncate(frame.buffer_size());
+        (reader.info().clone(), buf)
+    }
+
+    #[test]
+    fn optimize_pngsuite_is_lossless() {
+        let mut optimizer = Optimizer::new();
+        optimizer.set_filters(&[Filter::Adaptive]);
+        optimizer.set_compressions(&[DeflateCompression::FdeflateUltraFast]);
+
+        for path in glob::glob("tests/pngsuite/*.png")
+            .unwrap()
+            .map(|r| r.unwrap())
+        {
+            let name = path.file_name().unwrap().to_str().unwrap();
+            if name.starts_with('x') {
+                // x* files are expected to fail to decode
+                continue;
+            }
+            let (info, data) = decode_raw(BufReader::new(File::open(&path).unwrap()));
+            if info.animation_control.is_some() {
+                continue;
+            }
+            let optimized = optimizer.optimize(&info, &data).unwrap();
+
+            let expected = decode_rgba(BufReader::new(File::open(&path).unwrap()));
+            let (optimized_info, actual) = decode_rgba(Cursor::new(&optimized));
+            assert!(expected.1 == actual, "{} is not lossless", name);
+            assert!(!optimized_info.interlaced);
+        }
+    }
+
+    #[test]
+    fn opaque_rgba_becomes_grayscale() {
+        let mut info = Info::with_size(4, 1);
+        info.color_type = ColorType::Rgba;
+        #[rustfmt::skip]
+        let data = [
+            0, 0, 0, 255,
+            85, 85, 85, 255,
+            170, 170, 170, 255,
+            255, 255, 255, 255,
+        ];
+        let optimized = optimize(&info, &data).unwrap();
+        let (optimized_info, pixels) = decode_raw(Cursor::new(optimized));
+        assert_eq!(optimized_info.color_type, ColorType::Grayscale);
+        assert_eq!(optimized_info.bit_depth, BitDepth::Two);
+        assert_eq!(pixels, [0b00_01_10_11]);
+    }
+
+    #[test]
+    fn binary_transparency_becomes_color_key() {
+        let mut info = Info::with_size(3, 1);
+        info.color_type = ColorType::Rgba;
+        info.bit_depth = BitDepth::Sixteen;
+        let data: Vec<u8> = [
+            [0x1000u16, 0x2000, 0x3000, 0xffff],
+            [0x4000, 0x5000, 0x6000, 0],
+            [0x7000, 0x8000, 0x9000, 0xffff],
+        ]
+        .iter()
+        .flatten()
+        .flat_map(|s| s.to_be_bytes())
+        .collect();
+
+        let candidate = Image::from_info(&info, &data).unwrap().reduce_true_color();
+        assert_eq!(candidate.color_type, ColorType::Rgb);
+        assert_eq!(candidate.bit_depth, BitDepth::Sixteen);
+        assert_eq!(
+            candidate.trns.as_deref(),
+            Some(&[0x40, 0x00, 0x50, 0x00, 0x60, 0x00][..])
+        );
+    }
+
+    #[test]
+    fn palette_is_sorted_and_trns_trimmed() {
+        let mut info = Info::with_size(5, 1);
+        info.color_type = ColorType::Rgba;
+        #[rustfmt::skip]
+        let data = [
+            10, 20, 30, 255,
+            1, 2, 3, 0,
+            10, 20, 30, 255,
+            40, 50, 60, 128,
+            70, 80, 90, 255,
+        ];
+        let candidate = Image::from_info(&info, &data)
+            .unwrap()
+            .reduce_indexed()
+            .unwrap();
+        assert_eq!(candidate.bit_depth, BitDepth::Two);
+        assert_eq!(
+            candidate.palette.as_deref(),
+            Some(&[1, 2, 3, 40, 50, 60, 10, 20, 30, 70, 80, 90][..])
+        );
+        assert_eq!(candidate.trns.as_deref(), Some(&[0, 128][..]));
+        assert_eq!(candidate.data, [0b10_00_10_01, 0b11_000000]);
+    }
+
+    #[test]
+    fn metadata_is_converted() {
+        let mut info = Info::with_size(2, 1);
+        info.color_type = ColorType::Rgb;
+        info.bit_depth = BitDepth::Sixteen;
+        info.sbit = Some(Cow::Borrowed(&[12, 12, 12]));
+        info.bkgd = Some(Cow::Borrowed(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff]));
+        info.uncompressed_latin1_text
+            .push(crate::text_metadata::TEXtChunk::new("Title", "optimized"));
+        let data = [0u8; 12];
+
+        let optimized = optimize(&info, &data).unwrap();
+        let (optimized_info, pixels) = decode_raw(Cursor::new(optimized));
+        assert_eq!(optimized_info.bit_depth, BitDepth::One);
+        assert_eq!(pixels, [0]);
+        assert_eq!(optimized_info.uncompressed_latin1_text.len(), 1);
+        assert_eq!(optimized_info.sbit.as_deref(), Some(&[1][..]));
+        match optimized_info.color_type {
+            ColorType::Grayscale => assert_eq!(optimized_info.bkgd.as_deref(), Some(&[0, 1][..])),
+            ColorType::Indexed => {
+                let index = usize::from(optimized_info.bkgd.as_deref().unwrap()[0]);
+                let palette = optimized_info.palette.as_deref().unwrap();
+                assert_eq!(palette[3 * index..][..3], [255, 255, 255]);
+            }
+            color_type => panic!("unexpected {:?}", color_type),
+        }
+    }
+
+    #[test]
+    fn unsafe_to_copy_chunks_are_dropped() {
+        use crate::{chunk::ChunkType, ChunkPosition, UnknownChunk};
+
+        let mut info = Info::with_size(1, 1);
+        info.color_type = ColorType::Rgba;
+        for type_ in [*b"prVt", *b"prVT"] {
+            info.unknown_chunks.push(UnknownChunk {
+                type_: ChunkType(type_),
+                data: Cow::Borrowed(b"data"),
+                position: ChunkPosition::BeforeIdat,
+            });
+        }
+
+        let optimized = optimize(&info, &[0, 0, 0, 255]).unwrap();
+        let mut decoder = Decoder::new(Cursor::new(optimized));
+        decoder.set_keep_unknown_chunks(true);
+        let reader = decoder.read_info().unwrap();
+        let types: Vec<_> = reader
+            .info()
+            .unknown_chunks
+            .iter()
+            .map(|chunk| chunk.type_)
+            .collect();
+        assert_eq!(types, [ChunkType(*b"prVt")]);
+    }
+
+    #[test]
+    fn icc_profile_keeps_gray_or_color() {
+        let mut info = Info::with_size(2, 1);
+        info.color_type = ColorType::Rgb;
+        info.icc_profile = Some(Cow::Borrowed(b"RGB profile"));
+        let optimized = optimize(&info, &[0, 0, 0, 255, 255, 255]).unwrap();
+        let (optimized_info, _) = decode_raw(Cursor::new(optimized));
+        assert!(optimized_info.icc_profile.is_some());
+        assert!(matches!(
+            optimized_info.color_type,
+            ColorType::Rgb | ColorType::Indexed
+        ));
+
+        info.color_type = ColorType::Grayscale;
+        info.icc_profile = Some(Cow::Borrowed(b"GRAY profile"));
+        let optimized = optimize(&info, &[0, 255]).unwrap();
+        let (optimized_info, pixels) = decode_raw(Cursor::new(optimized));
+        assert_eq!(optimized_info.color_type, ColorType::Grayscale);
+        assert_eq!(pixels, [0b01_000000]);
+    }
+
+    #[test]
+    fn background_is_kept() {
+        // A color background keeps gray pixels in a color format.
+        let mut info = Info::with_size(2, 1);
+        info.color_type = ColorType::Rgb;
+        info.bkgd = Some(Cow::Borrowed(&[0, 0x10, 0, 0x20, 0, 0x30]));
+        let optimized = optimize(&info, &[0, 0, 0, 255, 255, 255]).unwrap();
+        let (optimized_info, _) = decode_rgba(Cursor::new(optimized));
+        match optimized_info.color_type {
+            ColorType::Rgb => {
+                assert_eq!(
+                    optimized_info.bkgd.as_deref(),
+                    Some(&[0, 0x10, 0, 0x20, 0, 0x30][..])
+                )
+            }
+            ColorType::Indexed => {
+                let index = usize::from(optimized_info.bkgd.as_deref().unwrap()[0]);
+                let palette = optimized_info.palette.as_deref().unwrap();
+                assert_eq!(palette[3 * index..][..3], [0x10, 0x20, 0x30]);
+            }
+            color_type => panic!("unexpected {:?}", color_type),
+        }
+
+        // A gray background that doesn't fit into fewer bits keeps the bit depth.
+        let mut info = Info::with_size(2, 1);
+        info.color_type = ColorType::Grayscale;
+        info.icc_profile = Some(Cow::Borrowed(b"GRAY profile"));
+        info.bkgd = Some(Cow::Borrowed(&[0, 100]));
+        let optimized = optimize(&info, &[0, 255]).unwrap();
+        let (optimized_info, pixels) = decode_raw(Cursor::new(optimized));
+        assert_eq!(optimized_info.bit_depth, BitDepth::Eight);
+        assert_eq!(optimized_info.bkgd.as_deref(), Some(&[0, 100][..]));
+        assert_eq!(pixels, [0, 255]);
+    }
+
+    #[test]
+    fn wrong_buffer_size_is_rejected() {
+        let info = Info::with_size(2, 2);
+        assert!(matches!(
+            optimize(&info, &[0; 3]),
+            Err(EncodingError::Parameter(_))
+        ));
+    }
+}
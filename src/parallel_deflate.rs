@@ -0,0 +1,273 @@
+//! Multithreaded compression of image data, see [`DeflateCompression::Parallel`].
+//!
+//! The filtered rows are split into bands which are compressed independently into raw DEFLATE
+//! streams. Every band except the last one ends with a sync flush, which aligns it to a byte
+//! boundary without terminating the stream, so the bands can simply be concatenated. The
+//! Adler-32 checksums of the bands are combined into the one of the whole stream.
+//!
+//! [`DeflateCompression::Parallel`]: crate::DeflateCompression::Parallel
+use std::io::{self, Write};
+use std::num::NonZeroUsize;
+use std::{mem, thread};
+
+use flate2::write::DeflateEncoder;
+
+use crate::common::BytesPerPixel;
+use crate::filter::{filter, Filter};
+
+/// Bands smaller than this are not worth a thread of their own.
+const MIN_BAND_SIZE: usize = 256 * 1024;
+
+const ADLER_BASE: u32 = 65521;
+
+/// A compressed band together with what is needed to checksum the whole stream.
+struct Band {
+    compressed: Vec<u8>,
+    adler: u32,
+    len: u64,
+}
+
+/// Filters and compresses `rows` into a zlib stream.
+///
+/// Each row is given as `(first_in_pass, line)` like in `Writer::write_image_data`, where the
+/// first row of an interlace pass is filtered against a row of zeros.
+pub(crate) fn compress_rows(
+    rows: &[(bool, &[u8])],
+    filter_method: Filter,
+    bpp: BytesPerPixel,
+    level: u8,
+) -> io::Result<Vec<u8>> {
+    let total: usize = rows.iter().map(|(_, line)| line.len() + 1).sum();
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let band_count = (total / MIN_BAND_SIZE).clamp(1, threads);
+    compress_bands(rows, filter_method, bpp, level, band_count)
+}
+
+fn compress_bands(
+    rows: &[(bool, &[u8])],
+    filter_method: Filter,
+    bpp: BytesPerPixel,
+    level: u8,
+    band_count: usize,
+) -> io::Result<Vec<u8>> {
+    let total: usize = rows.iter().map(|(_, line)| line.len() + 1).sum();
+    let ranges = split_bands(rows, total.div_ceil(band_count));
+
+    let compress = |&(start, end): &(usize, usize)| {
+        compress_band(
+            rows,
+            start,
+            end,
+            end == rows.len(),
+            filter_method,
+            bpp,
+            level,
+        )
+    };
+    let bands = if ranges.len() == 1 {
+        vec![compress(&ranges[0])?]
+    } else {
+        thread::scope(|scope| {
+            let handles: Vec<_> = ranges
+                .iter()
+                .map(|range| scope.spawn(move || compress(range)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect::<io::Result<Vec<_>>>()
+        })?
+    };
+
+    let compressed_len: usize = bands.iter().map(|band| band.compressed.len()).sum();
+    let mut zlib = Vec::with_capacity(compressed_len + 6);
+    zlib.extend_from_slice(&zlib_header(level));
+    let mut adler = 1;
+    for band in bands {
+        zlib.extend_from_slice(&band.compressed);
+        adler = adler32_combine(adler, band.adler, band.len);
+    }
+    zlib.extend_from_slice(&adler.to_be_bytes());
+    Ok(zlib)
+}
+
+/// Splits the rows into ranges holding roughly `target` bytes of filtered data each.
+fn split_bands(rows: &[(bool, &[u8])], target: usize) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut size = 0;
+    for (idx, (_, line)) in rows.iter().enumerate() {
+        size += line.len() + 1;
+        if size >= target && idx + 1 < rows.len() {
+            ranges.push((start, idx + 1));
+            start = idx + 1;
+            size = 0;
+        }
+    }
+    ranges.push((start, rows.len()));
+    ranges
+}
+
+fn compress_band(
+    rows: &[(bool, &[u8])],
+    start: usize,
+    end: usize,
+    last: bool,
+    filter_method: Filter,
+    bpp: BytesPerPixel,
+    level: u8,
+) -> io::Result<Band> {
+    let max_len = rows[start..end]
+        .iter()
+        .map(|(_, line)| line.len())
+        .max()
+        .unwrap_or(0);
+    let zeros = vec![0; max_len];
+    let mut current = vec![0; max_len];
+    // The first row of a band is still filtered against its predecessor in the image.
+    let mut prev = match start.checked_sub(1) {
+        Some(idx) => rows[idx].1,
+        None => &zeros[..],
+    };
+
+    let mut deflate = DeflateEncoder::new(Vec::new(), flate2::Compression::new(u32::from(level)));
+    let mut adler = simd_adler32::Adler32::new();
+    let mut len = 0;
+    for &(first_in_pass, line) in &rows[start..end] {
+        if first_in_pass {
+            prev = &zeros[..line.len()];
+        }
+        let current = &mut current[..line.len()];
+        let filter_type = filter(filter_method, bpp, prev, line, current);
+
+        deflate.write_all(&[filter_type as u8])?;
+        deflate.write_all(current)?;
+        adler.write(&[filter_type as u8]);
+        adler.write(current);
+        len += line.len() as u64 + 1;
+        prev = line;
+    }
+
+    let compressed = if last {
+        deflate.finish()?
+    } else {
+        deflate.flush()?;
+        mem::take(deflate.get_mut())
+    };
+    Ok(Band {
+        compressed,
+        adler: adler.finish(),
+        len,
+    })
+}
+
+/// The zlib header for a DEFLATE stream with a 32 KiB window at the given level.
+fn zlib_header(level: u8) -> [u8; 2] {
+    const CMF: u8 = 0x78;
+    let flevel = match level {
+        0..=1 => 0,
+        2..=5 => 1,
+        6 => 2,
+        _ => 3,
+    };
+    let flg = flevel << 6;
+    let check = (31 - (u16::from(CMF) << 8 | u16::from(flg)) % 31) % 31;
+    [CMF, flg | check as u8]
+}
+
+/// Computes the Adler-32 of two concatenated inputs from their checksums, where `len2` is the
+/// length of the second input. This is `adler32_combine` from zlib.
+fn adler32_combine(adler1: u32, adler2: u32, len2: u64) -> u32 {
+    let rem = (len2 % u64::from(ADLER_BASE)) as u32;
+    let mut sum1 = adler1 & 0xffff;
+    let mut sum2 = (rem * sum1) % ADLER_BASE;
+    sum1 += (adler2 & 0xffff) + ADLER_BASE - 1;
+    sum2 += (adler1 >> 16) + (adler2 >> 16) + ADLER_BASE - rem;
+    if sum1 >= ADLER_BASE {
+        sum1 -= ADLER_BASE;
+    }
+    if sum1 >= ADLER_BASE {
+        sum1 -= ADLER_BASE;
+    }
+    if sum2 >= ADLER_BASE << 1 {
+        sum2 -= ADLER_BASE << 1;
+    }
+    if sum2 >= ADLER_BASE {
+        sum2 -= ADLER_BASE;
+    }
+    sum1 | (sum2 << 16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adler32(data: &[u8]) -> u32 {
+        let mut adler = simd_adler32::Adler32::new();
+        adler.write(data);
+        adler.finish()
+    }
+
+    #[test]
+    fn adler32_combine_matches_whole_input() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i * 7 + i / 13) as u8).collect();
+        for split in [0, 1, 5552, 65521, 100_000, data.len()] {
+            let (a, b) = data.split_at(split);
+            assert_eq!(
+                adler32_combine(adler32(a), adler32(b), b.len() as u64),
+                adler32(&data),
+                "split at {split}"
+            );
+        }
+    }
+
+    #[test]
+    fn zlib_header_is_valid() {
+        for level in 0..=9 {
+            let [cmf, flg] = zlib_header(level);
+            assert_eq!((u16::from(cmf) << 8 | u16::from(flg)) % 31, 0);
+            assert_eq!(flg & 0x20, 0);
+        }
+    }
+
+    #[test]
+    fn bands_decompress_to_the_same_data() {
+        use std::io::Read;
+
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i ^ (i >> 9)) as u8).collect();
+        let line_len = 1000;
+        let rows: Vec<_> = data
+            .chunks(line_len)
+            .enumerate()
+            // Start a new pass midway, like an interlaced image would.
+            .map(|(idx, line)| (idx == 0 || idx == 150, line))
+            .collect();
+        let bpp = BytesPerPixel::Four;
+
+        let decompress = |zlib: &[u8]| {
+            let mut out = Vec::new();
+            flate2::read::ZlibDecoder::new(zlib)
+                .read_to_end(&mut out)
+                .unwrap();
+            out
+        };
+        let expected = decompress(&compress_bands(&rows, Filter::Paeth, bpp, 6, 1).unwrap());
+        assert_eq!(expected.len(), 300 * (line_len + 1));
+        for band_count in [2, 3, 7, 300] {
+            let zlib = compress_bands(&rows, Filter::Paeth, bpp, 6, band_count).unwrap();
+            assert_eq!(decompress(&zlib), expected, "{band_count} bands");
+        }
+    }
+
+    #[test]
+    fn split_bands_covers_all_rows() {
+        let line = [0u8; 99];
+        let rows: Vec<_> = (0..10).map(|i| (i == 0, &line[..])).collect();
+        assert_eq!(split_bands(&rows, 300), [(0, 3), (3, 6), (6, 9), (9, 10)]);
+        assert_eq!(split_bands(&rows, 10_000), [(0, 10)]);
+    }
+}
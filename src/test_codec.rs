@@ -0,0 +1,47 @@
+//! Encoding and decoding whole images with this crate, for tests of the codec.
+//!
+//! Unlike `src/test_utils.rs`, which writes raw chunks and is shared with the benchmarks, these
+//! helpers go through the public [`Encoder`] and [`Decoder`].
+
+use std::io::{BufRead, Cursor};
+
+use crate::{BitDepth, ColorType, Decoder, Encoder, OutputInfo, Transformations};
+
+/// Encodes a `width` x `height` image with this crate, for tests that decode it again.
+///
+/// `configure` sets up anything beyond the size and format, such as a palette or color chunks.
+pub(crate) fn encode_png(
+    (width, height): (u32, u32),
+    (color_type, bit_depth): (ColorType, BitDepth),
+    data: &[u8],
+    configure: impl FnOnce(&mut Encoder<&mut Vec<u8>>),
+) -> Vec<u8> {
+    let mut png = Vec::new();
+    let mut encoder = Encoder::new(&mut png, width, height);
+    encoder.set_color(color_type);
+    encoder.set_depth(bit_depth);
+    configure(&mut encoder);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(data).unwrap();
+    writer.finish().unwrap();
+    png
+}
+
+/// Decodes the first frame of `png` with `transform`, see [`decode_frame`].
+pub(crate) fn decode_png(png: &[u8], transform: Transformations) -> (OutputInfo, Vec<u8>) {
+    let mut decoder = Decoder::new(Cursor::new(png));
+    decoder.set_transformations(transform);
+    decode_frame(decoder)
+}
+
+/// Decodes the first frame with a configured decoder, returning exactly the bytes of the frame.
+///
+/// The rest of the file is read as well, so it has to be valid.
+pub(crate) fn decode_frame<R: BufRead>(decoder: Decoder<R>) -> (OutputInfo, Vec<u8>) {
+    let mut reader = decoder.read_info().unwrap();
+    let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+    let info = reader.next_frame(&mut buf).unwrap();
+    buf.truncate(info.buffer_size());
+    reader.finish().unwrap();
+    (info, buf)
+}
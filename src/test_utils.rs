@@ -5,6 +5,9 @@
 //! * This module (unlike `src/encoder.rs`) performs no validation of the data being written - this
 //!   allows building testcases that use arbitrary, potentially invalid PNGs as input.
 //! * This module can be reused from `benches/decoder.rs` (a separate crate).
+//!
+//! Tests that round trip images through this crate's own encoder and decoder use
+//! `src/test_codec.rs` instead.
 
 use byteorder::WriteBytesExt;
 use std::io::Write;